@@ -0,0 +1,85 @@
+// Standard library imports
+use std::sync::Arc;
+
+// Third-party imports
+use log::error;
+use serde::Serialize;
+use tauri::Manager;
+use teralib::patch::preflight::PreflightReport;
+use teralib::services::progress::{
+    DownloadReport, FileCheckProgress, FileCheckSummary, HashFileProgress, ProgressPayload, ProgressReporter, UpdateInfo,
+};
+
+/// Forwards the progress of the services to the frontend, as events of the
+/// same name. Game events go to every window.
+pub struct WindowReporter {
+    window: tauri::Window,
+}
+
+impl WindowReporter {
+    pub fn shared(window: tauri::Window) -> Arc<dyn ProgressReporter> {
+        Arc::new(WindowReporter { window })
+    }
+
+    fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Err(e) = self.window.emit(event, payload) {
+            error!("Failed to emit {} event: {:?}", event, e);
+        }
+    }
+
+    fn emit_all<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Err(e) = self.window.app_handle().emit_all(event, payload) {
+            error!("Failed to emit {} event: {:?}", event, e);
+        }
+    }
+}
+
+impl ProgressReporter for WindowReporter {
+    fn update_info(&self, info: &UpdateInfo) {
+        self.emit("update_info", info);
+    }
+
+    fn file_check_progress(&self, progress: &FileCheckProgress) {
+        self.emit("file_check_progress", progress);
+    }
+
+    fn file_check_completed(&self, summary: &FileCheckSummary) {
+        self.emit("file_check_completed", summary);
+    }
+
+    fn update_preflight_failed(&self, report: &PreflightReport) {
+        self.emit("update_preflight_failed", report);
+    }
+
+    fn download_progress(&self, progress: &ProgressPayload) {
+        self.emit("download_progress", progress);
+    }
+
+    fn download_report(&self, report: &DownloadReport) {
+        self.emit("download_report", report);
+    }
+
+    fn download_cancelled(&self) {
+        self.emit("download_cancelled", ());
+    }
+
+    fn download_complete(&self) {
+        self.emit("download_complete", ());
+    }
+
+    fn hash_file_progress(&self, progress: &HashFileProgress) {
+        self.emit("hash_file_progress", progress);
+    }
+
+    fn game_status_changed(&self, running: bool) {
+        self.emit_all("game_status_changed", running);
+    }
+
+    fn game_status(&self, status: &str) {
+        self.emit_all("game_status", status);
+    }
+
+    fn game_ended(&self) {
+        self.emit_all("game_ended", ());
+    }
+}
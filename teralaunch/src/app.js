@@ -32,6 +32,7 @@ const App = {
     speedHistoryMaxLength: 10,
     isUpdateAvailable: false,
     isDownloadComplete: false,
+    isUpdatePaused: false,
     lastProgressUpdate: null,
     lastDownloadedBytes: 0,
     currentUpdateMode: null,
@@ -117,6 +118,7 @@ const App = {
       await this.Router.navigate();
       this.sendStoredAuthInfoToBackend();
       this.setupMutationObserver();
+      this.checkLauncherUpdate();
 
       this.checkAuthentication();
       document.addEventListener("DOMContentLoaded", () => {
@@ -273,6 +275,8 @@ const App = {
                 <h2>${this.t("WELCOME_TO_LAUNCHER")}</h2>
                 <p>${this.t("FIRST_LAUNCH_MESSAGE")}</p>
                 <button id="set-game-path-btn">${this.t("SET_GAME_PATH")}</button>
+                <button id="install-game-btn">${this.t("INSTALL_GAME")}</button>
+                <button id="adopt-game-btn">${this.t("ADOPT_EXISTING_INSTALL")}</button>
             </div>
         `;
     document.body.appendChild(modal);
@@ -283,6 +287,18 @@ const App = {
       this.openGamePathSettings();
     });
 
+    const installGameBtn = document.getElementById("install-game-btn");
+    installGameBtn.addEventListener("click", () => {
+      this.closeFirstLaunchModal();
+      this.installGame();
+    });
+
+    const adoptGameBtn = document.getElementById("adopt-game-btn");
+    adoptGameBtn.addEventListener("click", () => {
+      this.closeFirstLaunchModal();
+      this.adoptExistingInstall();
+    });
+
     anime({
       targets: modal,
       opacity: [0, 1],
@@ -307,6 +323,88 @@ const App = {
     });
   },
 
+  /**
+   * Confirms the start of a freshly updated launcher, then offers the next
+   * launcher build if the server publishes one.
+   *
+   * Until the start is confirmed, the previous launcher waits in the background
+   * and restores itself if this one fails to come up.
+   *
+   * @returns {Promise<void>}
+   */
+  async checkLauncherUpdate() {
+    try {
+      const updatedTo = await invoke("confirm_launcher_start");
+      if (updatedTo) {
+        this.showCustomNotification(
+          this.t("LAUNCHER_UPDATED", updatedTo),
+          "success",
+        );
+      }
+    } catch (error) {
+      console.error("Error confirming the launcher start:", error);
+    }
+
+    if (!UPDATE_CHECK_ENABLED) {
+      return;
+    }
+
+    try {
+      const release = await invoke("check_launcher_update");
+      if (release) {
+        this.showLauncherUpdateModal(release);
+      }
+    } catch (error) {
+      console.error("Error checking for a launcher update:", error);
+    }
+  },
+
+  /**
+   * Offers a new launcher build. Accepting downloads it and restarts into it.
+   * @param {Object} release the release returned by `check_launcher_update`
+   */
+  showLauncherUpdateModal(release) {
+    const modal = document.createElement("div");
+    modal.id = "launcher-update-modal";
+    modal.innerHTML = `
+            <div class="first-launch-modal-content">
+                <h2>${this.t("LAUNCHER_UPDATE_AVAILABLE", release.version)}</h2>
+                <p id="launcher-update-notes"></p>
+                <button id="launcher-update-btn">${this.t("LAUNCHER_UPDATE_NOW")}</button>
+                <button id="launcher-update-later-btn">${this.t("LAUNCHER_UPDATE_LATER")}</button>
+            </div>
+        `;
+    document.body.appendChild(modal);
+    document.getElementById("launcher-update-notes").textContent =
+      release.release_notes || this.t("LAUNCHER_UPDATE_MESSAGE");
+
+    const updateBtn = document.getElementById("launcher-update-btn");
+    updateBtn.addEventListener("click", async () => {
+      updateBtn.disabled = true;
+      updateBtn.textContent = this.t("LAUNCHER_UPDATE_DOWNLOADING");
+      try {
+        await invoke("download_launcher_update");
+        await invoke("restart_to_update_launcher");
+      } catch (error) {
+        console.error("Error updating the launcher:", error);
+        modal.remove();
+        this.showErrorMessage(this.t("LAUNCHER_UPDATE_ERROR"));
+      }
+    });
+
+    document
+      .getElementById("launcher-update-later-btn")
+      .addEventListener("click", () => modal.remove());
+
+    anime({
+      targets: modal,
+      opacity: [0, 1],
+      scale: [0.9, 1],
+      duration: 300,
+      easing: "easeOutQuad",
+    });
+  },
+
   // Function to open game path settings
   openGamePathSettings() {
     const settingsBtn = document.getElementById("openModal");
@@ -315,6 +413,131 @@ const App = {
     }
   },
 
+  /**
+   * Installs the game into a folder picked by the user.
+   *
+   * The backend writes the folder to tera_config.ini, checks the disk, unpacks the
+   * base archive when the server publishes one and downloads the remaining files.
+   * Progress comes through the usual `file_check_progress` and `download_progress`
+   * events. If the install is interrupted, the next start continues it as an update.
+   *
+   * @returns {Promise<void>}
+   */
+  async installGame() {
+    let path;
+    try {
+      path = await invoke("select_game_folder");
+    } catch (error) {
+      console.log("Install folder selection cancelled:", error);
+      this.showFirstLaunchModal();
+      return;
+    }
+
+    // The config points at the new folder from now on
+    localStorage.setItem("isFirstLaunch", "false");
+    this.setState({ isFirstLaunch: false, currentUpdateMode: "file_check" });
+    this.updateLaunchGameButton(true);
+    this.toggleLanguageSelector(false);
+    this.togglePauseButton(true);
+
+    let preflightReport = null;
+    const unlisten = await listen("update_preflight_failed", (event) => {
+      preflightReport = event.payload;
+    });
+
+    try {
+      const report = await invoke("install_game", { path });
+      console.log("Installation complete:", report);
+      this.handleCompletion();
+      this.showCustomNotification(this.t("INSTALL_COMPLETED"), "success");
+      await this.loadGamePath();
+    } catch (error) {
+      console.error("Error during installation:", error);
+      this.resetState();
+      if (preflightReport) {
+        this.showPreflightIssues(preflightReport);
+      } else if (error?.code !== "cancelled") {
+        this.showErrorMessage(this.t("INSTALL_ERROR"));
+      }
+      this.updateLaunchGameButton(false);
+      this.toggleLanguageSelector(true);
+    } finally {
+      unlisten();
+      this.togglePauseButton(false);
+    }
+  },
+
+  /**
+   * Takes over a copy of the game the user already has.
+   *
+   * The backend finds the client in or around the picked folder, makes it the game
+   * path and verifies it against the full hash file. Only the missing or changed
+   * files are then downloaded.
+   *
+   * @returns {Promise<void>}
+   */
+  async adoptExistingInstall() {
+    let path;
+    try {
+      path = await invoke("select_game_folder");
+    } catch (error) {
+      console.log("Folder selection cancelled:", error);
+      this.showFirstLaunchModal();
+      return;
+    }
+
+    this.setState({ currentUpdateMode: "file_check" });
+    this.updateLaunchGameButton(true);
+    this.toggleLanguageSelector(false);
+
+    let report;
+    try {
+      report = await invoke("adopt_game_folder", { path });
+    } catch (error) {
+      console.error("Error adopting the game folder:", error);
+      this.resetState();
+      this.showErrorMessage(this.t("ADOPT_NOT_FOUND"));
+      this.updateLaunchGameButton(false);
+      this.toggleLanguageSelector(true);
+      this.showFirstLaunchModal();
+      return;
+    }
+
+    console.log("Adopted game folder:", report);
+    localStorage.setItem("isFirstLaunch", "false");
+    this.setState({ isFirstLaunch: false });
+    await this.loadGamePath();
+    this.showCustomNotification(
+      this.t(
+        "ADOPT_SUMMARY",
+        String(report.matching_files),
+        String(report.total_files),
+        String(report.missing_files + report.changed_files),
+        this.formatSize(report.missing_size + report.changed_size),
+      ),
+      "success",
+    );
+
+    const filesToUpdate = report.files_to_update;
+    if (filesToUpdate.length === 0) {
+      this.handleCompletion();
+      return;
+    }
+
+    this.setState({
+      isUpdateAvailable: true,
+      isFileCheckComplete: true,
+      currentUpdateMode: "download",
+      totalFiles: filesToUpdate.length,
+      totalSize: filesToUpdate.reduce(
+        (total, file) =>
+          total + (file.compressed ? file.compressed.size : file.size),
+        0,
+      ),
+    });
+    await this.runPatchSystem(filesToUpdate);
+  },
+
   // Function to complete the first launch process
   completeFirstLaunch() {
     localStorage.setItem("isFirstLaunch", "false");
@@ -947,8 +1170,10 @@ const App = {
           isFileCheckComplete: true,
           currentUpdateMode: "complete",
           totalFiles: filesToUpdate.length,
+          // Download size, compressed variants are transferred instead of the raw files
           totalSize: filesToUpdate.reduce(
-            (total, file) => total + file.size,
+            (total, file) =>
+              total + (file.compressed ? file.compressed.size : file.size),
             0,
           ),
         });
@@ -999,6 +1224,16 @@ const App = {
         return;
       }
 
+      // Nothing is downloaded when the disk or the install location cannot take the update
+      const preflight = await invoke("check_update_preflight", {
+        filesToUpdate: filesToUpdate,
+      });
+      if (preflight.issues.length > 0) {
+        this.showPreflightIssues(preflight);
+        return;
+      }
+
+      this.togglePauseButton(true);
       const downloadedSizes = await invoke("download_all_files", {
         filesToUpdate: filesToUpdate,
       });
@@ -1042,14 +1277,48 @@ const App = {
       this.handleCompletion();
     } catch (error) {
       console.error("Error during update:", error);
-      this.showErrorMessage(this.t("UPDATE_ERROR_MESSAGE"));
+      if (error?.code !== "cancelled") {
+        this.showErrorMessage(this.t("UPDATE_ERROR_MESSAGE"));
+      }
     } finally {
+      this.togglePauseButton(false);
       // Re-enable the game launch button and language selector at the end of the process
       this.updateLaunchGameButton(false);
       this.toggleLanguageSelector(true);
     }
   },
 
+  /**
+   * Shows or hides the pause button of the download progress bar.
+   *
+   * While visible, clicking the button pauses the running update, and clicking it
+   * again resumes it. The button is dimmed while the update is paused.
+   *
+   * @param {boolean} visible - Whether the pause button should be shown.
+   */
+  togglePauseButton(visible) {
+    const pauseBtn = document.querySelector(".btn-pause");
+    if (!pauseBtn) {
+      return;
+    }
+
+    this.setState({ isUpdatePaused: false });
+    pauseBtn.style.display = visible ? "flex" : "none";
+    pauseBtn.style.opacity = 1;
+    pauseBtn.onclick = visible
+      ? async () => {
+          const isUpdatePaused = !this.state.isUpdatePaused;
+          try {
+            await invoke(isUpdatePaused ? "pause_update" : "resume_update");
+            this.setState({ isUpdatePaused });
+            pauseBtn.style.opacity = isUpdatePaused ? 0.5 : 1;
+          } catch (error) {
+            console.error("Error toggling update pause:", error);
+          }
+        }
+      : null;
+  },
+
   /**
    * Logs in to the game server using the given username and password.
    *
@@ -1374,7 +1643,7 @@ const App = {
       console.log("Game launch result:", result);
     } catch (error) {
       console.error("Error initiating game launch:", error);
-      const game_launch_error = this.t("GAME_LAUNCH_ERROR") + error.toString();
+      const game_launch_error = this.t("GAME_LAUNCH_ERROR") + this.errorText(error);
 
       await message(game_launch_error, {
         title: this.t("ERROR"),
@@ -1383,7 +1652,7 @@ const App = {
       if (this.statusEl)
         this.statusEl.textContent = this.t(
           "GAME_LAUNCH_ERROR",
-          error.toString(),
+          this.errorText(error),
         );
       await invoke("reset_launch_state");
       this.updateUIForGameStatus(false);
@@ -1674,6 +1943,31 @@ const App = {
     }
   },
 
+  /**
+   * Shows why an update cannot start, from the report of `check_update_preflight`.
+   * @param {Object} report the preflight report, each issue has a `code`
+   * @memberof App
+   */
+  showPreflightIssues(report) {
+    const messages = report.issues.map((issue) => {
+      switch (issue.code) {
+        case "insufficient_space":
+          return this.t(
+            "PREFLIGHT_INSUFFICIENT_SPACE",
+            this.formatSize(issue.required),
+            this.formatSize(issue.available),
+          );
+        case "protected_location":
+          return this.t("PREFLIGHT_PROTECTED_LOCATION", issue.protected_folder);
+        case "not_writable":
+          return this.t("PREFLIGHT_NOT_WRITABLE", issue.path);
+        default:
+          return this.t("UPDATE_ERROR_MESSAGE");
+      }
+    });
+    this.showErrorMessage(messages.join(" "));
+  },
+
   // Updated methods for loading modal
   showLoadingModal(message) {
     this.toggleModal("loading-modal", true, message);
@@ -1918,6 +2212,21 @@ const App = {
     return str.replace(/\{(\d+)\}/g, (_, index) => args[index] || "");
   },
 
+  /**
+   * Returns the translated message of an error returned by a Tauri command.
+   * Commands reject with `{ code, message_key, message }`; anything else is
+   * shown as is.
+   *
+   * @param {*} error The error returned by `invoke`.
+   * @returns {string} The translated message.
+   */
+  errorText(error) {
+    if (error && error.message_key) {
+      return this.t(error.message_key);
+    }
+    return error ? error.toString() : "";
+  },
+
   /**
    * Updates the language selector with the current language from the config file.
    * If any error occurs, it logs the error to the console and sets the
@@ -2526,15 +2835,10 @@ const App = {
       console.error("Error loading game path:", error);
       // Display the error in a Windows system message
       let errorMessage;
-      if (
-        error &&
-        error.message &&
-        typeof error.message === "string" &&
-        error.message.toLowerCase().includes("tera_config.ini")
-      ) {
+      if (error && error.code === "config_not_found") {
         errorMessage = this.t("CONFIG_INI_MISSING");
       } else {
-        errorMessage = `${this.t("GAME_PATH_LOAD_ERROR")} ${error && error ? error : ""}`;
+        errorMessage = `${this.t("GAME_PATH_LOAD_ERROR")} ${this.errorText(error)}`;
       }
 
       const userResponse = await message(errorMessage, {
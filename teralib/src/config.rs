@@ -15,3 +15,24 @@ pub fn get_config_value(key: &str) -> String {
         .unwrap_or_else(|| panic!("{} must be set in config.json", key))
         .to_string()
 }
+
+/// Returns a list valued config entry.
+///
+/// The value can be a JSON array of strings or a comma separated string.
+/// Missing keys yield an empty list, since list entries are optional.
+pub fn get_config_list(key: &str) -> Vec<String> {
+    match &CONFIG_JSON[key] {
+        Value::Array(values) => values
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(str::to_string)
+            .collect(),
+        Value::String(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
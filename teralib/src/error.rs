@@ -0,0 +1,189 @@
+// Standard library imports
+use std::io;
+use std::path::PathBuf;
+
+// Third-party imports
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use thiserror::Error;
+
+use crate::patch::download::CANCELLED;
+
+/// Error returned by the Tauri commands.
+///
+/// Serialized as `{ code, message_key, message }`. The frontend branches on
+/// `code`, which never changes, and shows the `message_key` entry of
+/// translations.json. `message` holds the English details for the logs.
+#[derive(Debug, Error)]
+pub enum LauncherError {
+    #[error("tera_config.ini is missing")]
+    ConfigNotFound,
+    #[error("Invalid config: {0}")]
+    Config(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Login failed: {0}")]
+    Auth(String),
+    #[error("{0}")]
+    Patch(String),
+    /// The disk checks before an update failed, see `update_preflight_failed`.
+    #[error("{0}")]
+    Preflight(String),
+    #[error("Update cancelled")]
+    Cancelled,
+    #[error("Game is already running")]
+    GameRunning,
+    #[error("Game is already launching")]
+    GameLaunching,
+    #[error("Game client not found at {0:?}")]
+    GameNotFound(PathBuf),
+    #[error("Failed to launch the game: {0}")]
+    Launch(String),
+    #[error("Folder selection cancelled")]
+    SelectionCancelled,
+    #[error("Launcher update failed: {0}")]
+    LauncherUpdate(String),
+    #[error("{0}")]
+    Io(String),
+}
+
+impl LauncherError {
+    /// Maps an error of the patch code, where cancelling is reported as
+    /// `download::CANCELLED`.
+    pub fn patch(error: String) -> Self {
+        if error == CANCELLED {
+            LauncherError::Cancelled
+        } else {
+            LauncherError::Patch(error)
+        }
+    }
+
+    /// Stable identifier of the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LauncherError::ConfigNotFound => "config_not_found",
+            LauncherError::Config(_) => "config",
+            LauncherError::Network(_) => "network",
+            LauncherError::Auth(_) => "auth",
+            LauncherError::Patch(_) => "patch",
+            LauncherError::Preflight(_) => "preflight",
+            LauncherError::Cancelled => "cancelled",
+            LauncherError::GameRunning => "game_running",
+            LauncherError::GameLaunching => "game_launching",
+            LauncherError::GameNotFound(_) => "game_not_found",
+            LauncherError::Launch(_) => "launch",
+            LauncherError::SelectionCancelled => "selection_cancelled",
+            LauncherError::LauncherUpdate(_) => "launcher_update",
+            LauncherError::Io(_) => "io",
+        }
+    }
+
+    /// Key of the message in translations.json.
+    pub fn message_key(&self) -> &'static str {
+        match self {
+            LauncherError::ConfigNotFound => "ERROR_CONFIG_NOT_FOUND",
+            LauncherError::Config(_) => "ERROR_CONFIG",
+            LauncherError::Network(_) => "ERROR_NETWORK",
+            LauncherError::Auth(_) => "ERROR_AUTH",
+            LauncherError::Patch(_) => "ERROR_PATCH",
+            LauncherError::Preflight(_) => "ERROR_PREFLIGHT",
+            LauncherError::Cancelled => "ERROR_CANCELLED",
+            LauncherError::GameRunning => "ERROR_GAME_RUNNING",
+            LauncherError::GameLaunching => "ERROR_GAME_LAUNCHING",
+            LauncherError::GameNotFound(_) => "ERROR_GAME_NOT_FOUND",
+            LauncherError::Launch(_) => "ERROR_LAUNCH",
+            LauncherError::SelectionCancelled => "ERROR_SELECTION_CANCELLED",
+            LauncherError::LauncherUpdate(_) => "ERROR_LAUNCHER_UPDATE",
+            LauncherError::Io(_) => "ERROR_IO",
+        }
+    }
+}
+
+impl Serialize for LauncherError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("LauncherError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message_key", self.message_key())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<io::Error> for LauncherError {
+    fn from(error: io::Error) -> Self {
+        LauncherError::Io(error.to_string())
+    }
+}
+
+impl From<reqwest::Error> for LauncherError {
+    fn from(error: reqwest::Error) -> Self {
+        LauncherError::Network(error.to_string())
+    }
+}
+
+impl From<ini::Error> for LauncherError {
+    fn from(error: ini::Error) -> Self {
+        LauncherError::Config(error.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for LauncherError {
+    fn from(error: tokio::task::JoinError) -> Self {
+        LauncherError::Patch(error.to_string())
+    }
+}
+
+/// Lets helpers still returning `Result<_, String>` use `?` on typed errors.
+impl From<LauncherError> for String {
+    fn from(error: LauncherError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn every_variant_serializes_with_a_stable_code_and_message_key() {
+        let cases = [
+            (LauncherError::ConfigNotFound, "config_not_found", "ERROR_CONFIG_NOT_FOUND", "tera_config.ini is missing"),
+            (LauncherError::Config("bad".into()), "config", "ERROR_CONFIG", "Invalid config: bad"),
+            (LauncherError::Network("down".into()), "network", "ERROR_NETWORK", "Network error: down"),
+            (LauncherError::Auth("denied".into()), "auth", "ERROR_AUTH", "Login failed: denied"),
+            (LauncherError::Patch("broken".into()), "patch", "ERROR_PATCH", "broken"),
+            (LauncherError::Preflight("full".into()), "preflight", "ERROR_PREFLIGHT", "full"),
+            (LauncherError::Cancelled, "cancelled", "ERROR_CANCELLED", "Update cancelled"),
+            (LauncherError::GameRunning, "game_running", "ERROR_GAME_RUNNING", "Game is already running"),
+            (LauncherError::GameLaunching, "game_launching", "ERROR_GAME_LAUNCHING", "Game is already launching"),
+            (
+                LauncherError::GameNotFound(PathBuf::from("TERA.exe")),
+                "game_not_found",
+                "ERROR_GAME_NOT_FOUND",
+                "Game client not found at \"TERA.exe\"",
+            ),
+            (LauncherError::Launch("crashed".into()), "launch", "ERROR_LAUNCH", "Failed to launch the game: crashed"),
+            (LauncherError::SelectionCancelled, "selection_cancelled", "ERROR_SELECTION_CANCELLED", "Folder selection cancelled"),
+            (
+                LauncherError::LauncherUpdate("bad build".into()),
+                "launcher_update",
+                "ERROR_LAUNCHER_UPDATE",
+                "Launcher update failed: bad build",
+            ),
+            (LauncherError::Io("locked".into()), "io", "ERROR_IO", "locked"),
+        ];
+
+        for (error, code, message_key, message) in cases {
+            assert_eq!(
+                serde_json::to_value(&error).unwrap(),
+                json!({ "code": code, "message_key": message_key, "message": message })
+            );
+        }
+    }
+
+    #[test]
+    fn cancelled_patch_errors_are_recognized() {
+        assert!(matches!(LauncherError::patch(CANCELLED.to_string()), LauncherError::Cancelled));
+        assert!(matches!(LauncherError::patch("Hash mismatch".to_string()), LauncherError::Patch(_)));
+    }
+}
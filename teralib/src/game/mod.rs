@@ -2,13 +2,12 @@
 use crate::{
     config,
     global_credentials::{set_credentials, GLOBAL_CREDENTIALS},
+    server_list::{self, ServerListDocument},
 };
 use lazy_static::lazy_static;
 use log::{error, info, Level, Metadata, Record};
 use once_cell::sync::Lazy;
 use prost::Message;
-use reqwest;
-use serde_json::Value;
 use std::{
     ffi::OsStr,
     os::windows::ffi::OsStrExt,
@@ -19,7 +18,6 @@ use std::{
         atomic::{AtomicBool, Ordering},
         mpsc, {Arc, Mutex},
     },
-    time::Duration,
 };
 use tokio::{
     runtime::Runtime,
@@ -201,10 +199,9 @@ pub async fn run_game(
     set_credentials(account_name, characters_count, ticket, game_lang, game_path);
 
     info!(
-        "Set credentials - Account: {}, Characters_count: {}, Ticket: {}, Lang: {}, Game Path: {}",
+        "Set credentials - Account: {}, Characters_count: {}, Lang: {}, Game Path: {}",
         GLOBAL_CREDENTIALS.get_account_name(),
         GLOBAL_CREDENTIALS.get_characters_count(),
-        GLOBAL_CREDENTIALS.get_ticket(),
         GLOBAL_CREDENTIALS.get_game_lang(),
         GLOBAL_CREDENTIALS.get_game_path()
     );
@@ -620,7 +617,7 @@ unsafe fn handle_account_name_request(recipient: WPARAM, sender: HWND) {
 /// * `sender` - The sender's window handle as a HWND.
 unsafe fn handle_session_ticket_request(recipient: WPARAM, sender: HWND) {
     let session_ticket = GLOBAL_CREDENTIALS.get_ticket();
-    info!("Session Ticket Request - Sending: {}", session_ticket);
+    info!("Session Ticket Request - Sending {} byte(s)", session_ticket.len());
     send_response_message(recipient, sender, 4, session_ticket.as_bytes());
 }
 
@@ -754,47 +751,43 @@ fn on_world_entered(world_name: &str) {
 
 /// Asynchronously retrieves the server list.
 ///
-/// This function sends a GET request to a local server to retrieve the server list,
-/// then parses the JSON response into a ServerList struct.
+/// This function loads the server list document from `SERVER_LIST_URL`, which can be
+/// an HTTP endpoint or a `file://` path, in either the JSON or the classic XML format,
+/// then converts it into a ServerList struct.
 ///
 /// # Returns
 ///
 /// A Result containing a Vec<u8> of the encoded server list on success, or an error on failure.
 async fn get_server_list() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let url = config::get_config_value("SERVER_LIST_URL");
-    let client = reqwest::Client::new();
-    let response = client
-        .get(url)
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(format!("Unsuccessful HTTP response: {}", response.status()).into());
-    }
+    let source = config::get_config_value("SERVER_LIST_URL");
+    let document = server_list::load(&source).await?;
+    info!(
+        "Server list loaded - Version: {}, Servers: {}",
+        document.version,
+        document.servers.len()
+    );
 
-    let json: Value = response.json().await?;
-    let server_list = parse_server_list_json(&json)?;
+    let server_list = build_server_list(&document)?;
 
     let mut buf = Vec::new();
     server_list.encode(&mut buf)?;
     Ok(buf)
 }
 
-/// Parses JSON into ServerList struct.
+/// Converts a server list document into ServerList struct.
 ///
-/// Converts server list JSON to ServerList with error checking.
+/// Merges the document with the character counts of the current account.
 ///
 /// # Arguments
 ///
-/// * `json` - Reference to serde_json::Value with server list data.
+/// * `document` - Reference to the parsed ServerListDocument.
 ///
 /// # Returns
 ///
 /// Result<ServerList, Box<dyn std::error::Error>>:
 /// - Ok(ServerList): Populated ServerList struct
-/// - Err: Parsing error description
-fn parse_server_list_json(json: &Value) -> Result<ServerList, Box<dyn std::error::Error>> {
+/// - Err: Conversion error description
+fn build_server_list(document: &ServerListDocument) -> Result<ServerList, Box<dyn std::error::Error>> {
     let mut server_list = ServerList {
         servers: vec![],
         last_server_id: 0,
@@ -842,55 +835,30 @@ fn parse_server_list_json(json: &Value) -> Result<ServerList, Box<dyn std::error
         player_last_server, player_last_server_id, character_counts
     );
 
-    let servers = json["servers"]
-        .as_array()
-        .ok_or("No servers found in JSON")?;
-    for server in servers {
-        let server_id = server["id"]
-            .as_u64()
-            .ok_or("Missing or invalid 'id' field")? as u32;
+    for server in &document.servers {
+        let server_id = server.id;
         let character_count = character_counts.get(&server_id).cloned().unwrap_or(0);
 
-        let json_available = server["available"].as_u64().unwrap_or(0);
-
         info!(
-            "Processing server: id={}, name={}, json_available={}",
-            server_id, server["name"], json_available
+            "Processing server: id={}, name={}, available={}",
+            server_id, server.name, server.available
         );
 
         let display_count = format!("({})", character_count);
-        let name = format!(
-            "{}{}",
-            server["name"]
-                .as_str()
-                .ok_or("Missing or invalid 'name' field")?,
-            display_count
-        );
-        let title = format!(
-            "{}{}",
-            server["title"]
-                .as_str()
-                .ok_or("Missing or invalid 'title' field")?,
-            display_count
-        );
+        let name = format!("{}{}", server.name, display_count);
+        let title = format!("{}{}", server.title(), display_count);
 
         info!("Formatted server name: {}", name);
 
-        // Modify population field based on 'available' in JSON
-        let population = if json_available == 0 {
+        // Modify population field based on 'available' in the document
+        let population = if !server.available {
             "<b><font color=\"#FF0000\">Offline</font></b>".to_string()
         } else {
-            server["population"]
-                .as_str()
-                .ok_or("Missing or invalid 'population' field")?
-                .to_string()
+            server.population.clone()
         };
 
         // Handle address and host fields
-        let address_str = server["address"].as_str();
-        let host_str = server["host"].as_str();
-
-        let (address, host) = match (address_str, host_str) {
+        let (address, host) = match (server.address.as_deref(), server.host.as_deref()) {
             (Some(addr), Some(_)) => {
                 // If both are present, use address and ignore host
                 (ipv4_to_u32(addr), Vec::new())
@@ -903,33 +871,21 @@ fn parse_server_list_json(json: &Value) -> Result<ServerList, Box<dyn std::error
         let server_info = ServerInfo {
             id: server_id,
             name: utf16_to_bytes(&name),
-            category: utf16_to_bytes(
-                server["category"]
-                    .as_str()
-                    .ok_or("Missing or invalid 'category' field")?,
-            ),
+            category: utf16_to_bytes(&server.category),
             title: utf16_to_bytes(&title),
-            queue: utf16_to_bytes(
-                server["queue"]
-                    .as_str()
-                    .ok_or("Missing or invalid 'queue' field")?,
-            ),
+            queue: utf16_to_bytes(&server.queue),
             population: utf16_to_bytes(&population),
             address,
-            port: server["port"]
-                .as_u64()
-                .ok_or("Missing or invalid 'port' field")? as u32,
+            port: server.port,
             available: 1,
-            unavailable_message: utf16_to_bytes(
-                server["unavailable_message"].as_str().unwrap_or(""),
-            ),
+            unavailable_message: utf16_to_bytes(&server.unavailable_message),
             host,
         };
         server_list.servers.push(server_info);
     }
 
     server_list.last_server_id = player_last_server_id;
-    server_list.sort_criterion = json["sort_criterion"].as_u64().unwrap_or(3) as u32;
+    server_list.sort_criterion = document.sort_criterion.unwrap_or(3);
 
     Ok(server_list)
 }
@@ -1,6 +1,9 @@
-
 pub mod game;
 
 pub use game::{run_game, get_game_status_receiver, is_game_running, reset_global_state, setup_logging, TeraLogger};
 pub mod global_credentials;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod error;
+pub mod patch;
+pub mod server_list;
+pub mod services;
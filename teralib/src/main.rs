@@ -1,5 +1,688 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::future::Future;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
 
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::error;
+use once_cell::sync::Lazy;
+use teralib::error::LauncherError;
+use teralib::patch::builder::{build_manifest, read_manifest, BuildOptions, FILES_DIR};
+use teralib::patch::compression::Compression;
+use teralib::patch::delta::PatchFormat;
+use teralib::patch::hashing::{HashAlgorithm, StorageKind};
+use teralib::patch::ignore_rules::IgnoreRules;
+use teralib::patch::launcher_update::{publish_launcher, LAUNCHER_DIR, LAUNCHER_FILE_NAME};
+use teralib::patch::manifest::{FileInfo, MANIFEST_FILE_NAME};
+use teralib::patch::orphans::OrphanAction;
+use teralib::patch::preflight::PreflightReport;
+use teralib::patch::server;
+use teralib::patch::signing::{generate_signing_key, load_signing_key, sign_documents};
+use teralib::services::auth::{session_file_path, AuthService};
+use teralib::services::launch::LaunchService;
+use teralib::services::patch::PatchService;
+use teralib::services::progress::{
+    format_bytes, DownloadReport, FileCheckProgress, FileCheckSummary, ProgressPayload, ProgressReporter, UpdateInfo,
+};
+use teralib::services::settings::SettingsService;
 
-fn main() {
-//NOTHING
-}
\ No newline at end of file
+const USAGE: &str = "Usage: tera_launcher <command> [options]
+
+Commands:
+  login <username>
+      Logs in and keeps the session for `launch`. The password is read from
+      TERA_PASSWORD or asked for.
+
+  logout
+      Forgets the saved session.
+
+  check
+      Compares the game folder with the server and lists what `update` would
+      download.
+
+  update
+      Downloads the files changed since the installed release.
+
+  verify [--fix] [--orphans <action>]
+      Verifies every game file without the hash cache and lists files that are
+      not part of the game.
+
+      --fix                   Download the missing and damaged files
+      --orphans <action>      quarantine or delete the files not part of the game
+
+  launch [--no-update]
+      Updates the game, then starts it with the saved session and waits for it
+      to exit.
+
+      --no-update             Start the game without checking for updates
+
+  status
+      Shows the settings, the installed release and the saved session.
+
+  The commands above use tera_config.ini like the launcher, or the file given
+  with --config <file>.
+
+Publishing commands:
+  build-manifest <game dir> --out <dir> --base-url <url> [options]
+      Hashes the game folder and lays out a publishable tree in <dir>:
+      hash-file.json, version.json, the delta from the previous release,
+      files/, compressed/, patches/, chunks/ and archive/. Publish <dir> under <url>.
+
+      --previous <file>       Previous hash file, defaults to <dir>/hash-file.json
+      --version <n>           Build number, defaults to the previous one plus one
+      --notes <text>          Release notes
+      --compression <format>  Also publish zstd or gzip compressed files
+      --patches <format>      Also publish bsdiff or zstd diffs from the previous
+                              release's files in <dir>/files
+      --chunked               Publish content-defined chunks
+      --archive-part-size <MB>
+                              Also publish a base archive for first installs,
+                              split into parts of this size
+      --hash <algorithm>      sha256 (default) or blake3
+      --ignore-file <file>    Extra gitignore-style rules, after <game dir>/.teraignore
+      --storage <kind>        ssd, hdd or auto (default), sets the read parallelism
+      --link                  Hard link files into <dir>/files instead of copying
+      --no-files              Do not publish the game files, only the hash file and artifacts
+      --sign-key <file>       Sign the published documents with this key
+
+  publish-launcher <binary> --out <dir> --base-url <url> --version <version> [options]
+      Copies a launcher build to <dir>/launcher/<version>/ and describes it in
+      launcher.json, offered to launchers older than <version> on their next start.
+
+      --notes <text>          Release notes
+      --hash <algorithm>      sha256 (default) or blake3
+      --sign-key <file>       Sign the published documents with this key
+
+  serve <dir> [--bind <address>]
+      Serves a publish tree or a game folder with a generated hash file over
+      HTTP, with range requests, e.g. for a LAN or as a local file server.
+      Listens on 0.0.0.0:8080 unless --bind is given. Point the launcher at it
+      with `server_url` in the [download] section of tera_config.ini.
+
+  sign <dir> --key <file>
+      Signs hash-file.json, version.json, base-archive.json, launcher.json and the
+      deltas in <dir>.
+
+  create-signing-key <file>
+      Writes a new signing key and prints the public key for config.json.
+";
+
+/// Arguments following a command: positional values, `--name value` options
+/// and `--name` flags.
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>, options: &[&str], flags: &[&str]) -> Result<Self, String> {
+        let mut parsed = Args { positional: Vec::new(), options: HashMap::new(), flags: Vec::new() };
+        let mut args = args.peekable();
+
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg);
+                continue;
+            };
+            if flags.contains(&name) {
+                parsed.flags.push(name.to_string());
+            } else if options.contains(&name) {
+                let value = args.next().ok_or_else(|| format!("--{} needs a value", name))?;
+                parsed.options.insert(name.to_string(), value);
+            } else {
+                return Err(format!("Unknown option --{}", name));
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn required(&self, name: &str) -> Result<&str, String> {
+        self.option(name).ok_or_else(|| format!("--{} is required", name))
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    fn positional(&self, index: usize, what: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Missing {}", what))
+    }
+}
+
+fn parse_compression(value: &str) -> Result<Compression, String> {
+    match value {
+        "zstd" => Ok(Compression::Zstd),
+        "gzip" => Ok(Compression::Gzip),
+        _ => Err(format!("Unknown compression {:?}, expected zstd or gzip", value)),
+    }
+}
+
+fn parse_patch_format(value: &str) -> Result<PatchFormat, String> {
+    match value {
+        "bsdiff" => Ok(PatchFormat::Bsdiff),
+        "zstd" => Ok(PatchFormat::Zstd),
+        _ => Err(format!("Unknown patch format {:?}, expected bsdiff or zstd", value)),
+    }
+}
+
+fn parse_hash_algorithm(value: &str) -> Result<HashAlgorithm, String> {
+    match value {
+        "sha256" => Ok(HashAlgorithm::Sha256),
+        "blake3" => Ok(HashAlgorithm::Blake3),
+        _ => Err(format!("Unknown hash algorithm {:?}, expected sha256 or blake3", value)),
+    }
+}
+
+/// Progress bars of the command line. Log records and messages go through it,
+/// so they are printed above the bars instead of in the middle of them.
+static PROGRESS: Lazy<MultiProgress> = Lazy::new(MultiProgress::new);
+
+/// Sends log records to stderr above the progress bars.
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        PROGRESS.suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+fn progress_bar() -> ProgressBar {
+    let progress_bar = PROGRESS.add(ProgressBar::new(0));
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("##-"),
+    );
+    progress_bar
+}
+
+/// Shows the progress of the services on the console, with one bar for the
+/// file check and one for the download.
+#[derive(Default)]
+struct ConsoleReporter {
+    check_bar: Mutex<Option<ProgressBar>>,
+    download_bar: Mutex<Option<ProgressBar>>,
+}
+
+impl ConsoleReporter {
+    fn finish(bar: &Mutex<Option<ProgressBar>>) {
+        if let Some(progress_bar) = bar.lock().unwrap().take() {
+            progress_bar.finish_and_clear();
+        }
+    }
+
+    fn finish_download(&self) {
+        ConsoleReporter::finish(&self.download_bar);
+    }
+}
+
+impl ProgressReporter for ConsoleReporter {
+    fn update_info(&self, info: &UpdateInfo) {
+        PROGRESS.suspend(|| {
+            match info.installed_version {
+                Some(installed) => println!("Installed version {}, latest version {} ({} check)", installed, info.version, info.mode),
+                None => println!("Latest version {} ({} check)", info.version, info.mode),
+            }
+            if !info.release_notes.is_empty() && info.installed_version != Some(info.version) {
+                println!("{}", info.release_notes);
+            }
+        });
+    }
+
+    fn file_check_progress(&self, progress: &FileCheckProgress) {
+        let mut check_bar = self.check_bar.lock().unwrap();
+        let progress_bar = check_bar.get_or_insert_with(progress_bar);
+        progress_bar.set_length(progress.total_files as u64);
+        progress_bar.set_position(progress.current_count as u64);
+        progress_bar.set_message(format!("{} to update", progress.files_to_update));
+    }
+
+    fn file_check_completed(&self, _summary: &FileCheckSummary) {
+        ConsoleReporter::finish(&self.check_bar);
+    }
+
+    fn update_preflight_failed(&self, report: &PreflightReport) {
+        PROGRESS.suspend(|| {
+            for issue in &report.issues {
+                eprintln!("{}", issue.message());
+            }
+        });
+    }
+
+    fn download_progress(&self, progress: &ProgressPayload) {
+        let mut download_bar = self.download_bar.lock().unwrap();
+        let progress_bar = download_bar.get_or_insert_with(|| {
+            let progress_bar = PROGRESS.add(ProgressBar::new(progress.total_bytes));
+            progress_bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed_precise}] {bar:40.cyan/blue} {bytes}/{total_bytes} {msg}")
+                    .unwrap()
+                    .progress_chars("##-"),
+            );
+            progress_bar
+        });
+        progress_bar.set_length(progress.total_bytes);
+        progress_bar.set_position(progress.downloaded_bytes);
+        progress_bar.set_message(format!(
+            "{}/s {}/{} {}",
+            format_bytes(progress.speed as u64),
+            progress.current_file_index,
+            progress.total_files,
+            progress.file_name
+        ));
+    }
+
+    fn download_report(&self, report: &DownloadReport) {
+        self.finish_download();
+        for failure in &report.failed_files {
+            eprintln!("Failed to download {} after {} attempt(s): {}", failure.path, failure.attempts, failure.error);
+        }
+    }
+
+    fn download_cancelled(&self) {
+        self.finish_download();
+    }
+
+    fn download_complete(&self) {
+        self.finish_download();
+    }
+
+    fn game_status(&self, status: &str) {
+        PROGRESS.suspend(|| println!("{}", status));
+    }
+}
+
+/// The services of the launcher, on the same settings, hash cache and session.
+struct Launcher {
+    patch: PatchService,
+    auth: AuthService,
+    session_path: PathBuf,
+    reporter: Arc<dyn ProgressReporter>,
+}
+
+impl Launcher {
+    fn new(args: &Args) -> Result<Self, LauncherError> {
+        let settings = match args.option("config") {
+            Some(config_path) => SettingsService::with_config_path(PathBuf::from(config_path)),
+            None => SettingsService::new(),
+        };
+        let patch = PatchService::new(settings);
+
+        // Finish or undo a staged update cut short, as the launcher does on start
+        if let Ok(game_path) = patch.settings().game_path() {
+            if let Err(e) = patch.recover_interrupted_update(&game_path) {
+                error!("Failed to recover an interrupted update: {}", e);
+            }
+        }
+
+        Ok(Launcher {
+            patch,
+            auth: AuthService::new(),
+            session_path: session_file_path()?,
+            reporter: Arc::new(ConsoleReporter::default()),
+        })
+    }
+
+    /// Downloads the files found by a file check.
+    ///
+    /// # Returns
+    ///
+    /// The number of files downloaded.
+    async fn download(&self, files_to_update: Vec<FileInfo>) -> Result<usize, LauncherError> {
+        let count = files_to_update.len();
+        let size: u64 = files_to_update.iter().map(|f| f.transfer_size()).sum();
+        if count > 0 {
+            println!("Downloading {} file(s), {}", count, format_bytes(size));
+            self.patch.download_files(files_to_update, &self.reporter).await?;
+        }
+        Ok(count)
+    }
+
+    async fn update(&self) -> Result<(), LauncherError> {
+        let files_to_update = self.patch.files_to_update(false, &self.reporter).await?;
+        match self.download(files_to_update).await? {
+            0 => println!("The game is up to date"),
+            count => println!("Updated {} file(s)", count),
+        }
+        Ok(())
+    }
+}
+
+/// Runs a command of the launcher on a new runtime.
+fn run_launcher_command<F, Fut>(args: Args, command: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnOnce(Launcher, Args) -> Fut,
+    Fut: Future<Output = Result<(), Box<dyn Error>>>,
+{
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let launcher = Launcher::new(&args)?;
+        command(launcher, args).await
+    })
+}
+
+async fn login_command(launcher: Launcher, args: Args) -> Result<(), Box<dyn Error>> {
+    let username = args.positional(0, "username")?;
+    let password = match env::var("TERA_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => rpassword::prompt_password("Password: ")?,
+    };
+
+    let auth_info = launcher.auth.authenticate(username, &password).await?;
+    launcher.auth.save_session(&launcher.session_path)?;
+    println!("Logged in as {}", auth_info.user_name);
+    Ok(())
+}
+
+async fn logout_command(launcher: Launcher, _args: Args) -> Result<(), Box<dyn Error>> {
+    launcher.auth.clear_session(&launcher.session_path)?;
+    println!("Logged out");
+    Ok(())
+}
+
+async fn check_command(launcher: Launcher, _args: Args) -> Result<(), Box<dyn Error>> {
+    let files_to_update = launcher.patch.files_to_update(false, &launcher.reporter).await?;
+    if files_to_update.is_empty() {
+        println!("The game is up to date");
+        return Ok(());
+    }
+
+    for file_info in &files_to_update {
+        println!("  {} ({})", file_info.path, format_bytes(file_info.transfer_size()));
+    }
+    let size: u64 = files_to_update.iter().map(|f| f.transfer_size()).sum();
+    println!("{} file(s) to update, {}", files_to_update.len(), format_bytes(size));
+    Ok(())
+}
+
+async fn update_command(launcher: Launcher, _args: Args) -> Result<(), Box<dyn Error>> {
+    launcher.update().await?;
+    Ok(())
+}
+
+fn parse_orphan_action(value: &str) -> Result<OrphanAction, String> {
+    match value {
+        "quarantine" => Ok(OrphanAction::Quarantine),
+        "delete" => Ok(OrphanAction::Delete),
+        _ => Err(format!("Unknown orphan action {:?}, expected quarantine or delete", value)),
+    }
+}
+
+async fn verify_command(launcher: Launcher, args: Args) -> Result<(), Box<dyn Error>> {
+    let orphan_action = args.option("orphans").map(parse_orphan_action).transpose()?;
+    let report = launcher.patch.repair(&launcher.reporter).await?;
+
+    let size: u64 = report.files_to_update.iter().map(|f| f.size).sum();
+    println!("{} file(s) missing or damaged, {}", report.files_to_update.len(), format_bytes(size));
+    for orphan in &report.orphans {
+        println!("  {} ({})", orphan.path, format_bytes(orphan.size));
+    }
+    println!("{} file(s) not part of the game, {}", report.orphans.len(), format_bytes(report.orphaned_size));
+
+    if let Some(action) = orphan_action.filter(|_| !report.orphans.is_empty()) {
+        let paths = report.orphans.iter().map(|o| o.path.clone()).collect();
+        let cleanup = launcher.patch.clean_orphaned_files(paths, action).await?;
+        match cleanup.quarantine_path {
+            Some(quarantine_path) => println!("Moved {} file(s) to {}", cleanup.cleaned.len(), quarantine_path),
+            None => println!("Deleted {} file(s)", cleanup.cleaned.len()),
+        }
+    }
+
+    if args.flag("fix") {
+        let count = launcher.download(report.files_to_update).await?;
+        println!("Repaired {} file(s)", count);
+    } else if !report.files_to_update.is_empty() {
+        println!("Run `tera_launcher verify --fix` to download them");
+    }
+    Ok(())
+}
+
+async fn launch_command(launcher: Launcher, args: Args) -> Result<(), Box<dyn Error>> {
+    if !launcher.auth.load_session(&launcher.session_path)? {
+        return Err("Not logged in, run `tera_launcher login` first".into());
+    }
+    if !args.flag("no-update") {
+        launcher.update().await?;
+    }
+
+    let launch = LaunchService::new();
+    let game = launch
+        .launch(launcher.patch.settings(), launcher.auth.auth_info(), Arc::clone(&launcher.reporter))
+        .await?;
+    println!("Game started as {}, waiting for it to exit", launcher.auth.auth_info().user_name);
+    game.await?;
+    Ok(())
+}
+
+async fn status_command(launcher: Launcher, _args: Args) -> Result<(), Box<dyn Error>> {
+    let settings = launcher.patch.settings();
+    match settings.config_path() {
+        Some(config_path) => println!("Settings:      {}", config_path.display()),
+        None => println!("Settings:      not found"),
+    }
+    if let Ok((game_path, language)) = settings.load_config() {
+        println!("Game folder:   {}", game_path.display());
+        println!("Language:      {}", language);
+    }
+    match settings.installed_version() {
+        Some(version) => println!("Installed:     version {}", version),
+        None => println!("Installed:     unknown version"),
+    }
+
+    let account = match launcher.auth.load_session(&launcher.session_path) {
+        Ok(true) => launcher.auth.auth_info().user_name,
+        Ok(false) => "not logged in".to_string(),
+        Err(e) => format!("invalid session ({})", e),
+    };
+    println!("Account:       {}", account);
+
+    let server = match launcher.patch.check_server_connection().await {
+        Ok(true) => "reachable".to_string(),
+        Ok(false) => "not answering".to_string(),
+        Err(e) => format!("unreachable ({})", e),
+    };
+    println!("File server:   {} {}", settings.hash_file_url(), server);
+    Ok(())
+}
+
+fn build_manifest_command(args: Args) -> Result<(), Box<dyn Error>> {
+    let source = PathBuf::from(args.positional(0, "game directory")?);
+    let output = PathBuf::from(args.required("out")?);
+    let base_url = args.required("base-url")?.to_string();
+    if !source.is_dir() {
+        return Err(format!("Game directory not found: {:?}", source).into());
+    }
+
+    let previous_path = args
+        .option("previous")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| output.join(MANIFEST_FILE_NAME));
+    let previous = if previous_path.exists() {
+        let previous = read_manifest(&previous_path)?;
+        println!("Previous release: version {} ({} files)", previous.version, previous.files.len());
+        Some(previous)
+    } else if args.option("previous").is_some() {
+        return Err(format!("Previous hash file not found: {:?}", previous_path).into());
+    } else {
+        None
+    };
+
+    let options = BuildOptions {
+        rules: IgnoreRules::load(&source, args.option("ignore-file").map(Path::new))?,
+        storage: StorageKind::from_setting(args.option("storage").unwrap_or("auto"), &source),
+        source,
+        output: output.clone(),
+        base_url,
+        copy_files: !args.flag("no-files"),
+        link_files: args.flag("link"),
+        previous,
+        version: args.option("version").map(str::parse).transpose().map_err(|e| format!("Invalid --version: {}", e))?,
+        release_notes: args.option("notes").unwrap_or_default().to_string(),
+        compression: args.option("compression").map(parse_compression).transpose()?,
+        patch_format: args.option("patches").map(parse_patch_format).transpose()?,
+        chunked: args.flag("chunked"),
+        archive_part_size: args
+            .option("archive-part-size")
+            .map(|mb| mb.parse::<u64>().map(|mb| mb * 1024 * 1024))
+            .transpose()
+            .map_err(|e| format!("Invalid --archive-part-size: {}", e))?,
+        hash_algorithm: args.option("hash").map(parse_hash_algorithm).transpose()?.unwrap_or_default(),
+    };
+
+    let progress_bar = progress_bar();
+    let summary = build_manifest(&options, |progress| {
+        progress_bar.set_length(progress.total_files as u64);
+        progress_bar.set_position(progress.processed_files as u64);
+        Ok(())
+    })?;
+    progress_bar.finish_and_clear();
+
+    println!(
+        "Version {}: {} files, {} bytes in {:?}",
+        summary.version, summary.processed_files, summary.total_size, summary.duration
+    );
+    for document in &summary.documents {
+        println!("Wrote {}", document.display());
+    }
+    if options.copy_files {
+        println!("Game files published to {}", output.join(FILES_DIR).display());
+    }
+
+    if let Some(key_path) = args.option("sign-key") {
+        let signed = sign_documents(&output, &load_signing_key(Path::new(key_path))?)?;
+        println!("Signed {}", signed.join(", "));
+    } else {
+        println!("Not signed: run `tera_launcher sign` before publishing");
+    }
+    Ok(())
+}
+
+fn publish_launcher_command(args: Args) -> Result<(), Box<dyn Error>> {
+    let binary = PathBuf::from(args.positional(0, "launcher binary")?);
+    let output = PathBuf::from(args.required("out")?);
+    let hash_algorithm = args.option("hash").map(parse_hash_algorithm).transpose()?.unwrap_or_default();
+
+    let release = publish_launcher(
+        &binary,
+        &output,
+        args.required("base-url")?,
+        args.required("version")?,
+        args.option("notes").map(str::to_string),
+        hash_algorithm,
+    )?;
+    let document = output.join(LAUNCHER_FILE_NAME);
+    std::fs::write(&document, serde_json::to_string_pretty(&release)?)?;
+    println!("Launcher {} published to {}", release.version, output.join(LAUNCHER_DIR).display());
+    println!("Wrote {}", document.display());
+
+    if let Some(key_path) = args.option("sign-key") {
+        let signed = sign_documents(&output, &load_signing_key(Path::new(key_path))?)?;
+        println!("Signed {}", signed.join(", "));
+    } else {
+        println!("Not signed: run `tera_launcher sign` before publishing");
+    }
+    Ok(())
+}
+
+fn serve_command(args: Args) -> Result<(), Box<dyn Error>> {
+    let root = PathBuf::from(args.positional(0, "directory to serve")?);
+    let addr: SocketAddr = args
+        .option("bind")
+        .unwrap_or("0.0.0.0:8080")
+        .parse()
+        .map_err(|e| format!("Invalid --bind address: {}", e))?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let (addr, server) = server::bind(root.clone(), addr)?;
+        println!("Serving {} on http://{}", root.display(), addr);
+        if !root.join(MANIFEST_FILE_NAME).exists() {
+            println!("Warning: no {} in {}, build one first", MANIFEST_FILE_NAME, root.display());
+        }
+        server.await
+    })?;
+    Ok(())
+}
+
+fn sign_command(args: Args) -> Result<(), Box<dyn Error>> {
+    let folder = PathBuf::from(args.positional(0, "publish directory")?);
+    let signing_key = load_signing_key(Path::new(args.required("key")?))?;
+    let signed = sign_documents(&folder, &signing_key)?;
+    println!("Signed {}", signed.join(", "));
+    Ok(())
+}
+
+fn create_signing_key_command(args: Args) -> Result<(), Box<dyn Error>> {
+    let key_path = PathBuf::from(args.positional(0, "key file")?);
+    let public_key = generate_signing_key(&key_path)?;
+    println!("Signing key written to {}", key_path.display());
+    println!("Set MANIFEST_PUBLIC_KEY in config.json to: {}", public_key);
+    Ok(())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let command = args.next().unwrap_or_default();
+
+    match command.as_str() {
+        "login" => run_launcher_command(Args::parse(args, &["config"], &[])?, login_command),
+        "logout" => run_launcher_command(Args::parse(args, &["config"], &[])?, logout_command),
+        "check" => run_launcher_command(Args::parse(args, &["config"], &[])?, check_command),
+        "update" => run_launcher_command(Args::parse(args, &["config"], &[])?, update_command),
+        "verify" => run_launcher_command(Args::parse(args, &["config", "orphans"], &["fix"])?, verify_command),
+        "launch" => run_launcher_command(Args::parse(args, &["config"], &["no-update"])?, launch_command),
+        "status" => run_launcher_command(Args::parse(args, &["config"], &[])?, status_command),
+        "build-manifest" => build_manifest_command(Args::parse(
+            args,
+            &["out", "base-url", "previous", "version", "notes", "compression", "patches", "hash", "ignore-file", "storage", "sign-key", "archive-part-size"],
+            &["chunked", "link", "no-files"],
+        )?),
+        "publish-launcher" => publish_launcher_command(Args::parse(args, &["out", "base-url", "version", "notes", "hash", "sign-key"], &[])?),
+        "serve" => serve_command(Args::parse(args, &["bind"], &[])?),
+        "sign" => sign_command(Args::parse(args, &["key"], &[])?),
+        "create-signing-key" => create_signing_key_command(Args::parse(args, &[], &[])?),
+        "" | "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("Unknown command {:?}\n\n{}", command, USAGE).into()),
+    }
+}
+
+/// The server logs every request, the other commands only show progress.
+fn command_logs_requests() -> bool {
+    env::args().nth(1).as_deref() == Some("serve")
+}
+
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(if command_logs_requests() { "info" } else { "warn" }))
+        .target(env_logger::Target::Pipe(Box::new(LogWriter)))
+        .init();
+
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// Standard library imports
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+// Third-party imports
+use log::info;
+use serde::{Deserialize, Serialize};
+use tar::{Archive, Builder, EntryType, HeaderMode};
+
+use super::hashing::{hash_file, HashAlgorithm};
+use super::ignore_rules::IgnoreRules;
+use super::manifest::FileInfo;
+use super::paths::resolve_game_file;
+
+/// Folder of the publish tree holding the base archive parts, published under
+/// `<base URL>/archive/`.
+pub const ARCHIVE_DIR: &str = "archive";
+
+/// Signed description of the base archive, published next to the hash file.
+pub const ARCHIVE_FILE_NAME: &str = "base-archive.json";
+
+/// Name of the part files, followed by their index.
+const ARCHIVE_PART_NAME: &str = "base.tar.zst";
+
+/// zstd level of the archive. Lower than the per-file artifacts, as the
+/// archive holds the whole client.
+const ARCHIVE_ZSTD_LEVEL: i32 = 9;
+
+/// One part of the base archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivePart {
+    pub url: String,
+    pub size: u64,
+    pub hash: String,
+}
+
+/// Base archive of a release: the full client as a zstd compressed tar,
+/// split into parts that are downloaded like any other file.
+///
+/// It only speeds up first installs. Extracted files go through the regular
+/// file check, which downloads whatever the archive got wrong or lacks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveInfo {
+    /// Release the archive was built from.
+    pub version: u64,
+    pub hash_algorithm: HashAlgorithm,
+    pub files: usize,
+    pub unpacked_size: u64,
+    /// Parts in order, concatenated they form the compressed stream.
+    pub parts: Vec<ArchivePart>,
+}
+
+impl ArchiveInfo {
+    /// Total size of the parts.
+    pub fn download_size(&self) -> u64 {
+        self.parts.iter().map(|part| part.size).sum()
+    }
+
+    /// File name of part number `index`, e.g. `base.tar.zst.003`.
+    pub fn part_file_name(index: usize) -> String {
+        format!("{}.{:03}", ARCHIVE_PART_NAME, index)
+    }
+}
+
+/// Writer splitting its output into numbered part files of `part_size` bytes.
+struct PartWriter {
+    folder: PathBuf,
+    part_size: u64,
+    written: u64,
+    current: Option<BufWriter<File>>,
+    paths: Vec<PathBuf>,
+}
+
+impl PartWriter {
+    fn new(folder: &Path, part_size: u64) -> Self {
+        PartWriter { folder: folder.to_path_buf(), part_size: part_size.max(1), written: 0, current: None, paths: Vec::new() }
+    }
+
+    fn next_part(&mut self) -> io::Result<()> {
+        if let Some(mut current) = self.current.take() {
+            current.flush()?;
+        }
+        let path = self.folder.join(ArchiveInfo::part_file_name(self.paths.len()));
+        self.current = Some(BufWriter::new(File::create(&path)?));
+        self.paths.push(path);
+        self.written = 0;
+        Ok(())
+    }
+
+    /// Flushes the last part and returns the paths of all parts.
+    fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        if let Some(mut current) = self.current.take() {
+            current.flush()?;
+        }
+        Ok(self.paths)
+    }
+}
+
+impl Write for PartWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.current.is_none() || self.written == self.part_size {
+            self.next_part()?;
+        }
+        let room = (self.part_size - self.written).min(buf.len() as u64) as usize;
+        let written = self.current.as_mut().map_or(Ok(0), |current| current.write(&buf[..room]))?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.current.as_mut().map_or(Ok(()), |current| current.flush())
+    }
+}
+
+/// Packs the manifest `files` of `source` into a base archive in
+/// `<output>/archive/`, split into parts of at most `part_size` bytes.
+/// Parts of an earlier archive are removed first.
+pub fn write_archive(
+    source: &Path,
+    output: &Path,
+    base_url: &str,
+    files: &[FileInfo],
+    version: u64,
+    part_size: u64,
+    hash_algorithm: HashAlgorithm,
+) -> Result<ArchiveInfo, String> {
+    let archive_path = output.join(ARCHIVE_DIR);
+    match fs::remove_dir_all(&archive_path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(format!("Failed to remove {:?}: {}", archive_path, e)),
+        _ => {}
+    }
+    fs::create_dir_all(&archive_path).map_err(|e| format!("Failed to create {:?}: {}", archive_path, e))?;
+    info!("Writing base archive of {} files to: {:?}", files.len(), archive_path);
+
+    let encoder = zstd::stream::write::Encoder::new(PartWriter::new(&archive_path, part_size), ARCHIVE_ZSTD_LEVEL)
+        .map_err(|e| e.to_string())?;
+    let mut builder = Builder::new(encoder);
+    builder.mode(HeaderMode::Deterministic);
+    for file_info in files {
+        builder
+            .append_path_with_name(source.join(&file_info.path), &file_info.path)
+            .map_err(|e| format!("Failed to archive {}: {}", file_info.path, e))?;
+    }
+    let paths = builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .and_then(PartWriter::finish)
+        .map_err(|e| format!("Failed to write the base archive: {}", e))?;
+
+    let base_url = base_url.trim_end_matches('/');
+    let parts = paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| {
+            Ok(ArchivePart {
+                url: format!("{}/{}/{}", base_url, ARCHIVE_DIR, ArchiveInfo::part_file_name(index)),
+                size: fs::metadata(path).map_err(|e| e.to_string())?.len(),
+                hash: hash_file(path, hash_algorithm)?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let archive = ArchiveInfo {
+        version,
+        hash_algorithm,
+        files: files.len(),
+        unpacked_size: files.iter().map(|f| f.size).sum(),
+        parts,
+    };
+    info!("Base archive: {} part(s), {} bytes", archive.parts.len(), archive.download_size());
+    Ok(archive)
+}
+
+/// Unpacks the downloaded `parts` of a base archive into the game folder.
+///
+/// Entry names are checked like manifest paths and entries excluded by the
+/// ignore rules are skipped, so the archive cannot overwrite user settings.
+/// `progress` is called after each file with its path and the file count.
+///
+/// # Returns
+///
+/// The number of files extracted.
+pub fn extract_archive<F>(parts: &[PathBuf], game_path: &Path, rules: &IgnoreRules, mut progress: F) -> Result<usize, String>
+where
+    F: FnMut(&str, usize) -> Result<(), String>,
+{
+    let mut stream: Box<dyn Read> = Box::new(io::empty());
+    for part in parts {
+        let file = File::open(part).map_err(|e| format!("Failed to open {:?}: {}", part, e))?;
+        stream = Box::new(stream.chain(BufReader::new(file)));
+    }
+    let decoder = zstd::stream::read::Decoder::new(stream).map_err(|e| e.to_string())?;
+    let mut archive = Archive::new(decoder);
+
+    let mut extracted = 0;
+    for entry in archive.entries().map_err(|e| format!("Invalid base archive: {}", e))? {
+        let mut entry = entry.map_err(|e| format!("Invalid base archive: {}", e))?;
+        if entry.header().entry_type() != EntryType::Regular {
+            continue;
+        }
+
+        let path = entry
+            .path()
+            .map_err(|e| format!("Invalid base archive entry: {}", e))?
+            .to_str()
+            .ok_or("Base archive entry is not valid UTF-8")?
+            .replace('\\', "/");
+        if rules.is_ignored_manifest_path(&path) {
+            continue;
+        }
+
+        let target = resolve_game_file(game_path, &path)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        entry.unpack(&target).map_err(|e| format!("Failed to extract {}: {}", path, e))?;
+
+        extracted += 1;
+        progress(&path, extracted)?;
+    }
+
+    info!("Extracted {} file(s) from the base archive", extracted);
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            hash: String::new(),
+            size,
+            url: String::new(),
+            patches: Vec::new(),
+            compressed: None,
+            chunks: Vec::new(),
+            hash_algorithm: HashAlgorithm::default(),
+        }
+    }
+
+    #[test]
+    fn archive_round_trips_through_parts() {
+        let base = std::env::temp_dir().join(format!("teralaunch-archive-{}", std::process::id()));
+        let (source, output, target) = (base.join("source"), base.join("publish"), base.join("install"));
+        fs::create_dir_all(source.join("S1Game/Config")).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        // Incompressible, so the archive spans several parts
+        let mut seed = 0x2545_f491u32;
+        let data: Vec<u8> = (0..20_000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        fs::write(source.join("S1Game/S1Data.gpk"), &data).unwrap();
+        fs::write(source.join("S1Game/Config/S1Engine.ini"), "settings").unwrap();
+
+        let files = [file("S1Game/S1Data.gpk", data.len() as u64), file("S1Game/Config/S1Engine.ini", 8)];
+        let archive = write_archive(&source, &output, "http://host/patch/", &files, 5, 1024, HashAlgorithm::Sha256).unwrap();
+
+        assert!(archive.parts.len() > 1);
+        assert_eq!(archive.parts[1].url, "http://host/patch/archive/base.tar.zst.001");
+        assert_eq!(archive.unpacked_size, data.len() as u64 + 8);
+
+        let parts: Vec<PathBuf> = (0..archive.parts.len())
+            .map(|index| output.join(ARCHIVE_DIR).join(ArchiveInfo::part_file_name(index)))
+            .collect();
+        let rules = IgnoreRules::load(&target, None).unwrap();
+        let mut reported = Vec::new();
+        let extracted = extract_archive(&parts, &target, &rules, |path, _| {
+            reported.push(path.to_string());
+            Ok(())
+        })
+        .unwrap();
+
+        // User settings are never taken from the archive
+        assert_eq!(extracted, 1);
+        assert_eq!(reported, ["S1Game/S1Data.gpk"]);
+        assert_eq!(fs::read(target.join("S1Game/S1Data.gpk")).unwrap(), data);
+        assert!(!target.join("S1Game/Config/S1Engine.ini").exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
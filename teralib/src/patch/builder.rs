@@ -0,0 +1,450 @@
+// Standard library imports
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Third-party imports
+use log::info;
+use rayon::prelude::*;
+
+use super::archive::{write_archive, ARCHIVE_FILE_NAME};
+use super::chunks::{write_chunks, CHUNK_STORE_DIR};
+use super::compression::{compress_file, CompressedInfo, Compression, COMPRESSED_ARTIFACTS_DIR};
+use super::delta::{create_patch, PatchFormat, PatchInfo, PATCHES_DIR};
+use super::hashing::{hash_file, io_thread_pool, HashAlgorithm, StorageKind};
+use super::ignore_rules::IgnoreRules;
+use super::manifest::{
+    delta_file_name, FileInfo, Manifest, ManifestDelta, VersionInfo, MANIFEST_FILE_NAME, VERSION_FILE_NAME,
+};
+
+/// Folder of the publish tree holding the game files, published under
+/// `<base URL>/files/`.
+pub const FILES_DIR: &str = "files";
+
+/// Settings of a manifest build.
+pub struct BuildOptions {
+    /// Game folder the manifest describes.
+    pub source: PathBuf,
+    /// Folder receiving the hash file and the artifacts. May be the game folder itself.
+    pub output: PathBuf,
+    /// Root URL the output folder is published under.
+    pub base_url: String,
+    /// Copy the game files to `<output>/files`, or hard link them if `link_files` is set.
+    pub copy_files: bool,
+    pub link_files: bool,
+    /// Release the new manifest follows; a delta from it is written next to the hash file.
+    pub previous: Option<Manifest>,
+    /// Build number, defaults to the previous one plus one.
+    pub version: Option<u64>,
+    pub release_notes: String,
+    pub compression: Option<Compression>,
+    /// Also publish diffs from the files of the previous release still found in
+    /// `<output>/files`.
+    pub patch_format: Option<PatchFormat>,
+    pub chunked: bool,
+    /// Also pack the release into a base archive for first installs, split
+    /// into parts of this many bytes.
+    pub archive_part_size: Option<u64>,
+    pub hash_algorithm: HashAlgorithm,
+    pub rules: IgnoreRules,
+    pub storage: StorageKind,
+}
+
+/// Progress of a running build, reported after each file.
+pub struct BuildProgress<'a> {
+    pub current_file: &'a str,
+    pub processed_files: usize,
+    pub total_files: usize,
+    pub total_size: u64,
+}
+
+/// Outcome of a finished build.
+pub struct BuildSummary {
+    pub version: u64,
+    pub processed_files: usize,
+    pub total_size: u64,
+    pub duration: Duration,
+    /// Documents written next to the hash file, to be signed before publishing.
+    pub documents: Vec<PathBuf>,
+}
+
+/// Reads a previously generated hash file.
+pub fn read_manifest(path: &Path) -> Result<Manifest, String> {
+    let contents = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    serde_json::from_slice(&contents).map_err(|e| format!("Invalid hash file {:?}: {}", path, e))
+}
+
+/// Places a game file in the publish tree.
+fn publish_file(source: &Path, destination: &Path, link: bool) -> Result<(), String> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    if destination.exists() {
+        fs::remove_file(destination).map_err(|e| e.to_string())?;
+    }
+    if link && fs::hard_link(source, destination).is_ok() {
+        return Ok(());
+    }
+    fs::copy(source, destination)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to copy {:?}: {}", source, e))
+}
+
+/// Writes a diff from the published copy of the previous release of a file.
+///
+/// # Returns
+///
+/// The patch entry, or `None` if that copy is gone or changed, the file is
+/// unchanged, or the diff would not be smaller than the file.
+fn write_patch(
+    options: &BuildOptions,
+    previous: &Manifest,
+    previous_file: &FileInfo,
+    format: PatchFormat,
+    path: &Path,
+    new_file: &FileInfo,
+) -> Result<Option<PatchInfo>, String> {
+    let published = options.output.join(FILES_DIR).join(&new_file.path);
+    if !published.is_file() || hash_file(&published, previous.hash_algorithm)? != previous_file.hash {
+        info!("No published copy of version {} of {}, skipping its patch", previous.version, new_file.path);
+        return Ok(None);
+    }
+
+    let from_hash = if previous.hash_algorithm == options.hash_algorithm {
+        previous_file.hash.clone()
+    } else {
+        hash_file(&published, options.hash_algorithm)?
+    };
+    if from_hash == new_file.hash {
+        return Ok(None);
+    }
+
+    let artifact = format!("{}.from-{}.{}", new_file.path, previous.version, format.extension());
+    let patch_path = options.output.join(PATCHES_DIR).join(&artifact);
+    let size = create_patch(&published, path, format, &patch_path)?;
+    if size >= new_file.size {
+        fs::remove_file(&patch_path).map_err(|e| e.to_string())?;
+        return Ok(None);
+    }
+
+    Ok(Some(PatchInfo {
+        from_hash,
+        to_hash: new_file.hash.clone(),
+        url: format!("{}/{}/{}", options.base_url.trim_end_matches('/'), PATCHES_DIR, artifact),
+        size,
+        hash: hash_file(&patch_path, options.hash_algorithm)?,
+        format,
+    }))
+}
+
+/// Hashes the game folder and writes the hash file, its version head and the
+/// delta from the previous release into the output folder.
+///
+/// Files are processed on a thread pool sized for the storage, `progress` is
+/// called from those threads after each file and can abort the build by
+/// returning an error.
+pub fn build_manifest<F>(options: &BuildOptions, progress: F) -> Result<BuildSummary, String>
+where
+    F: Fn(&BuildProgress) -> Result<(), String> + Sync,
+{
+    let start_time = Instant::now();
+    let base_url = options.base_url.trim_end_matches('/');
+
+    let previous = options.previous.as_ref();
+    let version = options.version.unwrap_or_else(|| previous.map_or(1, |p| p.version + 1));
+    if let Some(previous) = previous.filter(|p| p.version >= version) {
+        return Err(format!("Version {} is not newer than the previous hash file ({})", version, previous.version));
+    }
+    info!("Generating version {}", version);
+
+    let artifacts_path = options.output.join(COMPRESSED_ARTIFACTS_DIR);
+    if let Some(format) = options.compression {
+        info!("Writing {:?} artifacts to: {:?}", format, artifacts_path);
+    }
+    // Diffs are made from the previous release's copies before they are replaced
+    let previous_files: HashMap<&str, &FileInfo> = match (previous, options.patch_format) {
+        (Some(previous), Some(format)) => {
+            info!("Writing {:?} patches from version {} to: {:?}", format, previous.version, options.output.join(PATCHES_DIR));
+            previous.files.iter().map(|f| (f.path.as_str(), f)).collect()
+        }
+        _ => HashMap::new(),
+    };
+    let chunk_store_path = options.output.join(CHUNK_STORE_DIR);
+    if options.chunked {
+        info!("Writing chunk store to: {:?}", chunk_store_path);
+    }
+    let files_path = options.output.join(FILES_DIR);
+
+    // Walk the tree once, the list doubles as the progress total. An output
+    // folder inside the game folder is never part of the manifest.
+    let in_place = options.output == options.source;
+    let paths: Vec<PathBuf> = options
+        .rules
+        .game_files(&options.source)
+        .into_iter()
+        .filter(|path| in_place || !path.starts_with(&options.output))
+        .collect();
+    let total_files = paths.len();
+    info!("Total files to process: {}", total_files);
+
+    let processed_files = AtomicUsize::new(0);
+    let total_size = AtomicU64::new(0);
+    let files = Mutex::new(Vec::with_capacity(total_files));
+    let pool = io_thread_pool(options.storage)?;
+
+    pool.install(|| {
+        paths.par_iter().try_for_each(|path| -> Result<(), String> {
+            let relative_path = path
+                .strip_prefix(&options.source)
+                .map_err(|e| e.to_string())?
+                .to_str()
+                .ok_or_else(|| format!("File name is not valid UTF-8: {:?}", path))?
+                .replace('\\', "/");
+            info!("Processing file: {}", relative_path);
+
+            let size = fs::metadata(path).map_err(|e| e.to_string())?.len();
+            let hash = hash_file(path, options.hash_algorithm)?;
+            let url = format!("{}/{}/{}", base_url, FILES_DIR, relative_path);
+            let mut file_info = FileInfo {
+                path: relative_path.clone(),
+                hash,
+                size,
+                url,
+                patches: Vec::new(),
+                compressed: None,
+                chunks: Vec::new(),
+                hash_algorithm: options.hash_algorithm,
+            };
+
+            if let (Some(previous), Some(format), Some(previous_file)) =
+                (previous, options.patch_format, previous_files.get(relative_path.as_str()))
+            {
+                file_info.patches.extend(write_patch(options, previous, previous_file, format, path, &file_info)?);
+            }
+
+            if options.copy_files {
+                publish_file(path, &files_path.join(&relative_path), options.link_files)?;
+            }
+
+            file_info.compressed = match options.compression {
+                Some(format) => {
+                    let artifact = format!("{}.{}", relative_path, format.extension());
+                    let artifact_path = artifacts_path.join(&artifact);
+                    let compressed_size = compress_file(path, &artifact_path, format)?;
+                    // Only advertise variants that actually save bandwidth
+                    if compressed_size < size {
+                        Some(CompressedInfo {
+                            format,
+                            url: format!("{}/{}/{}", base_url, COMPRESSED_ARTIFACTS_DIR, artifact),
+                            size: compressed_size,
+                        })
+                    } else {
+                        fs::remove_file(&artifact_path).map_err(|e| e.to_string())?;
+                        None
+                    }
+                }
+                None => None,
+            };
+
+            if options.chunked {
+                file_info.chunks = write_chunks(path, &chunk_store_path)?;
+            }
+
+            files.lock().unwrap().push(file_info);
+
+            let total_size = total_size.fetch_add(size, Ordering::Relaxed) + size;
+            let processed_files = processed_files.fetch_add(1, Ordering::Relaxed) + 1;
+            progress(&BuildProgress { current_file: &relative_path, processed_files, total_files, total_size })
+        })
+    })?;
+
+    let mut files = files.into_inner().map_err(|e| e.to_string())?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    info!("Generating JSON");
+    let manifest = Manifest {
+        hash_algorithm: options.hash_algorithm,
+        version,
+        parent_version: previous.map(|p| p.version).filter(|&v| v > 0),
+        release_notes: options.release_notes.clone(),
+        files,
+    };
+    fs::create_dir_all(&options.output).map_err(|e| e.to_string())?;
+
+    let mut documents = Vec::new();
+    let mut write_document = |name: String, json: Result<String, serde_json::Error>| -> Result<(), String> {
+        let path = options.output.join(name);
+        fs::write(&path, json.map_err(|e| e.to_string())?).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+        documents.push(path);
+        Ok(())
+    };
+
+    info!("Writing hash file");
+    write_document(MANIFEST_FILE_NAME.to_string(), serde_json::to_string(&manifest))?;
+    write_document(VERSION_FILE_NAME.to_string(), serde_json::to_string(&VersionInfo::from(&manifest)))?;
+
+    if let Some(previous) = previous.filter(|p| p.version > 0) {
+        let delta = ManifestDelta::between(previous, &manifest);
+        info!("Writing delta from version {}: {} changed, {} removed", previous.version, delta.files.len(), delta.removed.len());
+        write_document(delta_file_name(previous.version), serde_json::to_string(&delta))?;
+    }
+
+    if let Some(part_size) = options.archive_part_size {
+        let archive = write_archive(
+            &options.source,
+            &options.output,
+            base_url,
+            &manifest.files,
+            version,
+            part_size,
+            options.hash_algorithm,
+        )?;
+        write_document(ARCHIVE_FILE_NAME.to_string(), serde_json::to_string(&archive))?;
+    }
+
+    let summary = BuildSummary {
+        version,
+        processed_files: processed_files.load(Ordering::Relaxed),
+        total_size: total_size.load(Ordering::Relaxed),
+        duration: start_time.elapsed(),
+        documents,
+    };
+    info!("Hash file generation completed in {:?}", summary.duration);
+    info!("Total files processed: {}", summary.processed_files);
+    info!("Total size: {} bytes", summary.total_size);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::ignore_rules::IGNORE_FILE_NAME;
+
+    /// A game folder and an empty publish folder next to it.
+    fn folders(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let folder = std::env::temp_dir().join(format!("teralaunch-builder-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        let (source, output) = (folder.join("game"), folder.join("publish"));
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&output).unwrap();
+        (folder, source, output)
+    }
+
+    fn write_files(source: &Path, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            fs::create_dir_all(source.join(path).parent().unwrap()).unwrap();
+            fs::write(source.join(path), contents).unwrap();
+        }
+    }
+
+    fn options(source: &Path, output: &Path, version: Option<u64>) -> BuildOptions {
+        BuildOptions {
+            rules: IgnoreRules::load(source, None).unwrap(),
+            source: source.to_path_buf(),
+            output: output.to_path_buf(),
+            base_url: "https://files.example.com/".to_string(),
+            copy_files: true,
+            link_files: false,
+            previous: read_manifest(&output.join(MANIFEST_FILE_NAME)).ok(),
+            version,
+            release_notes: String::new(),
+            compression: None,
+            patch_format: None,
+            chunked: false,
+            archive_part_size: None,
+            hash_algorithm: HashAlgorithm::default(),
+            storage: StorageKind::Ssd,
+        }
+    }
+
+    fn paths(files: &[FileInfo]) -> Vec<&str> {
+        files.iter().map(|f| f.path.as_str()).collect()
+    }
+
+    #[test]
+    fn first_release_lays_out_the_publish_tree() {
+        let (folder, source, output) = folders("layout");
+        write_files(&source, &[("Binaries/TERA.exe", "tera"), ("S1Game/S1Data.gpk", "data")]);
+
+        let summary = build_manifest(&options(&source, &output, None), |_| Ok(())).unwrap();
+        assert_eq!((summary.version, summary.processed_files, summary.total_size), (1, 2, 8));
+        assert_eq!(summary.documents, [output.join(MANIFEST_FILE_NAME), output.join(VERSION_FILE_NAME)]);
+
+        let manifest = read_manifest(&output.join(MANIFEST_FILE_NAME)).unwrap();
+        assert_eq!((manifest.version, manifest.parent_version), (1, None));
+        assert_eq!(paths(&manifest.files), ["Binaries/TERA.exe", "S1Game/S1Data.gpk"]);
+        let entry = &manifest.files[1];
+        assert_eq!(entry.url, "https://files.example.com/files/S1Game/S1Data.gpk");
+        assert_eq!(entry.hash, hash_file(&source.join("S1Game/S1Data.gpk"), HashAlgorithm::default()).unwrap());
+        assert_eq!(fs::read_to_string(output.join(FILES_DIR).join("S1Game/S1Data.gpk")).unwrap(), "data");
+
+        let version: VersionInfo = serde_json::from_slice(&fs::read(output.join(VERSION_FILE_NAME)).unwrap()).unwrap();
+        assert_eq!(version.version, 1);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn next_release_writes_the_delta_from_the_previous_one() {
+        let (folder, source, output) = folders("delta");
+        write_files(&source, &[("a.txt", "alpha"), ("b.txt", "bravo"), ("c.txt", "charlie")]);
+        build_manifest(&options(&source, &output, None), |_| Ok(())).unwrap();
+
+        write_files(&source, &[("a.txt", "alpha, changed"), ("d.txt", "delta")]);
+        fs::remove_file(source.join("b.txt")).unwrap();
+        let summary = build_manifest(&options(&source, &output, None), |_| Ok(())).unwrap();
+        assert_eq!(summary.version, 2);
+        assert!(summary.documents.contains(&output.join(delta_file_name(1))));
+
+        let manifest = read_manifest(&output.join(MANIFEST_FILE_NAME)).unwrap();
+        assert_eq!((manifest.version, manifest.parent_version), (2, Some(1)));
+        let delta: ManifestDelta = serde_json::from_slice(&fs::read(output.join(delta_file_name(1))).unwrap()).unwrap();
+        assert_eq!((delta.from_version, delta.version), (1, 2));
+        assert_eq!(paths(&delta.files), ["a.txt", "d.txt"]);
+        assert_eq!(delta.removed, ["b.txt"]);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn versions_not_newer_than_the_previous_release_are_rejected() {
+        let (folder, source, output) = folders("version");
+        write_files(&source, &[("a.txt", "alpha")]);
+        build_manifest(&options(&source, &output, Some(5)), |_| Ok(())).unwrap();
+
+        for version in [4, 5] {
+            let error = build_manifest(&options(&source, &output, Some(version)), |_| Ok(())).err().unwrap();
+            assert!(error.contains("is not newer"), "{}", error);
+        }
+        assert_eq!(read_manifest(&output.join(MANIFEST_FILE_NAME)).unwrap().version, 5);
+        assert_eq!(build_manifest(&options(&source, &output, None), |_| Ok(())).unwrap().version, 6);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn ignored_files_are_left_out() {
+        let (folder, source, output) = folders("ignore");
+        write_files(
+            &source,
+            &[
+                ("S1Game/S1Data.gpk", "data"),
+                ("S1Game/Logs/client.log", "log"),
+                ("S1Game/Config/S1Option.ini", "options"),
+                ("tera_config.ini", "[game]"),
+                ("Mods/mod.gpk", "mod"),
+                (IGNORE_FILE_NAME, "/Mods/\n"),
+            ],
+        );
+
+        build_manifest(&options(&source, &output, None), |_| Ok(())).unwrap();
+        let manifest = read_manifest(&output.join(MANIFEST_FILE_NAME)).unwrap();
+        assert_eq!(paths(&manifest.files), ["S1Game/S1Data.gpk"]);
+        assert!(!output.join(FILES_DIR).join("Mods").exists());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+// Standard library imports
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// Third-party imports
+use log::{info, warn};
+
+use super::chunks::{chunk_file, verify_chunk, ChunkInfo};
+use super::hash_cache::CachedFileInfo;
+use super::manifest::FileInfo;
+use super::paths::resolve_game_file;
+
+/// Location of a chunk inside a local file.
+#[derive(Debug, Clone)]
+struct ChunkSource {
+    path: PathBuf,
+    offset: u64,
+    size: u64,
+}
+
+/// Index of the chunks already present in the local install.
+#[derive(Default)]
+pub struct ChunkIndex {
+    sources: HashMap<String, ChunkSource>,
+}
+
+impl ChunkIndex {
+    /// Builds the index for an update of `files_to_update`.
+    ///
+    /// Files recorded in the hash cache contribute their chunk lists without being
+    /// read, as long as they were not modified since. The local versions of the
+    /// files about to be updated are chunked from disk, so older versions can be
+    /// reused too.
+    pub fn build(game_path: &Path, cache: Option<&HashMap<String, CachedFileInfo>>, files_to_update: &[FileInfo]) -> Self {
+        let mut index = ChunkIndex::default();
+
+        for (path, cached) in cache.into_iter().flatten() {
+            if cached.chunks.is_empty() {
+                continue;
+            }
+            let local_path = game_path.join(path);
+            let unchanged = fs::metadata(&local_path)
+                .map(|metadata| cached.matches(&metadata))
+                .unwrap_or(false);
+            if unchanged {
+                index.add_file(&local_path, &cached.chunks);
+            }
+        }
+
+        for file_info in files_to_update.iter().filter(|f| !f.chunks.is_empty()) {
+            let local_path = match resolve_game_file(game_path, &file_info.path) {
+                Ok(local_path) if local_path.is_file() => local_path,
+                _ => continue,
+            };
+            match chunk_file(&local_path) {
+                Ok(chunks) => index.add_file(&local_path, &chunks),
+                Err(e) => warn!("Failed to chunk {:?}: {}", local_path, e),
+            }
+        }
+
+        info!("Chunk index built with {} local chunk(s)", index.sources.len());
+        index
+    }
+
+    /// Adds the chunks of a local file, keeping already known sources.
+    fn add_file(&mut self, path: &Path, chunks: &[ChunkInfo]) {
+        let mut offset = 0;
+        for chunk in chunks {
+            self.sources.entry(chunk.hash.clone()).or_insert_with(|| ChunkSource {
+                path: path.to_path_buf(),
+                offset,
+                size: chunk.size,
+            });
+            offset += chunk.size;
+        }
+    }
+
+    /// Reads a chunk from the local install.
+    ///
+    /// # Returns
+    ///
+    /// The chunk contents, or `None` if the chunk is unknown or the source file
+    /// no longer holds it.
+    pub fn read_chunk(&self, chunk: &ChunkInfo) -> Option<Vec<u8>> {
+        let source = self.sources.get(&chunk.hash)?;
+        if source.size != chunk.size {
+            return None;
+        }
+
+        let mut file = File::open(&source.path).ok()?;
+        file.seek(SeekFrom::Start(source.offset)).ok()?;
+        let mut data = vec![0; source.size as usize];
+        file.read_exact(&mut data).ok()?;
+
+        // The source may have been replaced since the index was built
+        verify_chunk(chunk, &data).then_some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::hashing::{hash_file, HashAlgorithm};
+    use rand::Rng;
+
+    /// `size` bytes of incompressible data.
+    fn game_data(size: usize) -> Vec<u8> {
+        let mut data = vec![0; size];
+        rand::thread_rng().fill(&mut data[..]);
+        data
+    }
+
+    /// A game folder holding `contents` as `S1Data.gpk`.
+    fn game_folder(name: &str, contents: &[u8]) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("teralaunch-chunk-index-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("S1Data.gpk"), contents).unwrap();
+        folder
+    }
+
+    /// The manifest entry of a new release of `S1Data.gpk`.
+    fn release(folder: &Path, contents: &[u8]) -> FileInfo {
+        let path = folder.join("release.gpk");
+        fs::write(&path, contents).unwrap();
+        let file_info = FileInfo {
+            path: "S1Data.gpk".to_string(),
+            hash: hash_file(&path, HashAlgorithm::Sha256).unwrap(),
+            size: contents.len() as u64,
+            url: "http://localhost/files/S1Data.gpk".to_string(),
+            patches: Vec::new(),
+            compressed: None,
+            chunks: chunk_file(&path).unwrap(),
+            hash_algorithm: HashAlgorithm::Sha256,
+        };
+        fs::remove_file(&path).unwrap();
+        file_info
+    }
+
+    #[test]
+    fn unchanged_chunks_of_the_local_version_are_reused() {
+        let old = game_data(6 * 1024 * 1024);
+        let mut new = old.clone();
+        new[3 * 1024 * 1024..3 * 1024 * 1024 + 4096].fill(0);
+        let folder = game_folder("update", &old);
+        let file_info = release(&folder, &new);
+
+        let index = ChunkIndex::build(&folder, None, std::slice::from_ref(&file_info));
+        let mut offset = 0;
+        let mut reused = 0;
+        for chunk in &file_info.chunks {
+            if let Some(data) = index.read_chunk(chunk) {
+                assert!(data[..] == new[offset..offset + chunk.size as usize]);
+                reused += 1;
+            }
+            offset += chunk.size as usize;
+        }
+        assert!(reused > 0 && reused < file_info.chunks.len(), "{} of {}", reused, file_info.chunks.len());
+
+        // Chunks are checked again when read, the source may have changed since
+        fs::write(folder.join("S1Data.gpk"), game_data(1024)).unwrap();
+        assert!(file_info.chunks.iter().all(|chunk| index.read_chunk(chunk).is_none()));
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn cached_chunk_lists_are_used_while_the_file_is_unchanged() {
+        let contents = game_data(2 * 1024 * 1024);
+        let folder = game_folder("cache", &contents);
+        let local_path = folder.join("S1Data.gpk");
+        let chunks = chunk_file(&local_path).unwrap();
+        let cached = |path: &Path| {
+            let metadata = fs::metadata(path).unwrap();
+            let hash = hash_file(path, HashAlgorithm::Sha256).unwrap();
+            CachedFileInfo::new(hash, HashAlgorithm::Sha256, &metadata, chunks.clone()).unwrap()
+        };
+
+        let cache = HashMap::from([("S1Data.gpk".to_string(), cached(&local_path))]);
+        let index = ChunkIndex::build(&folder, Some(&cache), &[]);
+        assert!(chunks.iter().all(|chunk| index.read_chunk(chunk).is_some()));
+
+        // A file modified since it was cached is not trusted
+        fs::write(&local_path, &contents[..contents.len() - 1]).unwrap();
+        let index = ChunkIndex::build(&folder, Some(&cache), &[]);
+        assert!(chunks.iter().all(|chunk| index.read_chunk(chunk).is_none()));
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
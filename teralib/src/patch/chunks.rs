@@ -0,0 +1,202 @@
+// Standard library imports
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+// Third-party imports
+use fastcdc::v2020::{FastCDC, StreamCDC};
+use memmap2::Mmap;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Content-defined chunking parameters. The generator and the updater must
+/// agree on them, otherwise local files are cut at different boundaries and
+/// no chunks can be reused.
+pub const MIN_CHUNK_SIZE: u32 = 256 * 1024;
+pub const AVG_CHUNK_SIZE: u32 = 1024 * 1024;
+pub const MAX_CHUNK_SIZE: u32 = 4 * 1024 * 1024;
+
+/// Folder of the chunk store, published under `<FILE_SERVER_URL>/chunks/`.
+pub const CHUNK_STORE_DIR: &str = "chunks";
+
+/// Length of a chunk hash, a hex encoded SHA-256.
+const CHUNK_HASH_LEN: usize = 64;
+
+/// A content-defined chunk of a file. Chunks are listed in file order, so the
+/// offset of a chunk is the sum of the sizes before it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// SHA-256 of the chunk contents, lowercase hex.
+    #[serde(deserialize_with = "deserialize_chunk_hash")]
+    pub hash: String,
+    pub size: u64,
+}
+
+/// Chunk hashes become store paths and URLs, so anything but a lowercase
+/// hex SHA-256 is refused when the manifest is parsed.
+fn deserialize_chunk_hash<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let hash = String::deserialize(deserializer)?;
+    if hash.len() == CHUNK_HASH_LEN && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        Ok(hash)
+    } else {
+        Err(serde::de::Error::custom(format!("invalid chunk hash: {:?}", hash)))
+    }
+}
+
+/// Returns the store path of a chunk relative to the store root, e.g. `ab/abcdef...`.
+pub fn chunk_store_path(hash: &str) -> String {
+    format!("{}/{}", hash.get(..2).unwrap_or(hash), hash)
+}
+
+/// Returns the download URL of a chunk on the given file server.
+pub fn chunk_url(file_server_url: &str, hash: &str) -> String {
+    format!("{}/{}/{}", file_server_url.trim_end_matches('/'), CHUNK_STORE_DIR, chunk_store_path(hash))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Splits a local file into chunks without loading it into memory.
+pub fn chunk_file(path: &Path) -> Result<Vec<ChunkInfo>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let chunker = StreamCDC::new(BufReader::new(file), MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE);
+
+    chunker
+        .map(|chunk| {
+            let chunk = chunk.map_err(|e| format!("Failed to chunk {:?}: {}", path, e))?;
+            Ok(ChunkInfo { hash: sha256_hex(&chunk.data), size: chunk.length as u64 })
+        })
+        .collect()
+}
+
+/// Splits a file into chunks and writes the chunks missing from the store.
+///
+/// Used by the hash file generator; chunks shared between files are stored once.
+/// The file is memory mapped, so large packages are not read into memory.
+pub fn write_chunks(path: &Path, store_path: &Path) -> Result<Vec<ChunkInfo>, String> {
+    let mut chunks = Vec::new();
+
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    if file.metadata().map_err(|e| e.to_string())?.len() == 0 {
+        return Ok(chunks);
+    }
+    // SAFETY: the mapping is read only and the generator does not modify game files.
+    let contents = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to map file: {}", e))?;
+    let contents = &contents[..];
+
+    for chunk in FastCDC::new(contents, MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE) {
+        let data = &contents[chunk.offset..chunk.offset + chunk.length];
+        let hash = sha256_hex(data);
+        let chunk_path = store_path.join(chunk_store_path(&hash));
+
+        if let Some(parent) = chunk_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        // Another file may have written the same chunk already
+        match OpenOptions::new().write(true).create_new(true).open(&chunk_path) {
+            Ok(mut file) => file.write_all(data).map_err(|e| e.to_string())?,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(format!("Failed to write chunk {:?}: {}", chunk_path, e)),
+        }
+
+        chunks.push(ChunkInfo { hash, size: chunk.length as u64 });
+    }
+
+    Ok(chunks)
+}
+
+/// Checks chunk contents against the manifest.
+pub fn verify_chunk(chunk: &ChunkInfo, data: &[u8]) -> bool {
+    data.len() as u64 == chunk.size && sha256_hex(data) == chunk.hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    /// A folder holding `size` bytes of incompressible game data in `S1Data.gpk`.
+    fn game_file(name: &str, size: usize) -> (PathBuf, Vec<u8>) {
+        let folder = std::env::temp_dir().join(format!("teralaunch-chunks-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        let mut contents = vec![0; size];
+        rand::thread_rng().fill(&mut contents[..]);
+        fs::write(folder.join("S1Data.gpk"), &contents).unwrap();
+        (folder, contents)
+    }
+
+    #[test]
+    fn generator_and_updater_cut_at_the_same_boundaries() {
+        let (folder, contents) = game_file("boundaries", 6 * 1024 * 1024);
+        let store = folder.join(CHUNK_STORE_DIR);
+
+        let written = write_chunks(&folder.join("S1Data.gpk"), &store).unwrap();
+        assert_eq!(chunk_file(&folder.join("S1Data.gpk")).unwrap(), written);
+        assert!(written.len() > 1);
+        assert_eq!(written.iter().map(|c| c.size).sum::<u64>(), contents.len() as u64);
+        for chunk in &written[..written.len() - 1] {
+            assert!((MIN_CHUNK_SIZE as u64..=MAX_CHUNK_SIZE as u64).contains(&chunk.size), "{}", chunk.size);
+        }
+
+        let mut offset = 0;
+        for chunk in &written {
+            let stored = fs::read(store.join(chunk_store_path(&chunk.hash))).unwrap();
+            assert!(stored[..] == contents[offset..offset + chunk.size as usize]);
+            assert!(verify_chunk(chunk, &stored));
+            offset += chunk.size as usize;
+        }
+
+        // A second file with the same contents adds nothing to the store
+        fs::copy(folder.join("S1Data.gpk"), folder.join("S1Copy.gpk")).unwrap();
+        let stored_before = walkdir::WalkDir::new(&store).into_iter().count();
+        assert_eq!(write_chunks(&folder.join("S1Copy.gpk"), &store).unwrap(), written);
+        assert_eq!(walkdir::WalkDir::new(&store).into_iter().count(), stored_before);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn empty_files_have_no_chunks() {
+        let (folder, _) = game_file("empty", 0);
+        assert!(chunk_file(&folder.join("S1Data.gpk")).unwrap().is_empty());
+        assert!(write_chunks(&folder.join("S1Data.gpk"), &folder.join(CHUNK_STORE_DIR)).unwrap().is_empty());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn chunks_are_verified_by_size_and_hash() {
+        let chunk = ChunkInfo { hash: sha256_hex(b"chunk"), size: 5 };
+        assert!(verify_chunk(&chunk, b"chunk"));
+        assert!(!verify_chunk(&chunk, b"CHUNK"));
+        assert!(!verify_chunk(&ChunkInfo { size: 4, ..chunk }, b"chunk"));
+    }
+
+    #[test]
+    fn only_lowercase_sha256_hashes_are_accepted() {
+        let hash = sha256_hex(b"chunk");
+        let parsed: ChunkInfo = serde_json::from_value(json!({ "hash": hash, "size": 5 })).unwrap();
+        assert_eq!(chunk_url("https://files.example.com/", &parsed.hash), format!("https://files.example.com/chunks/{}/{}", &hash[..2], hash));
+
+        for invalid in [
+            hash.to_uppercase(),
+            hash[..63].to_string(),
+            format!("{}0", hash),
+            format!("../{}", &hash[3..]),
+            format!("é{}", &hash[2..]),
+            String::new(),
+        ] {
+            let parsed = serde_json::from_value::<ChunkInfo>(json!({ "hash": invalid, "size": 5 }));
+            assert!(parsed.is_err(), "{:?}", invalid);
+        }
+        // A cut inside a multi-byte character used to panic
+        assert_eq!(chunk_store_path("aé"), "aé/aé");
+    }
+}
@@ -0,0 +1,235 @@
+// Standard library imports
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Third-party imports
+use flate2::write::{GzDecoder, GzEncoder};
+use serde::{Deserialize, Serialize};
+
+use super::hashing::{HashAlgorithm, StreamHasher};
+
+/// Folder next to the hash file that receives the compressed artifacts,
+/// published under `<FILE_SERVER_URL>/compressed/`.
+pub const COMPRESSED_ARTIFACTS_DIR: &str = "compressed";
+
+/// zstd level used for published artifacts, favouring size over packing speed.
+const ZSTD_LEVEL: i32 = 19;
+
+/// Transport compression of a manifest entry.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    /// File extension of the compressed artifact.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Zstd => "zst",
+            Compression::Gzip => "gz",
+        }
+    }
+}
+
+/// Compressed variant of a file advertised by the hash file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressedInfo {
+    pub format: Compression,
+    /// Download URL of the compressed artifact.
+    pub url: String,
+    /// Size of the compressed artifact in bytes.
+    pub size: u64,
+}
+
+/// Receives the decoded bytes of a `StreamDecoder`, hashing and counting them.
+struct DecodedOutput {
+    pending: Vec<u8>,
+    hasher: StreamHasher,
+    written: u64,
+    size: u64,
+}
+
+impl Write for DecodedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written + buf.len() as u64 > self.size {
+            return Err(io::Error::other(format!("output exceeds the expected {} bytes", self.size)));
+        }
+        self.hasher.update(buf);
+        self.pending.extend_from_slice(buf);
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Decoder {
+    Zstd(zstd::stream::write::Decoder<'static, DecodedOutput>),
+    Gzip(GzDecoder<DecodedOutput>),
+}
+
+/// Decodes a compressed artifact as its bytes arrive, so a download is
+/// unpacked and hashed in the same pass.
+///
+/// Output beyond the expected size of the file is refused as soon as it is
+/// produced, so a malicious artifact cannot fill the disk.
+pub struct StreamDecoder {
+    decoder: Decoder,
+}
+
+impl StreamDecoder {
+    /// Creates a decoder for an artifact of a file of `size` bytes.
+    pub fn new(format: Compression, algorithm: HashAlgorithm, size: u64) -> Result<Self, String> {
+        let output = DecodedOutput { pending: Vec::new(), hasher: StreamHasher::new(algorithm), written: 0, size };
+        let decoder = match format {
+            Compression::Zstd => Decoder::Zstd(zstd::stream::write::Decoder::new(output).map_err(|e| e.to_string())?),
+            Compression::Gzip => Decoder::Gzip(GzDecoder::new(output)),
+        };
+        Ok(StreamDecoder { decoder })
+    }
+
+    /// Decodes the next compressed bytes.
+    ///
+    /// # Returns
+    ///
+    /// The decoded bytes produced so far.
+    pub fn decode(&mut self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let output = match &mut self.decoder {
+            Decoder::Zstd(decoder) => decoder.write_all(data).map(|_| decoder.get_mut()),
+            Decoder::Gzip(decoder) => decoder.write_all(data).map(|_| decoder.get_mut()),
+        }
+        .map_err(|e| format!("Failed to decompress file: {}", e))?;
+        Ok(std::mem::take(&mut output.pending))
+    }
+
+    /// Ends the stream once all compressed bytes were decoded.
+    ///
+    /// # Returns
+    ///
+    /// The last decoded bytes and the hash of the whole output.
+    pub fn finish(self) -> Result<(Vec<u8>, String), String> {
+        let output = match self.decoder {
+            Decoder::Zstd(mut decoder) => decoder.flush().map(|_| decoder.into_inner()),
+            Decoder::Gzip(decoder) => decoder.finish(),
+        }
+        .map_err(|e| format!("Failed to decompress file: {}", e))?;
+        if output.written != output.size {
+            return Err(format!("Decompressed output is truncated: {} of {} bytes", output.written, output.size));
+        }
+        Ok((output.pending, output.hasher.finalize()))
+    }
+}
+
+/// Compresses `source` into `destination`, creating parent directories as needed.
+///
+/// # Returns
+///
+/// The size of the compressed artifact.
+pub fn compress_file(source: &Path, destination: &Path, format: Compression) -> Result<u64, String> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    let mut input = BufReader::new(File::open(source).map_err(|e| format!("Failed to open file: {}", e))?);
+    let output = File::create(destination).map_err(|e| format!("Failed to create file: {}", e))?;
+    let output = BufWriter::new(output);
+
+    let output = match format {
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(output, ZSTD_LEVEL).map_err(|e| e.to_string())?;
+            io::copy(&mut input, &mut encoder).map_err(|e| e.to_string())?;
+            encoder.finish().map_err(|e| e.to_string())?
+        }
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(output, flate2::Compression::best());
+            io::copy(&mut input, &mut encoder).map_err(|e| e.to_string())?;
+            encoder.finish().map_err(|e| e.to_string())?
+        }
+    };
+
+    let file = output.into_inner().map_err(|e| e.to_string())?;
+    Ok(file.metadata().map_err(|e| e.to_string())?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::hashing::hash_file;
+    use std::path::PathBuf;
+
+    /// A file compressed in `format` next to it.
+    fn compressed(name: &str, format: Compression) -> (PathBuf, Vec<u8>, Vec<u8>) {
+        let folder = std::env::temp_dir().join(format!("teralaunch-compression-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+
+        let contents: Vec<u8> = (0..200_000u32).flat_map(|i| (i % 1000).to_le_bytes()).collect();
+        let source = folder.join("S1Data.gpk");
+        std::fs::write(&source, &contents).unwrap();
+        let artifact = folder.join(format!("S1Data.gpk.{}", format.extension()));
+        compress_file(&source, &artifact, format).unwrap();
+        let artifact = std::fs::read(&artifact).unwrap();
+        (folder, contents, artifact)
+    }
+
+    #[test]
+    fn artifacts_round_trip_in_both_formats() {
+        for format in [Compression::Zstd, Compression::Gzip] {
+            let (folder, contents, artifact) = compressed(&format!("round-trip-{}", format.extension()), format);
+            assert!(artifact.len() < contents.len() / 10, "{:?}", format);
+
+            let mut decoder = StreamDecoder::new(format, HashAlgorithm::Sha256, contents.len() as u64).unwrap();
+            let mut decoded = Vec::new();
+            for block in artifact.chunks(4096) {
+                decoded.extend(decoder.decode(block).unwrap());
+            }
+            let (rest, hash) = decoder.finish().unwrap();
+            decoded.extend(rest);
+
+            assert!(decoded == contents, "{:?}", format);
+            assert_eq!(hash, hash_file(&folder.join("S1Data.gpk"), HashAlgorithm::Sha256).unwrap());
+            std::fs::remove_dir_all(&folder).unwrap();
+        }
+    }
+
+    #[test]
+    fn output_beyond_the_expected_size_is_refused() {
+        for format in [Compression::Zstd, Compression::Gzip] {
+            let (folder, contents, artifact) = compressed(&format!("oversize-{}", format.extension()), format);
+
+            let mut decoder = StreamDecoder::new(format, HashAlgorithm::Sha256, contents.len() as u64 - 1).unwrap();
+            let mut decoded = 0;
+            let mut result = Ok(());
+            for block in artifact.chunks(4096) {
+                match decoder.decode(block) {
+                    Ok(output) => decoded += output.len(),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            let error = result.err().or_else(|| decoder.finish().err()).unwrap();
+            assert!(error.contains("exceeds the expected"), "{:?}: {}", format, error);
+            assert!(decoded < contents.len(), "{:?}", format);
+            std::fs::remove_dir_all(&folder).unwrap();
+        }
+    }
+
+    #[test]
+    fn truncated_artifacts_are_refused() {
+        for format in [Compression::Zstd, Compression::Gzip] {
+            let (folder, contents, artifact) = compressed(&format!("truncated-{}", format.extension()), format);
+
+            let mut decoder = StreamDecoder::new(format, HashAlgorithm::Sha256, contents.len() as u64).unwrap();
+            decoder.decode(&artifact[..artifact.len() / 2]).unwrap();
+            assert!(decoder.finish().is_err(), "{:?}", format);
+            std::fs::remove_dir_all(&folder).unwrap();
+        }
+    }
+}
@@ -0,0 +1,334 @@
+// Standard library imports
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Third-party imports
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use super::manifest::FileInfo;
+
+/// Folder next to the hash file that receives the binary diffs,
+/// published under `<FILE_SERVER_URL>/patches/`.
+pub const PATCHES_DIR: &str = "patches";
+
+/// Largest zstd window accepted when applying a patch, large enough for
+/// `--patch-from` diffs of multi-GB packages.
+const ZSTD_WINDOW_LOG_MAX: u32 = 31;
+
+/// zstd level used for published diffs, favouring size over packing speed.
+const ZSTD_LEVEL: i32 = 19;
+
+/// Size of the blocks a bsdiff patch is applied in.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Binary diff formats understood by the updater.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchFormat {
+    /// bsdiff patch as produced by the `bsdiff` crate, zstd compressed since its
+    /// control and data blocks are written uncompressed.
+    Bsdiff,
+    /// zstd frame compressed with the old file as reference prefix (`zstd --patch-from`).
+    Zstd,
+}
+
+impl PatchFormat {
+    /// File extension of the published diff.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PatchFormat::Bsdiff => "bsdiff",
+            PatchFormat::Zstd => "zst",
+        }
+    }
+}
+
+/// A binary diff turning one version of a file into another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchInfo {
+    /// Hash of the local file the patch applies to.
+    pub from_hash: String,
+    /// Hash of the file produced by the patch.
+    pub to_hash: String,
+    /// Download URL of the diff.
+    pub url: String,
+    /// Size of the diff in bytes.
+    pub size: u64,
+    /// Hash of the diff itself, checked before it is applied.
+    pub hash: String,
+    pub format: PatchFormat,
+}
+
+/// Returns the patch that upgrades a local file with hash `local_hash` to the
+/// manifest version, if the manifest has one.
+pub fn select_patch<'a>(file_info: &'a FileInfo, local_hash: &str) -> Option<&'a PatchInfo> {
+    file_info
+        .patches
+        .iter()
+        .find(|patch| patch.from_hash == local_hash && patch.to_hash == file_info.hash)
+}
+
+fn map_file(path: &Path) -> Result<Mmap, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    // SAFETY: the mapping is read only and the files are not modified while a
+    // patch is created or applied.
+    unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to map file: {}", e))
+}
+
+/// Writes a binary diff turning `old_path` into `new_path` to `patch_path`.
+///
+/// bsdiff builds a suffix array of the old file in memory, zstd only keeps
+/// its window, so zstd is the better choice for multi-GB packages.
+///
+/// # Returns
+///
+/// The size of the diff.
+pub fn create_patch(old_path: &Path, new_path: &Path, format: PatchFormat, patch_path: &Path) -> Result<u64, String> {
+    let old = map_file(old_path)?;
+    let new = map_file(new_path)?;
+
+    if let Some(parent) = patch_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let output = File::create(patch_path).map_err(|e| format!("Failed to create patch: {}", e))?;
+    let mut output = BufWriter::new(output);
+
+    match format {
+        PatchFormat::Bsdiff => {
+            let mut encoder = zstd::stream::write::Encoder::new(&mut output, ZSTD_LEVEL)
+                .map_err(|e| format!("Failed to create bsdiff patch: {}", e))?;
+            bsdiff::diff(&old, &new, &mut encoder).map_err(|e| format!("Failed to create bsdiff patch: {}", e))?;
+            encoder.finish().map_err(|e| format!("Failed to create bsdiff patch: {}", e))?;
+        }
+        PatchFormat::Zstd => {
+            // The window has to reach back over the whole old file, like `--patch-from`
+            let window_log = (u64::BITS - (old.len().max(new.len()) as u64).leading_zeros()).clamp(10, ZSTD_WINDOW_LOG_MAX);
+            let mut encoder = zstd::stream::write::Encoder::with_ref_prefix(&mut output, ZSTD_LEVEL, &old)
+                .map_err(|e| format!("Failed to create zstd patch: {}", e))?;
+            encoder.window_log(window_log).map_err(|e| e.to_string())?;
+            encoder.long_distance_matching(true).map_err(|e| e.to_string())?;
+            encoder.write_all(&new).map_err(|e| format!("Failed to create zstd patch: {}", e))?;
+            encoder.finish().map_err(|e| format!("Failed to create zstd patch: {}", e))?;
+        }
+    }
+
+    let file = output.into_inner().map_err(|e| e.to_string())?;
+    Ok(file.metadata().map_err(|e| e.to_string())?.len())
+}
+
+/// Applies a binary diff to `old_path` and writes the result to `output_path`.
+///
+/// The old file is memory mapped and the new file is streamed to disk, so
+/// large packages are never held in memory. A patch producing more than
+/// `max_size` bytes is refused before it can fill the disk.
+pub fn apply_patch(
+    old_path: &Path,
+    patch_path: &Path,
+    format: PatchFormat,
+    output_path: &Path,
+    max_size: u64,
+) -> Result<(), String> {
+    let old = map_file(old_path)?;
+
+    let patch_file = File::open(patch_path).map_err(|e| format!("Failed to open patch: {}", e))?;
+    let patch = BufReader::new(patch_file);
+
+    let output = File::create(output_path).map_err(|e| format!("Failed to create file: {}", e))?;
+    let mut output = BufWriter::new(output);
+
+    match format {
+        PatchFormat::Bsdiff => {
+            let mut decoder = zstd::stream::read::Decoder::with_buffer(patch)
+                .map_err(|e| format!("Failed to open bsdiff patch: {}", e))?;
+            apply_bsdiff(&old, &mut decoder, &mut output, max_size)?
+        }
+        PatchFormat::Zstd => {
+            let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(patch, &old)
+                .map_err(|e| format!("Failed to open zstd patch: {}", e))?;
+            decoder
+                .window_log_max(ZSTD_WINDOW_LOG_MAX)
+                .map_err(|e| e.to_string())?;
+            let written = io::copy(&mut decoder.take(max_size.saturating_add(1)), &mut output)
+                .map_err(|e| format!("Failed to apply zstd patch: {}", e))?;
+            if written > max_size {
+                return Err(format!("Patch output exceeds the expected {} bytes", max_size));
+            }
+        }
+    }
+
+    output
+        .into_inner()
+        .map_err(|e| e.to_string())?
+        .sync_all()
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Applies a patch in the format of the `bsdiff` crate block by block.
+///
+/// Each block is a control triple of little-endian integers (bytes to add to
+/// the old file, bytes to copy verbatim, seek in the old file) followed by
+/// the diff and literal bytes.
+fn apply_bsdiff<R: Read, W: Write>(old: &[u8], patch: &mut R, output: &mut W, max_size: u64) -> Result<(), String> {
+    let invalid = |what: &str| format!("Invalid bsdiff patch: {}", what);
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut old_position: usize = 0;
+    let mut written: u64 = 0;
+
+    let mut control = [0; 24];
+    while read_control(patch, &mut control).map_err(|e| format!("Failed to read patch: {}", e))? {
+        let mix_len = u64::from_le_bytes(control[0..8].try_into().unwrap());
+        let copy_len = u64::from_le_bytes(control[8..16].try_into().unwrap());
+        let seek = sign_magnitude(control[16..24].try_into().unwrap());
+
+        written = written
+            .checked_add(mix_len)
+            .and_then(|n| n.checked_add(copy_len))
+            .filter(|&n| n <= max_size)
+            .ok_or_else(|| format!("Patch output exceeds the expected {} bytes", max_size))?;
+
+        // Diff bytes are added to the old file
+        let mut remaining = mix_len;
+        while remaining > 0 {
+            let len = remaining.min(BUFFER_SIZE as u64) as usize;
+            let block = &mut buffer[..len];
+            patch.read_exact(block).map_err(|e| format!("Failed to read patch: {}", e))?;
+            let old_block = old_position
+                .checked_add(len)
+                .and_then(|end| old.get(old_position..end))
+                .ok_or_else(|| invalid("reads past the old file"))?;
+            for (byte, old_byte) in block.iter_mut().zip(old_block) {
+                *byte = byte.wrapping_add(*old_byte);
+            }
+            output.write_all(block).map_err(|e| e.to_string())?;
+            old_position += len;
+            remaining -= len as u64;
+        }
+
+        // Literal bytes are copied verbatim
+        let copied = io::copy(&mut patch.by_ref().take(copy_len), output).map_err(|e| e.to_string())?;
+        if copied != copy_len {
+            return Err(invalid("truncated"));
+        }
+
+        old_position = i64::try_from(old_position)
+            .ok()
+            .and_then(|position| position.checked_add(seek))
+            .and_then(|position| usize::try_from(position).ok())
+            .ok_or_else(|| invalid("seeks before the old file"))?;
+    }
+    Ok(())
+}
+
+/// Reads the next control triple, `false` at the end of the patch.
+fn read_control<R: Read>(patch: &mut R, control: &mut [u8; 24]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < control.len() {
+        match patch.read(&mut control[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Decodes the sign-magnitude seek offsets of bsdiff.
+fn sign_magnitude(bytes: [u8; 8]) -> i64 {
+    let value = i64::from_le_bytes(bytes);
+    if value & i64::MIN == 0 {
+        value
+    } else {
+        -(value & i64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// An old and a new release of a file sharing most of their contents.
+    fn releases(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let folder = std::env::temp_dir().join(format!("teralaunch-delta-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        let old: Vec<u8> = (0..300_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let mut new = old.clone();
+        new[1000..1100].fill(0xaa);
+        new.splice(150_000..150_000, b"inserted in the new release".iter().copied());
+        new.truncate(290_000);
+
+        fs::write(folder.join("old.gpk"), &old).unwrap();
+        fs::write(folder.join("new.gpk"), &new).unwrap();
+        (folder.join("old.gpk"), folder.join("new.gpk"), folder)
+    }
+
+    #[test]
+    fn patches_round_trip_in_both_formats() {
+        let (old_path, new_path, folder) = releases("round-trip");
+        let new = fs::read(&new_path).unwrap();
+
+        for format in [PatchFormat::Bsdiff, PatchFormat::Zstd] {
+            let patch_path = folder.join(format!("patch.{}", format.extension()));
+            let size = create_patch(&old_path, &new_path, format, &patch_path).unwrap();
+            assert!(size < new.len() as u64 / 10, "{:?} patch of {} bytes", format, size);
+
+            let output_path = folder.join("patched.gpk");
+            apply_patch(&old_path, &patch_path, format, &output_path, new.len() as u64).unwrap();
+            assert!(fs::read(&output_path).unwrap() == new, "{:?}", format);
+        }
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn patches_writing_more_than_the_expected_size_are_refused() {
+        let (old_path, new_path, folder) = releases("oversize");
+        let new_size = fs::metadata(&new_path).unwrap().len();
+
+        for format in [PatchFormat::Bsdiff, PatchFormat::Zstd] {
+            let patch_path = folder.join(format!("patch.{}", format.extension()));
+            create_patch(&old_path, &new_path, format, &patch_path).unwrap();
+
+            let output_path = folder.join("patched.gpk");
+            let error = apply_patch(&old_path, &patch_path, format, &output_path, new_size - 1).unwrap_err();
+            assert!(error.contains("exceeds the expected"), "{:?}: {}", format, error);
+            assert!(fs::metadata(&output_path).unwrap().len() <= new_size);
+        }
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn corrupt_patches_are_refused() {
+        let (old_path, new_path, folder) = releases("corrupt");
+        let new_size = fs::metadata(&new_path).unwrap().len();
+
+        for format in [PatchFormat::Bsdiff, PatchFormat::Zstd] {
+            let patch_path = folder.join(format!("patch.{}", format.extension()));
+            create_patch(&old_path, &new_path, format, &patch_path).unwrap();
+            let patch = fs::read(&patch_path).unwrap();
+            fs::write(&patch_path, &patch[..patch.len() / 2]).unwrap();
+
+            let output_path = folder.join("patched.gpk");
+            assert!(apply_patch(&old_path, &patch_path, format, &output_path, new_size).is_err(), "{:?}", format);
+        }
+
+        // A bsdiff block reading past the end of the old file
+        let mut patch = Vec::new();
+        patch.extend_from_slice(&(400_000u64).to_le_bytes());
+        patch.extend_from_slice(&0u64.to_le_bytes());
+        patch.extend_from_slice(&0u64.to_le_bytes());
+        patch.resize(24 + 400_000, 0);
+        fs::write(folder.join("past-end.bsdiff"), zstd::encode_all(&patch[..], 0).unwrap()).unwrap();
+        let error = apply_patch(&old_path, &folder.join("past-end.bsdiff"), PatchFormat::Bsdiff, &folder.join("patched.gpk"), 400_000);
+        assert!(error.unwrap_err().contains("reads past the old file"));
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
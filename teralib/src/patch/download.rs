@@ -0,0 +1,1538 @@
+// Standard library imports
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Third-party imports
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use parking_lot::Mutex;
+use rand::Rng;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::watch;
+
+use super::chunk_index::ChunkIndex;
+use super::chunks::{chunk_url, verify_chunk};
+use super::compression::{Compression, StreamDecoder};
+use super::delta::{apply_patch, select_patch, PatchInfo};
+use super::hashing::{hash_file, StreamHasher};
+use super::manifest::FileInfo;
+use super::paths::resolve_game_file;
+use crate::services::progress::{format_bytes, ProgressPayload, ProgressReporter};
+
+/// Interval between two aggregated `download_progress` events.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Delay before the first retry, doubled for every further attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Upper bound for the delay between two attempts.
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Error returned by downloads stopped through `DownloadControl::cancel`.
+pub const CANCELLED: &str = "Update cancelled";
+
+/// User tunable download settings, read from the `[download]` section of tera_config.ini.
+#[derive(Debug, Clone)]
+pub struct DownloadSettings {
+    /// Number of files downloaded at the same time.
+    pub connections: usize,
+    /// Number of retries for a file before it is reported as failed.
+    pub retries: u32,
+    /// Bandwidth cap in KB/s shared by all connections, 0 for unlimited.
+    pub max_speed_kbps: u64,
+    /// Download into the staging area and swap the files in once all of them
+    /// are verified, instead of replacing them one by one.
+    pub staged: bool,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        DownloadSettings { connections: 4, retries: 3, max_speed_kbps: 0, staged: true }
+    }
+}
+
+impl DownloadSettings {
+    /// Returns the delay to wait before retry number `attempt` (starting at 1).
+    ///
+    /// The delay grows exponentially and is jittered, so files that failed
+    /// together do not hit the server again at the same moment.
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        let exponential = RETRY_BASE_DELAY.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+        let capped = exponential.min(RETRY_MAX_DELAY);
+        let jitter = rand::thread_rng().gen_range(0.5..=1.0);
+        capped.mul_f64(jitter)
+    }
+}
+
+/// Token bucket limiting the bandwidth of all download workers together.
+///
+/// The bucket holds at most one second worth of bytes. Workers take tokens for
+/// every chunk they receive and go into debt when the bucket is empty, sleeping
+/// until the debt is paid back.
+pub struct RateLimiter {
+    bytes_per_second: AtomicU64,
+    bucket: Mutex<TokenBucket>,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new() -> Self {
+        RateLimiter {
+            bytes_per_second: AtomicU64::new(0),
+            bucket: Mutex::new(TokenBucket { tokens: 0.0, last_refill: Instant::now() }),
+        }
+    }
+
+    /// Sets the bandwidth cap in KB/s, 0 removes the cap.
+    pub fn set_limit_kbps(&self, kbps: u64) {
+        self.bytes_per_second.store(kbps.saturating_mul(1024), Ordering::Relaxed);
+        let mut bucket = self.bucket.lock();
+        bucket.tokens = 0.0;
+        bucket.last_refill = Instant::now();
+    }
+
+    /// Waits until `bytes` may be passed on without exceeding the cap.
+    pub async fn acquire(&self, bytes: u64) {
+        let rate = self.bytes_per_second.load(Ordering::Relaxed);
+        if rate == 0 {
+            return;
+        }
+
+        let wait = {
+            let mut bucket = self.bucket.lock();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.last_refill).as_secs_f64() * rate as f64;
+            bucket.tokens = (bucket.tokens + refill).min(rate as f64) - bytes as f64;
+            bucket.last_refill = now;
+
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / rate as f64)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Pause, resume and cancel state of the running update, owned by the `PatchService`.
+pub struct DownloadControl {
+    paused: watch::Sender<bool>,
+    cancelled: AtomicBool,
+    limiter: RateLimiter,
+}
+
+impl Default for DownloadControl {
+    fn default() -> Self {
+        DownloadControl::new()
+    }
+}
+
+impl DownloadControl {
+    pub fn new() -> Self {
+        let (paused, _) = watch::channel(false);
+        DownloadControl {
+            paused,
+            cancelled: AtomicBool::new(false),
+            limiter: RateLimiter::new(),
+        }
+    }
+
+    /// Clears the pause and cancel flags before a new update run.
+    pub fn reset(&self, settings: &DownloadSettings) {
+        self.cancelled.store(false, Ordering::SeqCst);
+        self.paused.send_replace(false);
+        self.limiter.set_limit_kbps(settings.max_speed_kbps);
+    }
+
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    /// Stops the update. Workers leave their `.part` files behind for the next run.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        // Wake paused workers so they notice the cancellation
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    /// Blocks while the update is paused.
+    ///
+    /// # Returns
+    ///
+    /// An error once the update has been cancelled.
+    pub async fn checkpoint(&self) -> Result<(), String> {
+        let mut paused = self.paused.subscribe();
+        loop {
+            if self.is_cancelled() {
+                return Err(CANCELLED.to_string());
+            }
+            if !*paused.borrow_and_update() {
+                return Ok(());
+            }
+            if paused.changed().await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Base URLs a manifest URL can be fetched from.
+///
+/// Manifest URLs point to `FILE_SERVER_URL`; every retry moves on to the next
+/// mirror by swapping that base URL.
+pub struct MirrorList {
+    bases: Vec<String>,
+}
+
+impl MirrorList {
+    /// Creates a mirror list from the primary file server and its mirrors.
+    pub fn new(primary: &str, mirrors: Vec<String>) -> Self {
+        let mut bases = vec![primary.trim_end_matches('/').to_string()];
+        bases.extend(mirrors.iter().map(|m| m.trim_end_matches('/').to_string()));
+        bases.dedup();
+        MirrorList { bases }
+    }
+
+    /// Returns the primary base URL.
+    pub fn primary(&self) -> &str {
+        &self.bases[0]
+    }
+
+    /// Returns the URL to use for the given attempt (0 is the first attempt).
+    pub fn url_for(&self, url: &str, attempt: u32) -> String {
+        let primary = &self.bases[0];
+        match url.strip_prefix(primary.as_str()) {
+            Some(rest) => {
+                let base = &self.bases[attempt as usize % self.bases.len()];
+                format!("{}{}", base, rest)
+            }
+            None => url.to_string(),
+        }
+    }
+}
+
+/// Everything a download worker needs for one update run.
+pub struct DownloadSession {
+    /// Shared client, so connections are pooled between files.
+    pub client: Client,
+    pub game_path: PathBuf,
+    /// Folder the files are written to, the game folder or the staging area.
+    pub output_path: PathBuf,
+    pub settings: DownloadSettings,
+    pub mirrors: MirrorList,
+    /// Chunks available in the local install, empty unless the manifest is chunked.
+    pub chunks: Arc<ChunkIndex>,
+}
+
+/// A file that could not be downloaded after all retries.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadFailure {
+    pub path: String,
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// Progress of a download run, shared by all download workers.
+///
+/// Workers only bump counters; a single reporter task turns them into
+/// `download_progress` events so the frontend sees one aggregated stream.
+pub struct DownloadTracker {
+    start_time: Instant,
+    total_bytes: u64,
+    total_files: usize,
+    downloaded_bytes: AtomicU64,
+    started_files: AtomicUsize,
+    current_file: Mutex<String>,
+}
+
+impl DownloadTracker {
+    /// Creates a tracker for `total_files` files of `total_bytes` bytes.
+    ///
+    /// `downloaded_bytes` and `started_files` allow continuing the counters of an
+    /// earlier run, as done by the single file `update_file` command.
+    pub fn new(total_files: usize, total_bytes: u64, downloaded_bytes: u64, started_files: usize) -> Self {
+        DownloadTracker {
+            start_time: Instant::now(),
+            total_bytes,
+            total_files,
+            downloaded_bytes: AtomicU64::new(downloaded_bytes),
+            started_files: AtomicUsize::new(started_files),
+            current_file: Mutex::new(String::new()),
+        }
+    }
+
+    /// Marks a file as being downloaded.
+    pub fn start_file(&self, path: &str) {
+        self.started_files.fetch_add(1, Ordering::SeqCst);
+        *self.current_file.lock() = path.to_string();
+    }
+
+    /// Records bytes written to disk.
+    pub fn add_bytes(&self, bytes: u64) {
+        self.downloaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Takes back bytes of a failed attempt, they are counted again when retried.
+    pub fn remove_bytes(&self, bytes: u64) {
+        self.downloaded_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the number of bytes downloaded so far.
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.downloaded_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Builds a `download_progress` payload from the current counters.
+    pub fn payload(&self, speed: f64) -> ProgressPayload {
+        let downloaded_bytes = self.downloaded_bytes();
+        let progress = if self.total_bytes > 0 {
+            (downloaded_bytes as f64 / self.total_bytes as f64) * 100.0
+        } else {
+            100.0
+        };
+
+        ProgressPayload {
+            file_name: self.current_file.lock().clone(),
+            progress: progress.min(100.0),
+            speed,
+            downloaded_bytes,
+            total_bytes: self.total_bytes,
+            total_files: self.total_files,
+            elapsed_time: self.start_time.elapsed().as_secs_f64(),
+            current_file_index: self.started_files.load(Ordering::SeqCst).min(self.total_files),
+        }
+    }
+}
+
+/// Reports aggregated download progress until the task is aborted.
+pub async fn report_progress(reporter: Arc<dyn ProgressReporter>, tracker: Arc<DownloadTracker>) {
+    let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+    let mut last_bytes = tracker.downloaded_bytes();
+    let mut last_tick = Instant::now();
+
+    loop {
+        interval.tick().await;
+
+        let now = Instant::now();
+        let bytes = tracker.downloaded_bytes();
+        let elapsed = now.duration_since(last_tick).as_secs_f64();
+        let speed = if elapsed > 0.0 { bytes.saturating_sub(last_bytes) as f64 / elapsed } else { 0.0 };
+        last_bytes = bytes;
+        last_tick = now;
+
+        let payload = tracker.payload(speed);
+        debug!("Current file: {}, Download speed: {}/s, Progress: {:.2}%",
+               payload.file_name, format_bytes(speed as u64), payload.progress);
+
+        reporter.download_progress(&payload);
+    }
+}
+
+/// Downloads a single manifest entry, retrying with backoff and rotating mirrors.
+///
+/// Binary diffs and chunks from the local install are tried before the full file.
+///
+/// # Returns
+///
+/// The size of the file on disk, or a `DownloadFailure` once all retries are used up.
+pub async fn download_file_with_retry(
+    session: &DownloadSession,
+    file_info: &FileInfo,
+    tracker: &DownloadTracker,
+    control: &DownloadControl,
+) -> Result<u64, DownloadFailure> {
+    let (client, output_path, settings, mirrors) =
+        (&session.client, session.output_path.as_path(), &session.settings, &session.mirrors);
+    let failure = |error: String, attempts: u32| DownloadFailure {
+        path: file_info.path.clone(),
+        error,
+        attempts,
+    };
+
+    // Files still queued when the update is cancelled are not started at all
+    control.checkpoint().await.map_err(|e| failure(e, 0))?;
+    // Unsafe paths are never retried
+    resolve_game_file(output_path, &file_info.path).map_err(|e| failure(e, 0))?;
+    tracker.start_file(&file_info.path);
+
+    if let Some(size) = reuse_staged_file(session, file_info, tracker).await {
+        return Ok(size);
+    }
+
+    if let Some(size) = patch_file(client, &session.game_path, output_path, file_info, tracker, control).await {
+        return Ok(size);
+    }
+
+    if !file_info.chunks.is_empty() {
+        match assemble_file(session, file_info, tracker, control).await {
+            Ok(size) => return Ok(size),
+            Err(e) if control.is_cancelled() => return Err(failure(e, 1)),
+            Err(e) => warn!("Assembling {} from chunks failed ({}), downloading the full file", file_info.path, e),
+        }
+    }
+
+    let mut attempt = 0;
+    loop {
+        let url = mirrors.url_for(file_info.transfer_url(), attempt);
+        match download_file(client, output_path, file_info, &url, tracker, control).await {
+            Ok(size) => return Ok(size),
+            Err(e) if control.is_cancelled() => return Err(failure(e, attempt + 1)),
+            Err(e) if attempt < settings.retries => {
+                attempt += 1;
+                let delay = settings.retry_delay(attempt);
+                warn!("Download of {} from {} failed ({}), retry {}/{} in {:?}",
+                      file_info.path, url, e, attempt, settings.retries, delay);
+                tokio::time::sleep(delay).await;
+                control.checkpoint().await.map_err(|e| failure(e, attempt))?;
+            }
+            Err(e) => {
+                error!("Giving up on {} after {} attempt(s): {}", file_info.path, attempt + 1, e);
+                return Err(failure(e, attempt + 1));
+            }
+        }
+    }
+}
+
+/// Keeps a file staged by an earlier, interrupted update if it is still current.
+///
+/// # Returns
+///
+/// The size of the staged file, or `None` when it has to be fetched again.
+async fn reuse_staged_file(session: &DownloadSession, file_info: &FileInfo, tracker: &DownloadTracker) -> Option<u64> {
+    if session.output_path == session.game_path {
+        return None;
+    }
+
+    let staged_path = resolve_game_file(&session.output_path, &file_info.path).ok()?;
+    if fs::metadata(&staged_path).await.ok()?.len() != file_info.size {
+        return None;
+    }
+    let algorithm = file_info.hash_algorithm;
+    let staged_hash = tokio::task::spawn_blocking(move || hash_file(&staged_path, algorithm))
+        .await
+        .ok()?
+        .ok()?;
+    if staged_hash != file_info.hash {
+        return None;
+    }
+
+    info!("Reusing staged file: {}", file_info.path);
+    tracker.add_bytes(file_info.transfer_size());
+    Some(file_info.size)
+}
+
+/// Tries to bring an existing file up to date with a binary diff. The patched
+/// file is written to `output_path`.
+///
+/// # Returns
+///
+/// The size of the updated file, or `None` when the file has to be downloaded in full.
+async fn patch_file(
+    client: &Client,
+    game_path: &Path,
+    output_path: &Path,
+    file_info: &FileInfo,
+    tracker: &DownloadTracker,
+    control: &DownloadControl,
+) -> Option<u64> {
+    if file_info.patches.is_empty() {
+        return None;
+    }
+
+    let file_path = resolve_game_file(game_path, &file_info.path).ok()?;
+    if !file_path.exists() {
+        return None;
+    }
+
+    let (hash_path, algorithm) = (file_path.clone(), file_info.hash_algorithm);
+    let local_hash = tokio::task::spawn_blocking(move || hash_file(&hash_path, algorithm))
+        .await
+        .ok()?
+        .ok()?;
+    let patch = select_patch(file_info, &local_hash)?;
+    let output_file = resolve_game_file(output_path, &file_info.path).ok()?;
+
+    info!("Patching {} with a {} byte {:?} diff", file_info.path, patch.size, patch.format);
+    match apply_remote_patch(client, &file_path, &output_file, file_info, patch, tracker, control).await {
+        Ok(size) => Some(size),
+        Err(e) => {
+            warn!("Patching {} failed ({}), downloading the full file", file_info.path, e);
+            None
+        }
+    }
+}
+
+async fn apply_remote_patch(
+    client: &Client,
+    file_path: &Path,
+    output_file: &Path,
+    file_info: &FileInfo,
+    patch: &PatchInfo,
+    tracker: &DownloadTracker,
+    control: &DownloadControl,
+) -> Result<u64, String> {
+    let partial = PartialDownload::new(output_file);
+    let patch_path = partial.sibling_path("patch");
+    partial.discard().await;
+
+    let mut downloaded = 0;
+    let result = async {
+        if let Some(parent) = output_file.parent() {
+            fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+        }
+
+        let res = client
+            .get(&patch.url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| e.to_string())?;
+
+        // The diff is checked against the manifest before it touches the old file
+        let mut file = File::create(&patch_path).await.map_err(|e| e.to_string())?;
+        let mut hasher = StreamHasher::new(file_info.hash_algorithm);
+        let mut stream = res.bytes_stream();
+        while let Some(chunk_result) = stream.next().await {
+            let chunk = chunk_result.map_err(|e| e.to_string())?;
+            control.checkpoint().await?;
+            control.limiter().acquire(chunk.len() as u64).await;
+            if downloaded + chunk.len() as u64 > patch.size {
+                return Err(format!("Patch for {} is larger than the {} bytes announced", file_info.path, patch.size));
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            downloaded += chunk.len() as u64;
+            tracker.add_bytes(chunk.len() as u64);
+        }
+        file.flush().await.map_err(|e| e.to_string())?;
+        drop(file);
+
+        if downloaded != patch.size {
+            return Err(format!("Patch for {} is truncated: {} of {} bytes", file_info.path, downloaded, patch.size));
+        }
+        if hasher.finalize() != patch.hash {
+            return Err(format!("Hash mismatch for the patch of {}", file_info.path));
+        }
+
+        let (old_path, diff_path, part_path) =
+            (file_path.to_path_buf(), patch_path.clone(), partial.part_path().to_path_buf());
+        let (format, max_size) = (patch.format, file_info.size);
+        tokio::task::spawn_blocking(move || apply_patch(&old_path, &diff_path, format, &part_path, max_size))
+            .await
+            .map_err(|e| e.to_string())??;
+
+        let (part_path, algorithm) = (partial.part_path().to_path_buf(), file_info.hash_algorithm);
+        let patched_hash = tokio::task::spawn_blocking(move || hash_file(&part_path, algorithm))
+            .await
+            .map_err(|e| e.to_string())??;
+        if patched_hash != file_info.hash {
+            return Err(format!("Hash mismatch after patching: {}", file_info.path));
+        }
+
+        partial.commit().await
+    }
+    .await;
+
+    let _ = fs::remove_file(&patch_path).await;
+    match result {
+        Ok(()) => {
+            // Count the bytes the diff saved us, so the totals still add up
+            tracker.add_bytes(file_info.transfer_size().saturating_sub(downloaded));
+            debug!("File patch completed: {}", file_info.path);
+            Ok(file_info.size)
+        }
+        Err(e) => {
+            tracker.remove_bytes(downloaded);
+            partial.discard().await;
+            Err(e)
+        }
+    }
+}
+
+/// Rebuilds a file from its chunks, reading chunks present in the local install
+/// and downloading only the missing ones.
+///
+/// # Returns
+///
+/// The size of the assembled file.
+async fn assemble_file(
+    session: &DownloadSession,
+    file_info: &FileInfo,
+    tracker: &DownloadTracker,
+    control: &DownloadControl,
+) -> Result<u64, String> {
+    let file_path = resolve_game_file(&session.output_path, &file_info.path)?;
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+    }
+
+    let partial = PartialDownload::new(&file_path);
+    let assembly_path = partial.sibling_path("assemble");
+    let file_server_url = session.mirrors.primary().to_string();
+
+    let mut downloaded = 0;
+    let mut reused = 0;
+    let result = async {
+        let mut file = File::create(&assembly_path).await.map_err(|e| e.to_string())?;
+
+        for chunk in &file_info.chunks {
+            control.checkpoint().await?;
+
+            let (index, wanted) = (Arc::clone(&session.chunks), chunk.clone());
+            let local = tokio::task::spawn_blocking(move || index.read_chunk(&wanted))
+                .await
+                .map_err(|e| e.to_string())?;
+            let data = match local {
+                Some(data) => {
+                    reused += data.len() as u64;
+                    data
+                }
+                None => {
+                    let url = chunk_url(&file_server_url, &chunk.hash);
+                    let data = fetch_chunk(session, &url, tracker, control, &mut downloaded).await?;
+                    if !verify_chunk(chunk, &data) {
+                        return Err(format!("Chunk {} of {} is corrupted", chunk.hash, file_info.path));
+                    }
+                    data
+                }
+            };
+            file.write_all(&data).await.map_err(|e| e.to_string())?;
+        }
+
+        file.flush().await.map_err(|e| e.to_string())?;
+        file.sync_all().await.map_err(|e| e.to_string())?;
+        drop(file);
+
+        let (hash_path, algorithm) = (assembly_path.clone(), file_info.hash_algorithm);
+        let assembled_hash = tokio::task::spawn_blocking(move || hash_file(&hash_path, algorithm))
+            .await
+            .map_err(|e| e.to_string())??;
+        if assembled_hash != file_info.hash {
+            return Err(format!("Hash mismatch after assembling: {}", file_info.path));
+        }
+
+        partial.commit_from(&assembly_path).await
+    }
+    .await;
+
+    match result {
+        Ok(()) => {
+            // Count the bytes the local chunks saved us, so the totals still add up
+            tracker.add_bytes(file_info.transfer_size().saturating_sub(downloaded));
+            debug!("File assembled from chunks: {} ({} reused, {} downloaded)",
+                   file_info.path, format_bytes(reused), format_bytes(downloaded));
+            Ok(file_info.size)
+        }
+        Err(e) => {
+            tracker.remove_bytes(downloaded);
+            let _ = fs::remove_file(&assembly_path).await;
+            Err(e)
+        }
+    }
+}
+
+/// Downloads a single chunk into memory, retrying with backoff and rotating mirrors.
+async fn fetch_chunk(
+    session: &DownloadSession,
+    url: &str,
+    tracker: &DownloadTracker,
+    control: &DownloadControl,
+    downloaded: &mut u64,
+) -> Result<Vec<u8>, String> {
+    let mut attempt = 0;
+    loop {
+        let chunk_url = session.mirrors.url_for(url, attempt);
+        let mut counted = 0;
+        let result = async {
+            let res = session
+                .client
+                .get(&chunk_url)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status())
+                .map_err(|e| e.to_string())?;
+
+            let mut data = Vec::new();
+            let mut stream = res.bytes_stream();
+            while let Some(chunk_result) = stream.next().await {
+                let bytes = chunk_result.map_err(|e| e.to_string())?;
+                control.checkpoint().await?;
+                control.limiter().acquire(bytes.len() as u64).await;
+                data.extend_from_slice(&bytes);
+                tracker.add_bytes(bytes.len() as u64);
+                counted += bytes.len() as u64;
+            }
+            Ok::<_, String>(data)
+        }
+        .await;
+
+        match result {
+            Ok(data) => {
+                *downloaded += counted;
+                return Ok(data);
+            }
+            Err(e) => {
+                tracker.remove_bytes(counted);
+                if control.is_cancelled() || attempt >= session.settings.retries {
+                    return Err(e);
+                }
+                attempt += 1;
+                let delay = session.settings.retry_delay(attempt);
+                warn!("Download of chunk {} failed ({}), retry {}/{} in {:?}",
+                      chunk_url, e, attempt, session.settings.retries, delay);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Downloads a single manifest entry from `url` into `game_path`, the game
+/// folder or the staging area of a staged update.
+///
+/// The file is written to a `.part` file, resumed when possible, verified against
+/// the manifest hash and then moved into place. Entries with a compressed variant
+/// are transferred compressed and unpacked as the bytes arrive, while the part file
+/// keeps the compressed bytes so resuming and progress work on those. Bytes of a failed attempt are
+/// taken back from the tracker. Pausing, cancelling and the bandwidth cap are
+/// applied between chunks.
+///
+/// # Returns
+///
+/// The number of bytes transferred, including bytes resumed from an earlier attempt.
+pub async fn download_file(
+    client: &Client,
+    game_path: &Path,
+    file_info: &FileInfo,
+    url: &str,
+    tracker: &DownloadTracker,
+    control: &DownloadControl,
+) -> Result<u64, String> {
+    let mut counted = 0;
+    let result = download_attempt(client, game_path, file_info, url, tracker, control, &mut counted).await;
+    if result.is_err() {
+        tracker.remove_bytes(counted);
+        // Unpacked output is redone from the part file by the next attempt
+        if let Ok(file_path) = resolve_game_file(game_path, &file_info.path) {
+            let _ = fs::remove_file(PartialDownload::new(&file_path).sibling_path("unpacked")).await;
+        }
+    }
+    result
+}
+
+/// Unpacks a compressed download into `<file>.unpacked` as its bytes arrive.
+struct Unpacker {
+    decoder: StreamDecoder,
+    file: File,
+}
+
+impl Unpacker {
+    async fn create(path: &Path, format: Compression, file_info: &FileInfo) -> Result<Self, String> {
+        let decoder = StreamDecoder::new(format, file_info.hash_algorithm, file_info.size)?;
+        let file = File::create(path).await.map_err(|e| e.to_string())?;
+        Ok(Unpacker { decoder, file })
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        let output = self.decoder.decode(data)?;
+        self.file.write_all(&output).await.map_err(|e| e.to_string())
+    }
+
+    /// Unpacks the first `len` bytes of the part file, left by an earlier attempt.
+    async fn replay(&mut self, part_path: &Path, len: u64) -> Result<(), String> {
+        let mut part = File::open(part_path).await.map_err(|e| e.to_string())?.take(len);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let bytes_read = part.read(&mut buffer).await.map_err(|e| e.to_string())?;
+            if bytes_read == 0 {
+                return Ok(());
+            }
+            self.write(&buffer[..bytes_read]).await?;
+        }
+    }
+
+    /// Returns the hash of the unpacked file.
+    async fn finish(mut self) -> Result<String, String> {
+        let (output, hash) = self.decoder.finish()?;
+        self.file.write_all(&output).await.map_err(|e| e.to_string())?;
+        self.file.flush().await.map_err(|e| e.to_string())?;
+        self.file.sync_all().await.map_err(|e| e.to_string())?;
+        Ok(hash)
+    }
+}
+
+/// A part file that does not unpack must not be resumed from.
+async fn unpack_failed(partial: &PartialDownload, file_info: &FileInfo, e: String) -> String {
+    partial.discard().await;
+    format!("Failed to unpack {}: {}", file_info.path, e)
+}
+
+async fn download_attempt(
+    client: &Client,
+    game_path: &Path,
+    file_info: &FileInfo,
+    url: &str,
+    tracker: &DownloadTracker,
+    control: &DownloadControl,
+    counted: &mut u64,
+) -> Result<u64, String> {
+    let file_path = resolve_game_file(game_path, &file_info.path)?;
+
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+    }
+
+    let partial = PartialDownload::new(&file_path);
+    let resume_offset = partial.resume_offset(file_info).await;
+
+    let request = partial.resume_request(client.get(url), resume_offset).await;
+    let res = request.send().await.map_err(|e| e.to_string())?;
+
+    let start = partial.start_for(&res, resume_offset)?;
+    let mut downloaded = match start {
+        DownloadStart::Resume(offset) => offset,
+        DownloadStart::Fresh => 0,
+        DownloadStart::Complete => resume_offset,
+    };
+    tracker.add_bytes(downloaded);
+    *counted += downloaded;
+
+    let unpacked_path = partial.sibling_path("unpacked");
+    let mut unpacker = match &file_info.compressed {
+        Some(compressed) => {
+            let mut unpacker = Unpacker::create(&unpacked_path, compressed.format, file_info).await?;
+            if downloaded > 0 {
+                if let Err(e) = unpacker.replay(partial.part_path(), downloaded).await {
+                    return Err(unpack_failed(&partial, file_info, e).await);
+                }
+            }
+            Some(unpacker)
+        }
+        None => None,
+    };
+
+    if !matches!(start, DownloadStart::Complete) {
+        let mut file = partial.open(file_info, url, &res, &start).await?;
+        let mut stream = res.bytes_stream();
+
+        debug!("Downloading file: {} (resuming at {} bytes)", file_info.path, downloaded);
+
+        while let Some(chunk_result) = stream.next().await {
+            let chunk = chunk_result.map_err(|e| e.to_string())?;
+            if let Err(e) = control.checkpoint().await {
+                // Keep what we have, the next run resumes from the part file
+                file.flush().await.map_err(|e| e.to_string())?;
+                return Err(e);
+            }
+            control.limiter().acquire(chunk.len() as u64).await;
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            if let Some(unpacker) = unpacker.as_mut() {
+                if let Err(e) = unpacker.write(&chunk).await {
+                    return Err(unpack_failed(&partial, file_info, e).await);
+                }
+            }
+            downloaded += chunk.len() as u64;
+            tracker.add_bytes(chunk.len() as u64);
+            *counted += chunk.len() as u64;
+        }
+
+        file.flush().await.map_err(|e| e.to_string())?;
+        file.sync_all().await.map_err(|e| e.to_string())?;
+    }
+
+    match unpacker {
+        Some(unpacker) => {
+            let unpacked_hash = match unpacker.finish().await {
+                Ok(hash) => hash,
+                Err(e) => return Err(unpack_failed(&partial, file_info, e).await),
+            };
+            if unpacked_hash != file_info.hash {
+                // A corrupted part file must not be resumed from
+                partial.discard().await;
+                return Err(format!("Hash mismatch for file: {}", file_info.path));
+            }
+
+            partial.commit_from(&unpacked_path).await?;
+        }
+        None => {
+            let (part_path, algorithm) = (partial.part_path().to_path_buf(), file_info.hash_algorithm);
+            let downloaded_hash = tokio::task::spawn_blocking(move || hash_file(&part_path, algorithm))
+                .await
+                .map_err(|e| e.to_string())??;
+            if downloaded_hash != file_info.hash {
+                // A corrupted part file must not be resumed from
+                partial.discard().await;
+                return Err(format!("Hash mismatch for file: {}", file_info.path));
+            }
+
+            partial.commit().await?;
+        }
+    }
+
+    debug!("File download completed: {}", file_info.path);
+
+    Ok(downloaded)
+}
+
+/// Resume information stored next to a `.part` file.
+///
+/// The validators are taken from the response that started the download, so a
+/// resumed request only appends to the part file if the remote file is unchanged.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct ResumeInfo {
+    url: String,
+    hash: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Compression of the part file contents, `None` for the raw file.
+    #[serde(default)]
+    compression: Option<Compression>,
+}
+
+/// How the response to a (possibly ranged) request has to be written.
+#[derive(Debug, PartialEq)]
+pub enum DownloadStart {
+    /// The server honoured the range request, append from the given offset.
+    Resume(u64),
+    /// The download starts from byte zero.
+    Fresh,
+    /// The part file already holds the whole file.
+    Complete,
+}
+
+/// A download that is written to `<file>.part` and moved into place once verified.
+pub struct PartialDownload {
+    target_path: PathBuf,
+    part_path: PathBuf,
+    meta_path: PathBuf,
+}
+
+impl PartialDownload {
+    /// Creates the partial download state for the given destination file.
+    pub fn new(target_path: &Path) -> Self {
+        let file_name = target_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        PartialDownload {
+            target_path: target_path.to_path_buf(),
+            part_path: target_path.with_file_name(format!("{}.part", file_name)),
+            meta_path: target_path.with_file_name(format!("{}.part.json", file_name)),
+        }
+    }
+
+    /// Returns the path of the `.part` file.
+    pub fn part_path(&self) -> &Path {
+        &self.part_path
+    }
+
+    /// Returns a scratch path next to the destination file, e.g. `<file>.patch`.
+    pub fn sibling_path(&self, extension: &str) -> PathBuf {
+        let file_name = self
+            .target_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.target_path.with_file_name(format!("{}.{}", file_name, extension))
+    }
+
+    async fn load_resume_info(&self) -> Option<ResumeInfo> {
+        let contents = fs::read_to_string(&self.meta_path).await.ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Returns the number of bytes that can be resumed for this file, or 0.
+    ///
+    /// A leftover part file is only reused if it was started for the same target
+    /// hash and transfer encoding, and the server gave us a validator to check it
+    /// against. The URL may differ, as mirrors serve the same content.
+    pub async fn resume_offset(&self, file_info: &FileInfo) -> u64 {
+        let part_size = match fs::metadata(&self.part_path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return 0,
+        };
+
+        match self.load_resume_info().await {
+            Some(info)
+                if info.hash == file_info.hash
+                    && info.compression == file_info.compressed.as_ref().map(|c| c.format)
+                    && (info.etag.is_some() || info.last_modified.is_some())
+                    && part_size <= file_info.transfer_size() =>
+            {
+                part_size
+            }
+            _ => {
+                info!("Discarding stale partial download: {:?}", self.part_path);
+                self.discard().await;
+                0
+            }
+        }
+    }
+
+    /// Adds the `Range` and `If-Range` headers needed to resume from `offset`.
+    pub async fn resume_request(&self, request: RequestBuilder, offset: u64) -> RequestBuilder {
+        if offset == 0 {
+            return request;
+        }
+
+        let validator = match self.load_resume_info().await {
+            Some(info) => info.etag.or(info.last_modified),
+            None => None,
+        };
+
+        match validator {
+            Some(validator) => request
+                .header(RANGE, format!("bytes={}-", offset))
+                .header(IF_RANGE, validator),
+            None => request,
+        }
+    }
+
+    /// Inspects the response status to decide whether the part file is appended to,
+    /// restarted or already complete.
+    pub fn start_for(&self, response: &Response, offset: u64) -> Result<DownloadStart, String> {
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT if offset > 0 => {
+                let range_start = response
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(parse_content_range_start);
+
+                if range_start == Some(offset) {
+                    Ok(DownloadStart::Resume(offset))
+                } else {
+                    Err(format!(
+                        "Unexpected Content-Range for {:?}: expected start {}, got {:?}",
+                        self.target_path, offset, range_start
+                    ))
+                }
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => Ok(DownloadStart::Complete),
+            status if status.is_success() => Ok(DownloadStart::Fresh),
+            status => Err(format!("Unsuccessful HTTP response: {}", status)),
+        }
+    }
+
+    /// Opens the part file for writing and records the resume information.
+    pub async fn open(
+        &self,
+        file_info: &FileInfo,
+        url: &str,
+        response: &Response,
+        start: &DownloadStart,
+    ) -> Result<File, String> {
+        match start {
+            DownloadStart::Resume(offset) => {
+                info!("Resuming download of {} at byte {}", file_info.path, offset);
+                OpenOptions::new()
+                    .append(true)
+                    .open(&self.part_path)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            DownloadStart::Fresh | DownloadStart::Complete => {
+                let header = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string)
+                };
+                let resume_info = ResumeInfo {
+                    url: url.to_string(),
+                    hash: file_info.hash.clone(),
+                    etag: header(ETAG),
+                    last_modified: header(LAST_MODIFIED),
+                    compression: file_info.compressed.as_ref().map(|c| c.format),
+                };
+                let serialized = serde_json::to_string(&resume_info).map_err(|e| e.to_string())?;
+                fs::write(&self.meta_path, serialized)
+                    .await
+                    .map_err(|e| e.to_string())?;
+
+                File::create(&self.part_path).await.map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Atomically replaces the destination file with the verified part file.
+    pub async fn commit(&self) -> Result<(), String> {
+        fs::rename(&self.part_path, &self.target_path)
+            .await
+            .map_err(|e| format!("Failed to move {:?} into place: {}", self.part_path, e))?;
+        let _ = fs::remove_file(&self.meta_path).await;
+        Ok(())
+    }
+
+    /// Moves a file derived from the part file into place, e.g. its unpacked
+    /// contents, and drops the part file.
+    pub async fn commit_from(&self, source: &Path) -> Result<(), String> {
+        fs::rename(source, &self.target_path)
+            .await
+            .map_err(|e| format!("Failed to move {:?} into place: {}", source, e))?;
+        self.discard().await;
+        Ok(())
+    }
+
+    /// Removes the part file and its resume information.
+    pub async fn discard(&self) {
+        for path in [&self.part_path, &self.meta_path] {
+            if let Err(e) = fs::remove_file(path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to remove {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+}
+
+/// Parses the first byte position of a `Content-Range: bytes <start>-<end>/<size>` header.
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    value
+        .trim()
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::chunks::{chunk_file, chunk_store_path, write_chunks, ChunkInfo, CHUNK_STORE_DIR};
+    use crate::patch::compression::{compress_file, CompressedInfo};
+    use crate::patch::hashing::HashAlgorithm;
+    use crate::patch::server;
+    use serde_json::json;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    fn file_info(url: &str) -> FileInfo {
+        serde_json::from_value(json!({
+            "path": "S1Data.gpk",
+            "hash": "abc123",
+            "size": 10_000,
+            "url": url,
+        }))
+        .unwrap()
+    }
+
+    /// A partial download in a fresh temporary folder.
+    fn partial(name: &str) -> PartialDownload {
+        let dir = std::env::temp_dir().join(format!("teralaunch-download-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        PartialDownload::new(&dir.join("S1Data.gpk"))
+    }
+
+    fn seed(partial: &PartialDownload, part_len: usize, resume_info: serde_json::Value) {
+        std::fs::write(&partial.part_path, vec![0u8; part_len]).unwrap();
+        std::fs::write(&partial.meta_path, resume_info.to_string()).unwrap();
+    }
+
+    /// Answers a single request with the given raw HTTP response.
+    async fn respond_with(raw: &'static str) -> Response {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(raw.as_bytes()).unwrap();
+        });
+        let client = Client::builder().no_proxy().build().unwrap();
+        client.get(format!("http://{}/S1Data.gpk", addr)).send().await.unwrap()
+    }
+
+    #[test]
+    fn parses_content_range_starts() {
+        assert_eq!(parse_content_range_start("bytes 9000-9999/10000"), Some(9000));
+        assert_eq!(parse_content_range_start(" bytes 0-0/1 "), Some(0));
+        assert_eq!(parse_content_range_start("bytes */10000"), None);
+        assert_eq!(parse_content_range_start("items 0-9/10"), None);
+    }
+
+    #[test]
+    fn retries_move_through_the_mirrors() {
+        let mirrors = MirrorList::new(
+            "https://files.example.com/",
+            vec!["https://mirror.example.com".to_string(), "https://files.example.com".to_string()],
+        );
+
+        let url = "https://files.example.com/files/S1Game/S1Data.gpk";
+        assert_eq!(mirrors.url_for(url, 0), url);
+        assert_eq!(mirrors.url_for(url, 1), "https://mirror.example.com/files/S1Game/S1Data.gpk");
+        assert_eq!(mirrors.url_for(url, 2), url);
+        assert_eq!(mirrors.url_for("https://cdn.example.com/a.gpk", 1), "https://cdn.example.com/a.gpk");
+    }
+
+    #[test]
+    fn retry_delay_grows_up_to_the_cap() {
+        let settings = DownloadSettings::default();
+        for _ in 0..20 {
+            let first = settings.retry_delay(1);
+            assert!(first >= RETRY_BASE_DELAY / 2 && first <= RETRY_BASE_DELAY, "{:?}", first);
+            let third = settings.retry_delay(3);
+            assert!(third >= RETRY_BASE_DELAY * 2 && third <= RETRY_BASE_DELAY * 4, "{:?}", third);
+            let late = settings.retry_delay(40);
+            assert!(late >= RETRY_MAX_DELAY / 2 && late <= RETRY_MAX_DELAY, "{:?}", late);
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_holds_back_bytes_over_the_cap() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+        limiter.acquire(1_000_000).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // An empty bucket at 10 KB/s owes half a second for 5 KB
+        limiter.set_limit_kbps(10);
+        let start = Instant::now();
+        limiter.acquire(5 * 1024).await;
+        assert!(start.elapsed() >= Duration::from_millis(450), "{:?}", start.elapsed());
+    }
+
+    #[tokio::test]
+    async fn checkpoint_waits_while_paused_and_fails_once_cancelled() {
+        let control = Arc::new(DownloadControl::new());
+        assert_eq!(control.checkpoint().await, Ok(()));
+
+        control.pause();
+        let waiting = tokio::spawn({
+            let control = Arc::clone(&control);
+            async move { control.checkpoint().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        control.resume();
+        assert_eq!(waiting.await.unwrap(), Ok(()));
+
+        control.pause();
+        let waiting = tokio::spawn({
+            let control = Arc::clone(&control);
+            async move { control.checkpoint().await }
+        });
+        control.cancel();
+        assert_eq!(waiting.await.unwrap(), Err(CANCELLED.to_string()));
+
+        control.reset(&DownloadSettings::default());
+        assert_eq!(control.checkpoint().await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn only_parts_of_the_same_unchanged_file_are_resumed() {
+        let url = "http://files.example.com/S1Data.gpk";
+        let file_info = file_info(url);
+
+        let matching = partial("resume-matching");
+        seed(&matching, 4000, json!({ "url": url, "hash": "abc123", "etag": "\"v1\"", "last_modified": null }));
+        assert_eq!(matching.resume_offset(&file_info).await, 4000);
+
+        let other_hash = partial("resume-other-hash");
+        seed(&other_hash, 4000, json!({ "url": url, "hash": "def456", "etag": "\"v1\"", "last_modified": null }));
+        assert_eq!(other_hash.resume_offset(&file_info).await, 0);
+        assert!(!other_hash.part_path().exists());
+
+        let no_validator = partial("resume-no-validator");
+        seed(&no_validator, 4000, json!({ "url": url, "hash": "abc123", "etag": null, "last_modified": null }));
+        assert_eq!(no_validator.resume_offset(&file_info).await, 0);
+
+        let oversized = partial("resume-oversized");
+        seed(&oversized, 10_001, json!({ "url": url, "hash": "abc123", "etag": "\"v1\"", "last_modified": null }));
+        assert_eq!(oversized.resume_offset(&file_info).await, 0);
+
+        assert_eq!(partial("resume-none").resume_offset(&file_info).await, 0);
+    }
+
+    #[tokio::test]
+    async fn resumed_requests_carry_the_stored_validator() {
+        let url = "http://files.example.com/S1Data.gpk";
+        let partial = partial("resume-request");
+        seed(
+            &partial,
+            4000,
+            json!({ "url": url, "hash": "abc123", "etag": null, "last_modified": "Tue, 01 Oct 2024 10:00:00 GMT" }),
+        );
+        let client = Client::new();
+
+        let request = partial.resume_request(client.get(url), 4000).await.build().unwrap();
+        assert_eq!(request.headers()[RANGE], "bytes=4000-");
+        assert_eq!(request.headers()[IF_RANGE], "Tue, 01 Oct 2024 10:00:00 GMT");
+
+        let request = partial.resume_request(client.get(url), 0).await.build().unwrap();
+        assert!(request.headers().get(RANGE).is_none());
+    }
+
+    #[tokio::test]
+    async fn start_follows_the_response_status() {
+        let partial = partial("start");
+
+        let ranged = respond_with(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 9000-9999/10000\r\nContent-Length: 0\r\n\r\n",
+        )
+        .await;
+        assert_eq!(partial.start_for(&ranged, 9000), Ok(DownloadStart::Resume(9000)));
+
+        let ranged = respond_with(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 9000-9999/10000\r\nContent-Length: 0\r\n\r\n",
+        )
+        .await;
+        assert!(partial.start_for(&ranged, 8000).unwrap_err().contains("Unexpected Content-Range"));
+
+        // If-Range did not match, so the server sent the whole file
+        let full = respond_with("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await;
+        assert_eq!(partial.start_for(&full, 9000), Ok(DownloadStart::Fresh));
+
+        let past_end = respond_with("HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\n\r\n").await;
+        assert_eq!(partial.start_for(&past_end, 10_000), Ok(DownloadStart::Complete));
+
+        let missing = respond_with("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await;
+        assert!(partial.start_for(&missing, 0).is_err());
+    }
+
+    /// A served file and an empty game folder to download it into.
+    struct Served {
+        root: PathBuf,
+        game_path: PathBuf,
+        contents: Vec<u8>,
+        file_info: FileInfo,
+        client: Client,
+    }
+
+    impl Served {
+        async fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("teralaunch-download-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            let game_path = root.join("game");
+            std::fs::create_dir_all(root.join("publish")).unwrap();
+            std::fs::create_dir_all(&game_path).unwrap();
+
+            let contents: Vec<u8> = (0..=255u8).cycle().take(10_000).collect();
+            let source = root.join("publish").join("S1Data.gpk");
+            std::fs::write(&source, &contents).unwrap();
+
+            let (addr, server) = server::bind(root.join("publish"), "127.0.0.1:0".parse().unwrap()).unwrap();
+            tokio::spawn(server);
+
+            let file_info = FileInfo {
+                path: "S1Data.gpk".to_string(),
+                hash: hash_file(&source, HashAlgorithm::Sha256).unwrap(),
+                size: contents.len() as u64,
+                url: format!("http://{}/S1Data.gpk", addr),
+                patches: Vec::new(),
+                compressed: None,
+                chunks: Vec::new(),
+                hash_algorithm: HashAlgorithm::Sha256,
+            };
+            let client = Client::builder().no_proxy().build().unwrap();
+            Served { root, game_path, contents, file_info, client }
+        }
+
+        /// Leaves `part` behind as the part file of an interrupted download
+        /// validated by `etag`.
+        fn seed_part(&self, part: &[u8], etag: &str) {
+            let partial = PartialDownload::new(&self.game_path.join(&self.file_info.path));
+            std::fs::write(partial.part_path(), part).unwrap();
+            let resume_info = ResumeInfo {
+                url: self.file_info.url.clone(),
+                hash: self.file_info.hash.clone(),
+                etag: Some(etag.to_string()),
+                last_modified: None,
+                compression: self.file_info.compressed.as_ref().map(|c| c.format),
+            };
+            std::fs::write(&partial.meta_path, serde_json::to_string(&resume_info).unwrap()).unwrap();
+        }
+
+        /// Publishes a compressed artifact of the file and advertises it.
+        fn compress(&mut self, format: Compression) -> Vec<u8> {
+            let publish_path = self.root.join("publish");
+            let name = format!("S1Data.gpk.{}", format.extension());
+            let size = compress_file(&publish_path.join("S1Data.gpk"), &publish_path.join(&name), format).unwrap();
+            let url = self.file_info.url.replace("S1Data.gpk", &name);
+            self.file_info.compressed = Some(CompressedInfo { format, url, size });
+            std::fs::read(publish_path.join(&name)).unwrap()
+        }
+
+        /// URL the file is transferred from, the compressed artifact if there is one.
+        fn url(&self) -> &str {
+            self.file_info.compressed.as_ref().map_or(&self.file_info.url, |c| &c.url)
+        }
+
+        async fn etag(&self) -> String {
+            let response = self.client.head(self.url()).send().await.unwrap();
+            response.headers()[ETAG].to_str().unwrap().to_string()
+        }
+
+        async fn download(&self) -> Result<u64, String> {
+            let tracker = DownloadTracker::new(1, self.file_info.size, 0, 0);
+            let control = DownloadControl::new();
+            download_file(&self.client, &self.game_path, &self.file_info, self.url(), &tracker, &control).await
+        }
+
+        fn downloaded(&self) -> Vec<u8> {
+            std::fs::read(self.game_path.join(&self.file_info.path)).unwrap()
+        }
+    }
+
+    impl Drop for Served {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_an_unchanged_file() {
+        let served = Served::new("resume").await;
+        served.seed_part(&served.contents[..4000], &served.etag().await);
+
+        assert_eq!(served.download().await, Ok(10_000));
+        assert_eq!(served.downloaded(), served.contents);
+        assert!(!served.game_path.join("S1Data.gpk.part").exists());
+    }
+
+    #[tokio::test]
+    async fn changed_validator_restarts_the_download() {
+        let served = Served::new("restart").await;
+        // Bytes of an older revision of the file, which must not be appended to
+        served.seed_part(&[0xff; 4000], "\"outdated\"");
+
+        assert_eq!(served.download().await, Ok(10_000));
+        assert_eq!(served.downloaded(), served.contents);
+
+        // With a matching validator the same bytes are appended to, caught by the hash
+        served.seed_part(&[0xff; 4000], &served.etag().await);
+        assert!(served.download().await.unwrap_err().contains("Hash mismatch"));
+        assert!(!served.game_path.join("S1Data.gpk.part").exists());
+    }
+
+    #[tokio::test]
+    async fn compressed_downloads_are_unpacked_while_streaming() {
+        for format in [Compression::Zstd, Compression::Gzip] {
+            let mut served = Served::new(&format!("unpack-{}", format.extension())).await;
+            let artifact = served.compress(format);
+            assert_eq!(served.download().await, Ok(artifact.len() as u64));
+            assert_eq!(served.downloaded(), served.contents);
+
+            // A resumed download unpacks the bytes of the earlier attempt first
+            std::fs::remove_file(served.game_path.join("S1Data.gpk")).unwrap();
+            served.seed_part(&artifact[..artifact.len() / 2], &served.etag().await);
+            assert_eq!(served.download().await, Ok(artifact.len() as u64));
+            assert_eq!(served.downloaded(), served.contents);
+            for leftover in ["S1Data.gpk.part", "S1Data.gpk.unpacked"] {
+                assert!(!served.game_path.join(leftover).exists(), "{:?}: {}", format, leftover);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn compressed_download_unpacking_past_the_file_size_is_refused() {
+        for format in [Compression::Zstd, Compression::Gzip] {
+            let mut served = Served::new(&format!("unpack-oversize-{}", format.extension())).await;
+            served.compress(format);
+            served.file_info.size -= 1;
+
+            let error = served.download().await.unwrap_err();
+            assert!(error.contains("exceeds the expected"), "{:?}: {}", format, error);
+            for leftover in ["S1Data.gpk", "S1Data.gpk.part", "S1Data.gpk.unpacked"] {
+                assert!(!served.game_path.join(leftover).exists(), "{:?}: {}", format, leftover);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn chunked_files_are_assembled_from_local_and_downloaded_chunks() {
+        let root = std::env::temp_dir().join(format!("teralaunch-download-assemble-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let (game_path, publish_path) = (root.join("game"), root.join("publish"));
+        std::fs::create_dir_all(&game_path).unwrap();
+        std::fs::create_dir_all(&publish_path).unwrap();
+
+        let mut old = vec![0; 6 * 1024 * 1024];
+        rand::thread_rng().fill(&mut old[..]);
+        let mut new = old.clone();
+        new[3 * 1024 * 1024..3 * 1024 * 1024 + 4096].fill(0);
+        let local_path = game_path.join("S1Data.gpk");
+        std::fs::write(&local_path, &old).unwrap();
+        let release_path = root.join("release.gpk");
+        std::fs::write(&release_path, &new).unwrap();
+
+        let store_path = publish_path.join(CHUNK_STORE_DIR);
+        let chunks = write_chunks(&release_path, &store_path).unwrap();
+        // Only the changed chunks are published, the rest must come from the local file
+        let local_chunks = chunk_file(&local_path).unwrap();
+        for chunk in chunks.iter().filter(|chunk| local_chunks.contains(chunk)) {
+            std::fs::remove_file(store_path.join(chunk_store_path(&chunk.hash))).unwrap();
+        }
+        let changed: Vec<ChunkInfo> = chunks.iter().filter(|chunk| !local_chunks.contains(chunk)).cloned().collect();
+        assert!(!changed.is_empty() && changed.len() < chunks.len());
+
+        let (addr, server) = server::bind(publish_path.clone(), "127.0.0.1:0".parse().unwrap()).unwrap();
+        tokio::spawn(server);
+        let file_info = FileInfo {
+            path: "S1Data.gpk".to_string(),
+            hash: hash_file(&release_path, HashAlgorithm::Sha256).unwrap(),
+            size: new.len() as u64,
+            url: format!("http://{}/files/S1Data.gpk", addr),
+            patches: Vec::new(),
+            compressed: None,
+            chunks,
+            hash_algorithm: HashAlgorithm::Sha256,
+        };
+        let session = |game_path: &Path| DownloadSession {
+            client: Client::builder().no_proxy().build().unwrap(),
+            game_path: game_path.to_path_buf(),
+            output_path: game_path.to_path_buf(),
+            settings: DownloadSettings::default(),
+            mirrors: MirrorList::new(&format!("http://{}", addr), Vec::new()),
+            chunks: Arc::new(ChunkIndex::build(game_path, None, std::slice::from_ref(&file_info))),
+        };
+        let tracker = DownloadTracker::new(1, file_info.size, 0, 0);
+        let control = DownloadControl::new();
+
+        // A tampered chunk on the server is refused and the local file kept
+        let tampered = store_path.join(chunk_store_path(&changed[0].hash));
+        let published = std::fs::read(&tampered).unwrap();
+        std::fs::write(&tampered, vec![0; published.len()]).unwrap();
+        let error = assemble_file(&session(&game_path), &file_info, &tracker, &control).await.unwrap_err();
+        assert!(error.contains("is corrupted"), "{}", error);
+        assert!(std::fs::read(&local_path).unwrap() == old);
+        assert!(!game_path.join("S1Data.gpk.assemble").exists());
+
+        std::fs::write(&tampered, published).unwrap();
+        assert_eq!(assemble_file(&session(&game_path), &file_info, &tracker, &control).await, Ok(new.len() as u64));
+        assert!(std::fs::read(&local_path).unwrap() == new);
+        assert!(!game_path.join("S1Data.gpk.assemble").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
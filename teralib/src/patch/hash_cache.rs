@@ -0,0 +1,220 @@
+// Standard library imports
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Third-party imports
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::chunks::ChunkInfo;
+use super::hashing::HashAlgorithm;
+
+/// Version of the cache file layout. Caches written with another version are
+/// discarded instead of being trusted.
+pub const CACHE_SCHEMA_VERSION: u32 = 1;
+
+const CACHE_FILE_NAME: &str = "file_cache.json";
+
+/// Folder below the local app data directory holding the cache.
+pub(crate) const CACHE_DIR_NAME: &str = "teralaunch";
+
+/// Verification result of a local file.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CachedFileInfo {
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_sha256")]
+    pub hash_algorithm: HashAlgorithm,
+    pub size: u64,
+    pub last_modified: SystemTime,
+    /// Chunk list of the file, known once it was verified against a chunked manifest.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<ChunkInfo>,
+}
+
+impl CachedFileInfo {
+    /// Creates an entry for a file with the given metadata.
+    pub fn new(hash: String, hash_algorithm: HashAlgorithm, metadata: &Metadata, chunks: Vec<ChunkInfo>) -> Option<Self> {
+        Some(CachedFileInfo {
+            hash,
+            hash_algorithm,
+            size: metadata.len(),
+            last_modified: metadata.modified().ok()?,
+            chunks,
+        })
+    }
+
+    /// Returns true if the file still has the size and modification time it was hashed with.
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        self.size == metadata.len() && metadata.modified().ok() == Some(self.last_modified)
+    }
+}
+
+/// Cached hashes of the installed game files, per install folder.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HashCache {
+    schema_version: u32,
+    /// Entries by install folder, then by manifest path.
+    installs: HashMap<String, HashMap<String, CachedFileInfo>>,
+}
+
+fn install_key(game_path: &Path) -> String {
+    let path = game_path.canonicalize().unwrap_or_else(|_| game_path.to_path_buf());
+    path.to_string_lossy().into_owned()
+}
+
+fn cache_file_path() -> Result<PathBuf, String> {
+    if let Some(data_dir) = dirs_next::data_local_dir() {
+        return Ok(data_dir.join(CACHE_DIR_NAME).join(CACHE_FILE_NAME));
+    }
+
+    let mut path = std::env::current_exe().map_err(|e| e.to_string())?;
+    path.pop();
+    path.push(CACHE_FILE_NAME);
+    Ok(path)
+}
+
+impl HashCache {
+    /// Loads the cache from disk. A missing, corrupt or outdated cache yields an
+    /// empty one, so files are hashed again rather than trusted.
+    pub fn load() -> Self {
+        match cache_file_path() {
+            Ok(cache_path) => HashCache::load_from(&cache_path),
+            Err(_) => HashCache::empty(),
+        }
+    }
+
+    fn empty() -> Self {
+        HashCache { schema_version: CACHE_SCHEMA_VERSION, installs: HashMap::new() }
+    }
+
+    /// Loads the cache from `cache_path`, see `load`.
+    pub fn load_from(cache_path: &Path) -> Self {
+        let empty = HashCache::empty();
+
+        let contents = match fs::read(cache_path) {
+            Ok(contents) => contents,
+            Err(_) => return empty,
+        };
+
+        match serde_json::from_slice::<HashCache>(&contents) {
+            Ok(cache) if cache.schema_version == CACHE_SCHEMA_VERSION => cache,
+            Ok(cache) => {
+                info!("Discarding hash cache with schema version {}", cache.schema_version);
+                empty
+            }
+            Err(e) => {
+                warn!("Discarding unreadable hash cache: {}", e);
+                empty
+            }
+        }
+    }
+
+    /// Writes the cache to a temporary file and moves it into place, so a crash
+    /// never leaves a truncated cache behind.
+    pub fn save(&self) -> Result<(), String> {
+        self.save_to(&cache_file_path()?)
+    }
+
+    /// Writes the cache to `cache_path`, see `save`.
+    pub fn save_to(&self, cache_path: &Path) -> Result<(), String> {
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+
+        let temp_path = cache_path.with_extension("json.tmp");
+        let serialized = serde_json::to_vec(self).map_err(|e| e.to_string())?;
+        let mut file = File::create(&temp_path).map_err(|e| e.to_string())?;
+        file.write_all(&serialized).map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| e.to_string())?;
+        drop(file);
+
+        fs::rename(&temp_path, cache_path).map_err(|e| format!("Failed to replace hash cache: {}", e))
+    }
+
+    /// Returns the entries of an install folder.
+    pub fn install(&self, game_path: &Path) -> Option<&HashMap<String, CachedFileInfo>> {
+        self.installs.get(&install_key(game_path))
+    }
+
+    /// Returns the entries of an install folder for modification.
+    pub fn install_mut(&mut self, game_path: &Path) -> &mut HashMap<String, CachedFileInfo> {
+        self.installs.entry(install_key(game_path)).or_default()
+    }
+
+    /// Drops every entry of an install folder, forcing a full verification.
+    pub fn invalidate(&mut self, game_path: &Path) {
+        self.installs.remove(&install_key(game_path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("teralaunch-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn entry(folder: &Path, hash: &str) -> CachedFileInfo {
+        let path = folder.join("file.gpk");
+        fs::write(&path, hash).unwrap();
+        CachedFileInfo::new(hash.to_string(), HashAlgorithm::Sha256, &fs::metadata(&path).unwrap(), Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn entries_survive_a_save_and_are_kept_per_install() {
+        let folder = folder("installs");
+        let (first, second) = (folder.join("first"), folder.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+
+        let mut cache = HashCache::load_from(&folder.join(CACHE_FILE_NAME));
+        cache.install_mut(&first).insert("S1Data.gpk".to_string(), entry(&folder, "aaaa"));
+        cache.install_mut(&second).insert("S1Data.gpk".to_string(), entry(&folder, "bbbb"));
+        cache.save_to(&folder.join(CACHE_FILE_NAME)).unwrap();
+
+        let mut cache = HashCache::load_from(&folder.join(CACHE_FILE_NAME));
+        assert_eq!(cache.install(&first).unwrap()["S1Data.gpk"].hash, "aaaa");
+        assert_eq!(cache.install(&second).unwrap()["S1Data.gpk"].hash, "bbbb");
+        // Another spelling of the same folder finds the same entries
+        assert_eq!(cache.install(&second.join("..").join("first")).unwrap()["S1Data.gpk"].hash, "aaaa");
+        assert!(cache.install(&folder.join("third")).is_none());
+
+        cache.invalidate(&first);
+        assert!(cache.install(&first).is_none());
+        assert!(cache.install(&second).is_some());
+    }
+
+    #[test]
+    fn other_schema_versions_and_corrupt_caches_are_dropped() {
+        let folder = folder("schema");
+        let cache_path = folder.join(CACHE_FILE_NAME);
+
+        let mut cache = HashCache::load_from(&cache_path);
+        cache.install_mut(&folder).insert("S1Data.gpk".to_string(), entry(&folder, "aaaa"));
+        cache.schema_version = CACHE_SCHEMA_VERSION + 1;
+        cache.save_to(&cache_path).unwrap();
+        let loaded = HashCache::load_from(&cache_path);
+        assert_eq!(loaded.schema_version, CACHE_SCHEMA_VERSION);
+        assert!(loaded.install(&folder).is_none());
+
+        fs::write(&cache_path, b"{ not json").unwrap();
+        assert!(HashCache::load_from(&cache_path).install(&folder).is_none());
+    }
+
+    #[test]
+    fn entries_only_match_unchanged_files() {
+        let folder = folder("matches");
+        let cached = entry(&folder, "aaaa");
+        assert!(cached.matches(&fs::metadata(folder.join("file.gpk")).unwrap()));
+
+        fs::write(folder.join("file.gpk"), "aaaaa").unwrap();
+        assert!(!cached.matches(&fs::metadata(folder.join("file.gpk")).unwrap()));
+    }
+}
@@ -0,0 +1,284 @@
+// Standard library imports
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// Third-party imports
+use log::info;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Read size for buffered hashing.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Files at least this large are memory mapped instead of read through a buffer.
+const MMAP_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Hash algorithm of the file hashes in a manifest.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// The original manifest format, used when a manifest does not say otherwise.
+    #[default]
+    Sha256,
+    /// Several times faster on modern CPUs, hashes large files on multiple threads.
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn is_sha256(&self) -> bool {
+        *self == HashAlgorithm::Sha256
+    }
+}
+
+/// Incremental hasher for data that is not read from a single file, e.g. a
+/// decompressed stream.
+pub enum StreamHasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl StreamHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => StreamHasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => StreamHasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            StreamHasher::Sha256(hasher) => hasher.update(data),
+            StreamHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Returns the lowercase hex digest.
+    pub fn finalize(self) -> String {
+        match self {
+            StreamHasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            StreamHasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// Hashes a file with bounded memory.
+///
+/// Small files go through a 1 MB buffer, large files are memory mapped so the
+/// OS can read ahead; BLAKE3 additionally hashes mapped files on all cores.
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<String, String> {
+    hash_file_mapped_from(path, algorithm, MMAP_THRESHOLD)
+}
+
+/// Hashes a file, memory mapping it if it holds at least `mmap_threshold` bytes.
+fn hash_file_mapped_from(path: &Path, algorithm: HashAlgorithm, mmap_threshold: u64) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let size = file.metadata().map_err(|e| format!("Failed to read file: {}", e))?.len();
+
+    if size >= mmap_threshold {
+        // SAFETY: the mapping is read only; a file changed while it is hashed
+        // only yields a wrong hash, which is treated like any other mismatch.
+        let mapped = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to map file: {}", e))?;
+        return Ok(match algorithm {
+            HashAlgorithm::Sha256 => format!("{:x}", Sha256::digest(&mapped[..])),
+            HashAlgorithm::Blake3 => blake3::Hasher::new().update_rayon(&mapped).finalize().to_hex().to_string(),
+        });
+    }
+
+    let mut hasher = StreamHasher::new(algorithm);
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let bytes_read = file.read(&mut buffer).map_err(|e| format!("Failed to read file: {}", e))?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Kind of storage the game is installed on, from the `[verify] storage` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    Ssd,
+    Hdd,
+    Unknown,
+}
+
+impl StorageKind {
+    /// Parses the setting, `auto` (or anything unknown) detects the drive.
+    pub fn from_setting(value: &str, game_path: &Path) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ssd" => StorageKind::Ssd,
+            "hdd" => StorageKind::Hdd,
+            _ => detect_storage(game_path),
+        }
+    }
+
+    /// Number of files read at the same time.
+    ///
+    /// SSDs keep up with one reader per core, spinning disks are fastest with a
+    /// single sequential reader since every extra reader adds seeks.
+    pub fn parallelism(&self) -> usize {
+        let cores = num_cpus::get().max(1);
+        match self {
+            StorageKind::Ssd => cores.min(16),
+            StorageKind::Hdd => 1,
+            StorageKind::Unknown => cores.min(4),
+        }
+    }
+}
+
+/// Builds a thread pool sized for hashing files on `storage`.
+pub fn io_thread_pool(storage: StorageKind) -> Result<rayon::ThreadPool, String> {
+    let threads = storage.parallelism();
+    info!("Hashing on {:?} storage with {} thread(s)", storage, threads);
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|index| format!("hash-{}", index))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(windows)]
+fn detect_storage(path: &Path) -> StorageKind {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use winapi::shared::minwindef::{DWORD, MAX_PATH};
+    use winapi::um::fileapi::{CreateFileW, GetVolumePathNameW, OPEN_EXISTING};
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::ioapiset::DeviceIoControl;
+    use winapi::um::winioctl::{
+        PropertyStandardQuery, StorageDeviceSeekPenaltyProperty, IOCTL_STORAGE_QUERY_PROPERTY,
+        STORAGE_PROPERTY_QUERY,
+    };
+    use winapi::um::winnt::{FILE_SHARE_READ, FILE_SHARE_WRITE};
+
+    /// `DEVICE_SEEK_PENALTY_DESCRIPTOR`, missing from winapi.
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    struct SeekPenaltyDescriptor {
+        Version: DWORD,
+        Size: DWORD,
+        IncursSeekPenalty: u8,
+    }
+
+    let wide: Vec<u16> = OsStr::new(path).encode_wide().chain(Some(0)).collect();
+    let mut volume = [0u16; MAX_PATH + 1];
+
+    // SAFETY: all buffers outlive the calls and their sizes are passed along.
+    unsafe {
+        if GetVolumePathNameW(wide.as_ptr(), volume.as_mut_ptr(), volume.len() as DWORD) == 0 {
+            return StorageKind::Unknown;
+        }
+
+        // `C:\` -> `\\.\C:`
+        let volume = String::from_utf16_lossy(&volume[..volume.iter().position(|&c| c == 0).unwrap_or(0)]);
+        let device: Vec<u16> = OsStr::new(&format!("\\\\.\\{}", volume.trim_end_matches('\\')))
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+
+        let handle = CreateFileW(
+            device.as_ptr(),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            ptr::null_mut(),
+            OPEN_EXISTING,
+            0,
+            ptr::null_mut(),
+        );
+        if handle == INVALID_HANDLE_VALUE {
+            return StorageKind::Unknown;
+        }
+
+        let mut query: STORAGE_PROPERTY_QUERY = std::mem::zeroed();
+        query.PropertyId = StorageDeviceSeekPenaltyProperty;
+        query.QueryType = PropertyStandardQuery;
+        let mut descriptor: SeekPenaltyDescriptor = std::mem::zeroed();
+        let mut returned: DWORD = 0;
+
+        let ok = DeviceIoControl(
+            handle,
+            IOCTL_STORAGE_QUERY_PROPERTY,
+            &mut query as *mut _ as *mut _,
+            std::mem::size_of::<STORAGE_PROPERTY_QUERY>() as DWORD,
+            &mut descriptor as *mut _ as *mut _,
+            std::mem::size_of::<SeekPenaltyDescriptor>() as DWORD,
+            &mut returned,
+            ptr::null_mut(),
+        );
+        CloseHandle(handle);
+
+        if ok == 0 {
+            StorageKind::Unknown
+        } else if descriptor.IncursSeekPenalty != 0 {
+            StorageKind::Hdd
+        } else {
+            StorageKind::Ssd
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn detect_storage(_path: &Path) -> StorageKind {
+    StorageKind::Unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256_ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const BLAKE3_ABC: &str = "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85";
+    const SHA256_EMPTY: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const BLAKE3_EMPTY: &str = "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262";
+
+    fn write_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let folder = std::env::temp_dir().join(format!("teralaunch-hashing-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn hashes_known_vectors_with_both_algorithms() {
+        let abc = write_file("abc", b"abc");
+        let empty = write_file("empty", b"");
+
+        assert_eq!(hash_file(&abc, HashAlgorithm::Sha256).unwrap(), SHA256_ABC);
+        assert_eq!(hash_file(&abc, HashAlgorithm::Blake3).unwrap(), BLAKE3_ABC);
+        assert_eq!(hash_file(&empty, HashAlgorithm::Sha256).unwrap(), SHA256_EMPTY);
+        assert_eq!(hash_file(&empty, HashAlgorithm::Blake3).unwrap(), BLAKE3_EMPTY);
+    }
+
+    #[test]
+    fn mapped_and_buffered_hashes_match() {
+        // Spans several buffers, with a partial one at the end
+        let large: Vec<u8> = (0..BUFFER_SIZE * 2 + 123).map(|i| (i % 251) as u8).collect();
+        let files = [write_file("parity-empty", b""), write_file("parity-abc", b"abc"), write_file("parity-large", &large)];
+
+        for path in &files {
+            for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+                let buffered = hash_file_mapped_from(path, algorithm, u64::MAX).unwrap();
+                let mapped = hash_file_mapped_from(path, algorithm, 0).unwrap();
+                assert_eq!(buffered, mapped, "{:?} {:?}", path, algorithm);
+
+                let mut hasher = StreamHasher::new(algorithm);
+                hasher.update(&std::fs::read(path).unwrap());
+                assert_eq!(hasher.finalize(), buffered);
+            }
+        }
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let missing = std::env::temp_dir().join("teralaunch-hashing-missing");
+        assert!(hash_file(&missing, HashAlgorithm::Sha256).unwrap_err().starts_with("Failed to open file"));
+    }
+}
@@ -0,0 +1,298 @@
+// Standard library imports
+use std::path::{Path, PathBuf};
+
+// Third-party imports
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use log::info;
+use walkdir::WalkDir;
+
+use super::archive::ARCHIVE_DIR;
+use super::chunks::CHUNK_STORE_DIR;
+use super::compression::COMPRESSED_ARTIFACTS_DIR;
+use super::delta::PATCHES_DIR;
+use super::launcher_update::LAUNCHER_DIR;
+
+/// Rules file read from the root of the game folder.
+pub const IGNORE_FILE_NAME: &str = ".teraignore";
+
+/// Folder in the game directory receiving quarantined files, one subfolder per run.
+pub const QUARANTINE_DIR: &str = "$Quarantine";
+
+/// Folder in the game directory holding staged updates, see `staging::StagingArea`.
+pub const STAGING_DIR: &str = "$Staging";
+
+/// Built-in rules, applied before the rules files so those can re-include
+/// anything with `!pattern`.
+const DEFAULT_RULES: &str = "
+# Launcher and update files
+/hash-file.json
+/hash-file.*.json
+/*.sig
+/version.json
+/base-archive.json
+/tera_config.ini
+/.teraignore
+/launcher.json
+/Launcher.exe
+/Launcher.exe.*
+/teralauncher.exe
+/teralauncher.exe.*
+/tera_launcher.exe
+/tera_launcher.exe.*
+/*.log
+/launcher-update.json
+/local.db
+/version.ini
+/unins000.dat
+/unins000.exe
+
+# Generated by the patcher and the client
+/$Patch/
+/Binaries/cookies.dat
+/S1Game/GuildFlagUpload/
+/S1Game/GuildLogoUpload/
+/S1Game/ImageCache/
+/S1Game/Logs/
+/S1Game/Screenshots/
+
+# User settings
+/S1Game/Config/S1Engine.ini
+/S1Game/Config/S1Game.ini
+/S1Game/Config/S1Input.ini
+/S1Game/Config/S1Lightmass.ini
+/S1Game/Config/S1Option.ini
+/S1Game/Config/S1SystemSettings.ini
+/S1Game/Config/S1TBASettings.ini
+/S1Game/Config/S1UI.ini
+";
+
+/// Gitignore-style rules deciding which files of the game folder belong to the
+/// hash file.
+///
+/// The same rules drive manifest generation, verification and orphan
+/// detection. As in git, the last matching pattern wins, matching is case
+/// insensitive like Windows paths, and a file inside an ignored directory
+/// cannot be re-included.
+pub struct IgnoreRules {
+    matcher: Gitignore,
+}
+
+impl IgnoreRules {
+    /// Loads the built-in rules, then `.teraignore` from the game folder, then
+    /// the rules file named in the launcher config.
+    pub fn load(game_path: &Path, config_rules_file: Option<&Path>) -> Result<Self, String> {
+        let mut builder = Self::builder(game_path)?;
+
+        let game_rules_file = game_path.join(IGNORE_FILE_NAME);
+        for rules_file in [Some(game_rules_file.as_path()), config_rules_file].into_iter().flatten() {
+            if !rules_file.is_file() {
+                continue;
+            }
+            info!("Loading ignore rules from {:?}", rules_file);
+            if let Some(e) = builder.add(rules_file) {
+                return Err(format!("Invalid ignore rules in {:?}: {}", rules_file, e));
+            }
+        }
+
+        Self::build(builder)
+    }
+
+    fn builder(game_path: &Path) -> Result<GitignoreBuilder, String> {
+        let mut builder = GitignoreBuilder::new(game_path);
+        builder.case_insensitive(true).map_err(|e| e.to_string())?;
+
+        let generated = [QUARANTINE_DIR, STAGING_DIR, COMPRESSED_ARTIFACTS_DIR, PATCHES_DIR, CHUNK_STORE_DIR, ARCHIVE_DIR, LAUNCHER_DIR]
+            .iter()
+            .map(|dir| format!("/{}/", dir))
+            .collect::<Vec<_>>();
+        for line in DEFAULT_RULES.lines().chain(generated.iter().map(String::as_str)) {
+            builder.add_line(None, line).map_err(|e| format!("Invalid ignore rule {:?}: {}", line, e))?;
+        }
+        Ok(builder)
+    }
+
+    fn build(builder: GitignoreBuilder) -> Result<Self, String> {
+        let matcher = builder.build().map_err(|e| format!("Failed to build ignore rules: {}", e))?;
+        Ok(IgnoreRules { matcher })
+    }
+
+    /// Returns true if a path relative to the game folder is excluded, either
+    /// itself or through one of its parent directories.
+    pub fn is_ignored(&self, relative_path: &Path, is_dir: bool) -> bool {
+        let mut parents: Vec<&Path> = relative_path
+            .ancestors()
+            .skip(1)
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .collect();
+        parents.reverse();
+
+        parents.into_iter().any(|parent| self.matcher.matched(parent, true).is_ignore())
+            || self.matcher.matched(relative_path, is_dir).is_ignore()
+    }
+
+    /// Lists the files of the game folder that are not excluded, without
+    /// descending into excluded directories.
+    pub fn game_files(&self, game_path: &Path) -> Vec<PathBuf> {
+        WalkDir::new(game_path)
+            .into_iter()
+            .filter_entry(|e| match e.path().strip_prefix(game_path) {
+                Ok(relative) if !relative.as_os_str().is_empty() => !self.is_ignored(relative, e.file_type().is_dir()),
+                _ => true,
+            })
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .collect()
+    }
+
+    /// Returns true if a `/` or `\` separated manifest path is excluded.
+    pub fn is_ignored_manifest_path(&self, path: &str) -> bool {
+        let relative: PathBuf = path.split(['/', '\\']).collect();
+        self.is_ignored(&relative, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Built-in rules followed by `extra`, one pattern per line.
+    fn rules(extra: &str) -> IgnoreRules {
+        let mut builder = IgnoreRules::builder(Path::new("game")).unwrap();
+        for line in extra.lines() {
+            builder.add_line(None, line).unwrap();
+        }
+        IgnoreRules::build(builder).unwrap()
+    }
+
+    #[test]
+    fn default_rules_skip_launcher_and_user_files() {
+        let rules = rules("");
+        for path in [
+            "hash-file.json",
+            "hash-file.from-3.json",
+            "hash-file.json.sig",
+            "version.json",
+            "Launcher.exe",
+            "teralauncher.exe",
+            "teralauncher.exe.old",
+            "tera_launcher.exe",
+            "tera_config.ini",
+            "launcher.log",
+            "$Patch/patch.bin",
+            "S1Game/Logs/2024/client.log",
+            "S1Game/Config/S1Option.ini",
+            "$Quarantine/1700000000/S1Game/old.gpk",
+            "compressed/ab/abcdef.zst",
+            "patches/S1Game/S1Data.gpk.from-3.bsdiff",
+            "chunks/ab/abcdef",
+        ] {
+            assert!(rules.is_ignored_manifest_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn root_files_are_not_skipped() {
+        let rules = rules("");
+        for path in ["TERA.exe", "steam_api.dll", "Binaries/TERA.exe", "S1Game/Config/DefaultEngine.ini"] {
+            assert!(!rules.is_ignored_manifest_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn anchored_patterns_only_match_at_the_root() {
+        let rules = rules("");
+        assert!(rules.is_ignored_manifest_path("Launcher.exe"));
+        assert!(!rules.is_ignored_manifest_path("Binaries/Launcher.exe"));
+        assert!(!rules.is_ignored_manifest_path("Mods/S1Game/Logs/readme.txt"));
+    }
+
+    #[test]
+    fn unanchored_globs_match_at_any_depth() {
+        let rules = rules("*.log\nThumbs.db");
+        assert!(rules.is_ignored_manifest_path("crash.log"));
+        assert!(rules.is_ignored_manifest_path("Binaries/crash.log"));
+        assert!(rules.is_ignored_manifest_path("S1Game/Art/Thumbs.db"));
+        assert!(!rules.is_ignored_manifest_path("S1Game/log.txt"));
+    }
+
+    #[test]
+    fn directory_patterns_only_match_directories() {
+        let rules = rules("Cache/");
+        assert!(rules.is_ignored_manifest_path("S1Game/Cache/file.bin"));
+        assert!(rules.is_ignored(Path::new("S1Game/Cache"), true));
+        assert!(!rules.is_ignored(Path::new("S1Game/Cache"), false));
+    }
+
+    #[test]
+    fn later_rules_win_and_negation_re_includes() {
+        let rules = rules("!/S1Game/Config/S1Option.ini\n*.tmp\n!keep.tmp");
+        assert!(!rules.is_ignored_manifest_path("S1Game/Config/S1Option.ini"));
+        assert!(rules.is_ignored_manifest_path("S1Game/Config/S1Input.ini"));
+        assert!(rules.is_ignored_manifest_path("Binaries/build.tmp"));
+        assert!(!rules.is_ignored_manifest_path("Binaries/keep.tmp"));
+    }
+
+    #[test]
+    fn files_in_ignored_directories_cannot_be_re_included() {
+        let rules = rules("!/S1Game/Logs/keep.log");
+        assert!(rules.is_ignored_manifest_path("S1Game/Logs/keep.log"));
+    }
+
+    #[test]
+    fn matching_is_case_insensitive() {
+        let rules = rules("");
+        assert!(rules.is_ignored_manifest_path("launcher.EXE"));
+        assert!(rules.is_ignored_manifest_path("s1game/logs/client.log"));
+        assert!(rules.is_ignored_manifest_path("S1Game\\Screenshots\\shot.png"));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_ignored() {
+        let rules = rules("# *.gpk\n\n   \n");
+        assert!(!rules.is_ignored_manifest_path("S1Game/S1Data.gpk"));
+    }
+
+    #[test]
+    fn loads_rules_from_the_game_folder_and_config() {
+        let game_path = std::env::temp_dir().join(format!("teralaunch-ignore-{}", std::process::id()));
+        std::fs::create_dir_all(&game_path).unwrap();
+        std::fs::write(game_path.join(IGNORE_FILE_NAME), "/Mods/\n").unwrap();
+        let config_rules = game_path.join("launcher.ignore");
+        std::fs::write(&config_rules, "!/Mods/\n*.bak\n").unwrap();
+
+        let game_only = IgnoreRules::load(&game_path, None).unwrap();
+        assert!(game_only.is_ignored_manifest_path("Mods/mod.gpk"));
+        assert!(!game_only.is_ignored_manifest_path("S1Game/S1Data.bak"));
+
+        let both = IgnoreRules::load(&game_path, Some(&config_rules)).unwrap();
+        assert!(!both.is_ignored_manifest_path("Mods/mod.gpk"));
+        assert!(both.is_ignored_manifest_path("S1Game/S1Data.bak"));
+
+        let missing = IgnoreRules::load(&game_path, Some(&game_path.join("missing.ignore"))).unwrap();
+        assert!(missing.is_ignored_manifest_path("Mods/mod.gpk"));
+
+        std::fs::remove_dir_all(&game_path).unwrap();
+    }
+
+    #[test]
+    fn game_files_skips_excluded_files_and_directories() {
+        let game_path = std::env::temp_dir().join(format!("teralaunch-walk-{}", std::process::id()));
+        for file in ["TERA.exe", "Launcher.exe", "S1Game/S1Data.gpk", "S1Game/Logs/client.log", "S1Game/Config/S1UI.ini"] {
+            let path = game_path.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+
+        let rules = IgnoreRules::load(&game_path, None).unwrap();
+        let mut files: Vec<String> = rules
+            .game_files(&game_path)
+            .iter()
+            .map(|path| path.strip_prefix(&game_path).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        files.sort();
+        assert_eq!(files, ["S1Game/S1Data.gpk", "TERA.exe"]);
+
+        std::fs::remove_dir_all(&game_path).unwrap();
+    }
+}
@@ -0,0 +1,344 @@
+// Standard library imports
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party imports
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::hashing::{hash_file, HashAlgorithm};
+
+/// Signed description of the latest launcher build, published next to the hash file.
+pub const LAUNCHER_FILE_NAME: &str = "launcher.json";
+
+/// Folder of the publish tree holding launcher builds, published under
+/// `<base URL>/launcher/`.
+pub const LAUNCHER_DIR: &str = "launcher";
+
+/// Journal of a launcher swap, written next to the launcher binary.
+const SWAP_JOURNAL_NAME: &str = "launcher-update.json";
+
+/// How long the previous launcher waits for the new one to report a
+/// successful start before rolling back.
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A launcher build offered for self-update.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LauncherRelease {
+    /// Dotted version, compared with the `package.version` of the running launcher.
+    pub version: String,
+    pub url: String,
+    pub size: u64,
+    pub hash: String,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_notes: Option<String>,
+}
+
+/// Compares dotted versions number by number, e.g. `0.0.10` > `0.0.9`.
+/// Missing or non-numeric parts count as 0.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.trim().parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Copies a launcher build into `<output>/launcher/` and describes it.
+/// The caller writes the result as `launcher.json` and signs it.
+pub fn publish_launcher(
+    binary: &Path,
+    output: &Path,
+    base_url: &str,
+    version: &str,
+    release_notes: Option<String>,
+    hash_algorithm: HashAlgorithm,
+) -> Result<LauncherRelease, String> {
+    let file_name = binary
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid launcher binary {:?}", binary))?;
+    let folder = output.join(LAUNCHER_DIR).join(version);
+    fs::create_dir_all(&folder).map_err(|e| format!("Failed to create {:?}: {}", folder, e))?;
+    let target = folder.join(file_name);
+    fs::copy(binary, &target).map_err(|e| format!("Failed to copy {:?}: {}", binary, e))?;
+    info!("Published launcher {} to {:?}", version, target);
+
+    Ok(LauncherRelease {
+        version: version.to_string(),
+        url: format!("{}/{}/{}/{}", base_url.trim_end_matches('/'), LAUNCHER_DIR, version, file_name),
+        size: fs::metadata(&target).map_err(|e| e.to_string())?.len(),
+        hash: hash_file(&target, hash_algorithm)?,
+        hash_algorithm,
+        release_notes,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapState {
+    /// The new build was moved into place and has not reported a start yet.
+    Trial,
+    /// The new build started; the previous one is removed on the next start.
+    Confirmed,
+    /// The new build failed to start and the previous one was restored.
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapJournal {
+    pub state: SwapState,
+    pub version: String,
+    pub previous_version: String,
+}
+
+/// Replaces the running launcher binary with a downloaded build.
+///
+/// A running executable cannot be overwritten on Windows, but it can be
+/// renamed. The new build is downloaded to `<exe>.new`; on restart the
+/// current binary becomes `<exe>.old` and the new one takes its name. The
+/// previous launcher then waits for the new one to confirm its start, and
+/// puts itself back in place if that does not happen.
+#[derive(Debug, Clone)]
+pub struct LauncherSwap {
+    exe_path: PathBuf,
+}
+
+impl LauncherSwap {
+    pub fn new(exe_path: &Path) -> Self {
+        LauncherSwap { exe_path: exe_path.to_path_buf() }
+    }
+
+    pub fn exe_path(&self) -> &Path {
+        &self.exe_path
+    }
+
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut name = self.exe_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(extension);
+        self.exe_path.with_file_name(name)
+    }
+
+    /// Where the new build is downloaded to.
+    pub fn staged_path(&self) -> PathBuf {
+        self.sibling("new")
+    }
+
+    fn backup_path(&self) -> PathBuf {
+        self.sibling("old")
+    }
+
+    fn failed_path(&self) -> PathBuf {
+        self.sibling("failed")
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.exe_path.with_file_name(SWAP_JOURNAL_NAME)
+    }
+
+    pub fn read_journal(&self) -> Result<Option<SwapJournal>, String> {
+        match fs::read(self.journal_path()) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map(Some)
+                .map_err(|e| format!("Invalid launcher update journal: {}", e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read the launcher update journal: {}", e)),
+        }
+    }
+
+    fn write_journal(&self, journal: &SwapJournal) -> Result<(), String> {
+        let path = self.journal_path();
+        let temp_path = path.with_extension("json.tmp");
+        let contents = serde_json::to_vec_pretty(journal).map_err(|e| e.to_string())?;
+        let mut file = File::create(&temp_path).map_err(|e| format!("Failed to write {:?}: {}", temp_path, e))?;
+        file.write_all(&contents).map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| e.to_string())?;
+        drop(file);
+        fs::rename(&temp_path, &path).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    /// Returns true if the staged build matches the release.
+    pub fn verify_staged(&self, release: &LauncherRelease) -> Result<bool, String> {
+        let staged = self.staged_path();
+        match fs::metadata(&staged) {
+            Ok(metadata) if metadata.len() == release.size => Ok(hash_file(&staged, release.hash_algorithm)? == release.hash),
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("Failed to read {:?}: {}", staged, e)),
+        }
+    }
+
+    /// Moves the staged build into place, keeping the current one as backup.
+    pub fn swap(&self, version: &str, previous_version: &str) -> Result<(), String> {
+        let staged = self.staged_path();
+        if !staged.exists() {
+            return Err(format!("No launcher build staged at {:?}", staged));
+        }
+        // Leftovers of an earlier swap are gone by now unless they were still in use
+        self.cleanup()?;
+        remove_file(&self.backup_path())?;
+
+        self.write_journal(&SwapJournal {
+            state: SwapState::Trial,
+            version: version.to_string(),
+            previous_version: previous_version.to_string(),
+        })?;
+        fs::rename(&self.exe_path, self.backup_path())
+            .map_err(|e| format!("Failed to move {:?} aside: {}", self.exe_path, e))?;
+        if let Err(e) = fs::rename(&staged, &self.exe_path) {
+            let _ = fs::rename(self.backup_path(), &self.exe_path);
+            let _ = fs::remove_file(self.journal_path());
+            return Err(format!("Failed to move the new launcher into place: {}", e));
+        }
+        info!("Launcher {} moved into place, {} kept as backup", version, previous_version);
+        Ok(())
+    }
+
+    /// Called by a starting launcher: confirms a build on trial.
+    ///
+    /// # Returns
+    ///
+    /// The version that was confirmed, if a swap was waiting for it.
+    pub fn confirm(&self) -> Result<Option<String>, String> {
+        match self.read_journal()? {
+            Some(mut journal) if journal.state == SwapState::Trial => {
+                journal.state = SwapState::Confirmed;
+                self.write_journal(&journal)?;
+                info!("Launcher {} confirmed its start", journal.version);
+                Ok(Some(journal.version))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Puts the previous build back in place. The failed build is kept as
+    /// `<exe>.failed` until the next cleanup.
+    pub fn roll_back(&self) -> Result<(), String> {
+        let mut journal = self.read_journal()?.ok_or("No launcher update to roll back")?;
+        let backup = self.backup_path();
+        if !backup.exists() {
+            return Err(format!("Previous launcher {:?} not found", backup));
+        }
+
+        remove_file(&self.failed_path())?;
+        fs::rename(&self.exe_path, self.failed_path())
+            .map_err(|e| format!("Failed to move the failed launcher aside: {}", e))?;
+        fs::rename(&backup, &self.exe_path).map_err(|e| format!("Failed to restore the previous launcher: {}", e))?;
+
+        journal.state = SwapState::RolledBack;
+        self.write_journal(&journal)?;
+        warn!("Launcher {} failed to start, restored {}", journal.version, journal.previous_version);
+        Ok(())
+    }
+
+    /// Waits for the launcher started from the new build to confirm its start.
+    /// Gives up when the process exits first or after `timeout`, killing it.
+    ///
+    /// # Returns
+    ///
+    /// True if the new build confirmed.
+    pub fn wait_for_confirmation(&self, child: &mut Child, timeout: Duration) -> Result<bool, String> {
+        let started = Instant::now();
+        loop {
+            if self.read_journal()?.is_some_and(|journal| journal.state == SwapState::Confirmed) {
+                return Ok(true);
+            }
+            if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+                warn!("New launcher exited with {} before confirming its start", status);
+                return Ok(false);
+            }
+            if started.elapsed() >= timeout {
+                warn!("New launcher did not confirm its start within {:?}", timeout);
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(false);
+            }
+            thread::sleep(CONFIRM_POLL_INTERVAL);
+        }
+    }
+
+    /// Removes the files of a finished swap, confirmed or rolled back.
+    /// A swap still on trial is left alone.
+    ///
+    /// # Returns
+    ///
+    /// The journal of the finished swap, if any.
+    pub fn cleanup(&self) -> Result<Option<SwapJournal>, String> {
+        let journal = match self.read_journal()? {
+            Some(journal) if journal.state != SwapState::Trial => journal,
+            _ => return Ok(None),
+        };
+        remove_file(&self.backup_path())?;
+        remove_file(&self.failed_path())?;
+        remove_file(&self.journal_path())?;
+        Ok(Some(journal))
+    }
+}
+
+fn remove_file(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(format!("Failed to remove {:?}: {}", path, e)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_dotted_versions() {
+        assert_eq!(compare_versions("0.0.10", "0.0.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("v0.1.0", "0.0.6"), Ordering::Greater);
+        assert_eq!(compare_versions("0.0.6", "0.0.7"), Ordering::Less);
+    }
+
+    #[test]
+    fn swaps_confirms_and_rolls_back() {
+        let base = std::env::temp_dir().join(format!("teralaunch-launcher-update-{}", std::process::id()));
+        fs::create_dir_all(&base).unwrap();
+        let exe = base.join("Launcher.exe");
+        let swap = LauncherSwap::new(&exe);
+
+        // A confirmed swap keeps the backup until the next cleanup
+        fs::write(&exe, "v1").unwrap();
+        fs::write(swap.staged_path(), "v2").unwrap();
+        swap.swap("2", "1").unwrap();
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "v2");
+        assert_eq!(swap.cleanup().unwrap().map(|j| j.version), None);
+        assert_eq!(swap.confirm().unwrap(), Some("2".to_string()));
+        assert_eq!(swap.confirm().unwrap(), None);
+        assert_eq!(swap.cleanup().unwrap().map(|j| j.state), Some(SwapState::Confirmed));
+        assert!(!swap.backup_path().exists());
+
+        // A failed start restores the previous build
+        fs::write(swap.staged_path(), "v3").unwrap();
+        swap.swap("3", "2").unwrap();
+        swap.roll_back().unwrap();
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "v2");
+        assert_eq!(swap.cleanup().unwrap().map(|j| j.state), Some(SwapState::RolledBack));
+        assert!(!swap.failed_path().exists());
+        assert_eq!(swap.read_journal().unwrap().map(|j| j.state), None);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+// Standard library imports
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::manifest::FileInfo;
+use super::paths::sanitize_manifest_path;
+
+/// Most manifest files looked up in each candidate folder. They are spread
+/// over the whole manifest, so a partial copy still scores.
+const PROBE_FILES: usize = 256;
+
+/// How deep below the selected folder the client is searched, enough for
+/// e.g. `<selected>/TERA/Client`.
+const SEARCH_DEPTH: usize = 2;
+
+/// How many parents of the selected folder are tried, for players picking
+/// e.g. `Binaries` instead of the client root.
+const PARENT_DEPTH: usize = 2;
+
+/// Folder found to hold an existing copy of the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientLayout {
+    /// Client root, the folder manifest paths are relative to.
+    pub root: PathBuf,
+    /// Probed manifest files present below `root`.
+    pub found: usize,
+    pub probed: usize,
+}
+
+/// Picks the manifest paths looked up in each candidate, evenly spread.
+fn probe_paths(files: &[FileInfo]) -> Vec<PathBuf> {
+    let step = (files.len() / PROBE_FILES).max(1);
+    files
+        .iter()
+        .step_by(step)
+        .take(PROBE_FILES)
+        .filter_map(|file_info| sanitize_manifest_path(&file_info.path).ok())
+        .collect()
+}
+
+/// Appends the sub folders of `folder` down to `depth` levels, breadth first.
+fn collect_sub_folders(folder: &Path, depth: usize, candidates: &mut Vec<PathBuf>) {
+    let mut level = vec![folder.to_path_buf()];
+    for _ in 0..depth {
+        let mut next = Vec::new();
+        for parent in &level {
+            let entries = match fs::read_dir(parent) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            let mut sub_folders: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+                .map(|entry| entry.path())
+                .collect();
+            sub_folders.sort();
+            next.extend(sub_folders);
+        }
+        candidates.extend(next.iter().cloned());
+        level = next;
+    }
+}
+
+/// Looks for an existing client in or around the folder picked by the user.
+///
+/// The selected folder, its sub folders and a few of its parents are scored
+/// by how many manifest files they hold. On a tie the selected folder wins,
+/// then the shallowest sub folder, then the closest parent.
+///
+/// # Returns
+///
+/// The best candidate, or `None` if no candidate holds any manifest file.
+pub fn detect_client_layout(selected: &Path, files: &[FileInfo]) -> Option<ClientLayout> {
+    let probes = probe_paths(files);
+
+    let mut candidates = vec![selected.to_path_buf()];
+    collect_sub_folders(selected, SEARCH_DEPTH, &mut candidates);
+    candidates.extend(selected.ancestors().skip(1).take(PARENT_DEPTH).map(Path::to_path_buf));
+
+    let mut best: Option<ClientLayout> = None;
+    for root in candidates {
+        let found = probes.iter().filter(|path| root.join(path).is_file()).count();
+        if found > best.as_ref().map_or(0, |layout| layout.found) {
+            best = Some(ClientLayout { root, found, probed: probes.len() });
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::hashing::HashAlgorithm;
+
+    fn file(path: &str) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            hash: String::new(),
+            size: 0,
+            url: String::new(),
+            patches: Vec::new(),
+            compressed: None,
+            chunks: Vec::new(),
+            hash_algorithm: HashAlgorithm::default(),
+        }
+    }
+
+    #[test]
+    fn detects_the_client_around_the_selected_folder() {
+        let base = std::env::temp_dir().join(format!("teralaunch-layout-{}", std::process::id()));
+        let client = base.join("Games/TERA/Client");
+        fs::create_dir_all(client.join("Binaries")).unwrap();
+        fs::create_dir_all(client.join("S1Game/CookedPC")).unwrap();
+        fs::create_dir_all(base.join("Empty")).unwrap();
+        fs::write(client.join("Binaries/TERA.exe"), "exe").unwrap();
+        fs::write(client.join("S1Game/CookedPC/S1Data.gpk"), "data").unwrap();
+
+        let files = [file("Binaries/TERA.exe"), file("S1Game/CookedPC/S1Data.gpk"), file("S1Game/Missing.gpk")];
+
+        // Nested below the selected folder
+        let layout = detect_client_layout(&base.join("Games"), &files).unwrap();
+        assert_eq!(layout.root, client);
+        assert_eq!((layout.found, layout.probed), (2, 3));
+
+        // A folder inside the client
+        assert_eq!(detect_client_layout(&client.join("Binaries"), &files).unwrap().root, client);
+
+        assert_eq!(detect_client_layout(&base.join("Empty"), &files), None);
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+// Standard library imports
+use std::collections::HashMap;
+
+// Third-party imports
+use serde::{Deserialize, Serialize};
+
+use super::chunks::ChunkInfo;
+use super::compression::CompressedInfo;
+use super::delta::PatchInfo;
+use super::hashing::HashAlgorithm;
+
+/// File name of the full manifest written by the generator.
+pub const MANIFEST_FILE_NAME: &str = "hash-file.json";
+
+/// File name of the version head published next to the manifest.
+pub const VERSION_FILE_NAME: &str = "version.json";
+
+/// Maximum number of deltas followed before falling back to the full manifest.
+pub const MAX_DELTA_CHAIN: usize = 64;
+
+/// An entry of the hash file.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileInfo {
+    pub path: String,
+    pub hash: String,
+    pub size: u64,
+    pub url: String,
+    /// Binary diffs from older versions of the file, see `delta`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<PatchInfo>,
+    /// Compressed variant to download instead of `url`, see `compression`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed: Option<CompressedInfo>,
+    /// Content-defined chunks of the file in chunked manifest mode, see `chunks`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<ChunkInfo>,
+    /// Algorithm of `hash`, taken from the manifest the entry was loaded from.
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_sha256")]
+    pub hash_algorithm: HashAlgorithm,
+}
+
+impl FileInfo {
+    /// Returns the URL the file is transferred from, preferring the compressed variant.
+    pub fn transfer_url(&self) -> &str {
+        self.compressed.as_ref().map_or(&self.url, |c| &c.url)
+    }
+
+    /// Returns the number of bytes transferred for a full download of the file.
+    pub fn transfer_size(&self) -> u64 {
+        self.compressed.as_ref().map_or(self.size, |c| c.size)
+    }
+}
+
+/// The full manifest (`hash-file.json`).
+///
+/// Manifests from before versioning only carry `files` and get version 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Algorithm of all file hashes, SHA-256 unless stated otherwise.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// Build number of the release.
+    #[serde(default)]
+    pub version: u64,
+    /// Build number of the release this one was generated on top of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_version: Option<u64>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub release_notes: String,
+    pub files: Vec<FileInfo>,
+}
+
+/// Head of the latest release (`version.json`), small enough to be checked on
+/// every start.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub version: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_version: Option<u64>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub release_notes: String,
+}
+
+impl From<&Manifest> for VersionInfo {
+    fn from(manifest: &Manifest) -> Self {
+        VersionInfo {
+            version: manifest.version,
+            parent_version: manifest.parent_version,
+            release_notes: manifest.release_notes.clone(),
+        }
+    }
+}
+
+/// Entries that changed between two releases (`hash-file.from-<version>.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestDelta {
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    pub from_version: u64,
+    pub version: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub release_notes: String,
+    /// Entries added or changed since `from_version`.
+    pub files: Vec<FileInfo>,
+    /// Paths that no longer exist in `version`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+impl ManifestDelta {
+    /// Computes the delta turning `previous` into `current`.
+    pub fn between(previous: &Manifest, current: &Manifest) -> Self {
+        let previous_hashes: HashMap<&str, &str> = previous
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.hash.as_str()))
+            .collect();
+        let current_paths: HashMap<&str, ()> = current.files.iter().map(|f| (f.path.as_str(), ())).collect();
+
+        ManifestDelta {
+            hash_algorithm: current.hash_algorithm,
+            from_version: previous.version,
+            version: current.version,
+            release_notes: current.release_notes.clone(),
+            files: current
+                .files
+                .iter()
+                .filter(|f| {
+                    previous.hash_algorithm != current.hash_algorithm
+                        || previous_hashes.get(f.path.as_str()) != Some(&f.hash.as_str())
+                })
+                .cloned()
+                .collect(),
+            removed: previous
+                .files
+                .iter()
+                .filter(|f| !current_paths.contains_key(f.path.as_str()))
+                .map(|f| f.path.clone())
+                .collect(),
+        }
+    }
+
+    /// Appends the delta of the following release.
+    pub fn chain(mut self, next: ManifestDelta) -> Result<Self, String> {
+        if next.from_version != self.version {
+            return Err(format!(
+                "Delta chain broken: expected a delta from {}, got one from {}",
+                self.version, next.from_version
+            ));
+        }
+
+        let next_paths: HashMap<&str, ()> = next.files.iter().map(|f| (f.path.as_str(), ())).collect();
+        self.files.retain(|f| !next_paths.contains_key(f.path.as_str()) && !next.removed.contains(&f.path));
+        self.removed.retain(|path| !next_paths.contains_key(path.as_str()));
+        self.removed.extend(next.removed);
+        self.files.extend(next.files);
+
+        // Newest notes first
+        if !next.release_notes.is_empty() {
+            self.release_notes = if self.release_notes.is_empty() {
+                next.release_notes
+            } else {
+                format!("{}\n\n{}", next.release_notes, self.release_notes)
+            };
+        }
+        self.version = next.version;
+        self.hash_algorithm = next.hash_algorithm;
+        Ok(self)
+    }
+}
+
+/// Returns the file name of the delta from `from_version` to its next release.
+pub fn delta_file_name(from_version: u64) -> String {
+    format!("hash-file.from-{}.json", from_version)
+}
+
+/// Returns the URL of a document published next to the manifest.
+pub fn sibling_url(hash_file_url: &str, file_name: &str) -> String {
+    match hash_file_url.rsplit_once('/') {
+        Some((base, _)) => format!("{}/{}", base, file_name),
+        None => file_name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, hash: &str) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            hash: hash.to_string(),
+            size: 1,
+            url: format!("files/{}", path),
+            patches: Vec::new(),
+            compressed: None,
+            chunks: Vec::new(),
+            hash_algorithm: HashAlgorithm::Sha256,
+        }
+    }
+
+    fn manifest(version: u64, hash_algorithm: HashAlgorithm, files: &[(&str, &str)]) -> Manifest {
+        Manifest {
+            hash_algorithm,
+            version,
+            parent_version: version.checked_sub(1),
+            release_notes: format!("Release {}", version),
+            files: files.iter().map(|(path, hash)| file(path, hash)).collect(),
+        }
+    }
+
+    fn paths(files: &[FileInfo]) -> Vec<&str> {
+        let mut paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn between_lists_changed_added_and_removed_files() {
+        let v1 = manifest(1, HashAlgorithm::Sha256, &[("a", "1"), ("b", "1"), ("S1Game/x.ini", "1")]);
+        let v2 = manifest(2, HashAlgorithm::Sha256, &[("a", "1"), ("b", "2"), ("c", "1")]);
+
+        let delta = ManifestDelta::between(&v1, &v2);
+        assert_eq!((delta.from_version, delta.version), (1, 2));
+        assert_eq!(paths(&delta.files), ["b", "c"]);
+        assert_eq!(delta.removed, ["S1Game/x.ini"]);
+    }
+
+    #[test]
+    fn broken_chain_is_refused() {
+        let v1 = manifest(1, HashAlgorithm::Sha256, &[("a", "1")]);
+        let v2 = manifest(2, HashAlgorithm::Sha256, &[("a", "2")]);
+        let v3 = manifest(3, HashAlgorithm::Sha256, &[("a", "3")]);
+
+        let result = ManifestDelta::between(&v1, &v2).chain(ManifestDelta::between(&v1, &v3));
+        assert!(result.unwrap_err().contains("expected a delta from 2, got one from 1"));
+    }
+
+    #[test]
+    fn path_removed_then_added_again_is_downloaded() {
+        let v1 = manifest(1, HashAlgorithm::Sha256, &[("a", "1"), ("b", "1")]);
+        let v2 = manifest(2, HashAlgorithm::Sha256, &[("a", "1")]);
+        let v3 = manifest(3, HashAlgorithm::Sha256, &[("a", "1"), ("b", "3")]);
+
+        let delta = ManifestDelta::between(&v1, &v2)
+            .chain(ManifestDelta::between(&v2, &v3))
+            .unwrap();
+        assert_eq!(delta.version, 3);
+        assert_eq!(paths(&delta.files), ["b"]);
+        assert_eq!(delta.files[0].hash, "3");
+        assert!(delta.removed.is_empty());
+        assert_eq!(delta.release_notes, "Release 3\n\nRelease 2");
+    }
+
+    #[test]
+    fn path_added_then_removed_is_not_downloaded() {
+        let v1 = manifest(1, HashAlgorithm::Sha256, &[("a", "1")]);
+        let v2 = manifest(2, HashAlgorithm::Sha256, &[("a", "1"), ("b", "2")]);
+        let v3 = manifest(3, HashAlgorithm::Sha256, &[("a", "1")]);
+
+        let delta = ManifestDelta::between(&v1, &v2)
+            .chain(ManifestDelta::between(&v2, &v3))
+            .unwrap();
+        assert!(delta.files.is_empty());
+        assert_eq!(delta.removed, ["b"]);
+    }
+
+    #[test]
+    fn hash_algorithm_switch_lists_every_file() {
+        let v1 = manifest(1, HashAlgorithm::Sha256, &[("a", "1"), ("b", "1")]);
+        let v2 = manifest(2, HashAlgorithm::Sha256, &[("a", "2"), ("b", "1")]);
+        let v3 = manifest(3, HashAlgorithm::Blake3, &[("a", "2"), ("b", "1")]);
+
+        let switch = ManifestDelta::between(&v2, &v3);
+        assert_eq!(switch.hash_algorithm, HashAlgorithm::Blake3);
+        assert_eq!(paths(&switch.files), ["a", "b"]);
+
+        let delta = ManifestDelta::between(&v1, &v2).chain(switch).unwrap();
+        assert_eq!(delta.hash_algorithm, HashAlgorithm::Blake3);
+        assert_eq!(paths(&delta.files), ["a", "b"]);
+    }
+}
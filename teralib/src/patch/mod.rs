@@ -0,0 +1,19 @@
+pub mod archive;
+pub mod builder;
+pub mod chunk_index;
+pub mod chunks;
+pub mod compression;
+pub mod delta;
+pub mod download;
+pub mod hash_cache;
+pub mod hashing;
+pub mod ignore_rules;
+pub mod launcher_update;
+pub mod layout;
+pub mod manifest;
+pub mod orphans;
+pub mod paths;
+pub mod preflight;
+pub mod server;
+pub mod signing;
+pub mod staging;
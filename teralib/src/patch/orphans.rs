@@ -0,0 +1,209 @@
+// Standard library imports
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Third-party imports
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::ignore_rules::{IgnoreRules, QUARANTINE_DIR};
+use super::manifest::FileInfo;
+
+/// A local file that is not listed in the server manifest.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedFile {
+    /// Path relative to the game folder, `/` separated like manifest paths.
+    pub path: String,
+    pub size: u64,
+}
+
+/// What to do with orphaned files.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanAction {
+    /// Move them below `QUARANTINE_DIR`, so they can be restored by hand.
+    Quarantine,
+    Delete,
+}
+
+/// Manifest paths are compared case-insensitively, like Windows resolves them.
+fn manifest_key(path: &str) -> String {
+    path.replace('\\', "/").to_lowercase()
+}
+
+/// Returns the path of the running launcher relative to the game folder, if it
+/// was started from there. Users rename it freely, so no ignore rule covers it.
+fn running_launcher(game_path: &Path) -> Option<String> {
+    let exe = env::current_exe().ok()?.canonicalize().ok()?;
+    let relative = exe.strip_prefix(game_path.canonicalize().ok()?).ok()?;
+    Some(manifest_key(&relative.to_string_lossy()))
+}
+
+/// Lists the files of the game folder that are not part of the manifest.
+/// Files excluded by the ignore rules and the running launcher, with the
+/// `.new` and `.old` copies of a self-update, are never reported.
+pub fn find_orphans(game_path: &Path, manifest: &[FileInfo], rules: &IgnoreRules) -> Vec<OrphanedFile> {
+    collect_orphans(game_path, manifest, rules, running_launcher(game_path).as_deref())
+}
+
+fn collect_orphans(game_path: &Path, manifest: &[FileInfo], rules: &IgnoreRules, launcher: Option<&str>) -> Vec<OrphanedFile> {
+    let mut known: HashSet<String> = manifest.iter().map(|f| manifest_key(&f.path)).collect();
+    if let Some(launcher) = launcher {
+        known.extend(["", ".new", ".old"].map(|suffix| format!("{}{}", launcher, suffix)));
+    }
+
+    let orphans: Vec<OrphanedFile> = rules
+        .game_files(game_path)
+        .into_iter()
+        .filter_map(|path| {
+            let relative = path.strip_prefix(game_path).ok()?.to_string_lossy().replace('\\', "/");
+            if known.contains(&manifest_key(&relative)) {
+                return None;
+            }
+            Some(OrphanedFile {
+                size: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                path: relative,
+            })
+        })
+        .collect();
+
+    info!("Found {} orphaned file(s)", orphans.len());
+    orphans
+}
+
+/// Moves or deletes orphaned files.
+///
+/// Files that fail are logged and skipped, so one locked file does not stop
+/// the cleanup.
+///
+/// # Returns
+///
+/// The paths that were handled, and the quarantine folder if files were moved.
+pub fn clean_orphans(
+    game_path: &Path,
+    orphans: &[OrphanedFile],
+    action: OrphanAction,
+) -> Result<(Vec<String>, Option<PathBuf>), String> {
+    let quarantine_path = match action {
+        OrphanAction::Quarantine => {
+            let run = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?
+                .as_secs();
+            Some(game_path.join(QUARANTINE_DIR).join(run.to_string()))
+        }
+        OrphanAction::Delete => None,
+    };
+
+    let mut cleaned = Vec::new();
+    for orphan in orphans {
+        let source = game_path.join(&orphan.path);
+        let result = match &quarantine_path {
+            Some(quarantine_path) => {
+                let target = quarantine_path.join(&orphan.path);
+                target
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::rename(&source, &target))
+            }
+            None => fs::remove_file(&source),
+        };
+
+        match result {
+            Ok(()) => cleaned.push(orphan.path.clone()),
+            Err(e) => warn!("Failed to {:?} orphaned file {}: {}", action, orphan.path, e),
+        }
+    }
+
+    info!("{:?}: {} of {} orphaned file(s)", action, cleaned.len(), orphans.len());
+    Ok((cleaned, quarantine_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_info(path: &str) -> FileInfo {
+        serde_json::from_value(serde_json::json!({ "path": path, "hash": "", "size": 0, "url": "" })).unwrap()
+    }
+
+    fn game_folder(name: &str, files: &[&str]) -> PathBuf {
+        let game_path = std::env::temp_dir().join(format!("teralaunch-orphans-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&game_path);
+        for file in files {
+            let path = game_path.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file.as_bytes()).unwrap();
+        }
+        game_path
+    }
+
+    fn paths(orphans: &[OrphanedFile]) -> Vec<&str> {
+        let mut paths: Vec<&str> = orphans.iter().map(|o| o.path.as_str()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn launcher_config_and_logs_are_never_orphans() {
+        let game_path = game_folder(
+            "protected",
+            &[
+                "TERA.exe",
+                "S1Game/S1Data.gpk",
+                "S1Game/Old.gpk",
+                "Readme.txt",
+                "tera_config.ini",
+                "teralauncher.exe",
+                "launcher.log",
+                "MyLauncher.exe",
+                "MyLauncher.exe.old",
+                "S1Game/Logs/client.log",
+            ],
+        );
+        // Manifest paths match whatever their case or separators
+        let manifest = [file_info("TERA.exe"), file_info("s1game\\s1data.gpk")];
+        let rules = IgnoreRules::load(&game_path, None).unwrap();
+
+        let orphans = collect_orphans(&game_path, &manifest, &rules, Some("mylauncher.exe"));
+        assert_eq!(paths(&orphans), ["Readme.txt", "S1Game/Old.gpk"]);
+        assert_eq!(orphans.iter().find(|o| o.path == "Readme.txt").unwrap().size, 10);
+
+        // Without the running launcher in the folder, a renamed copy is just another file
+        let orphans = collect_orphans(&game_path, &manifest, &rules, None);
+        assert_eq!(paths(&orphans), ["MyLauncher.exe", "MyLauncher.exe.old", "Readme.txt", "S1Game/Old.gpk"]);
+
+        fs::remove_dir_all(&game_path).unwrap();
+    }
+
+    #[test]
+    fn orphans_are_quarantined_or_deleted() {
+        let game_path = game_folder("clean", &["S1Game/Old.gpk", "Old.dll", "TERA.exe"]);
+        let orphans = [
+            OrphanedFile { path: "S1Game/Old.gpk".to_string(), size: 14 },
+            OrphanedFile { path: "Missing.gpk".to_string(), size: 0 },
+        ];
+
+        let (cleaned, quarantine_path) = clean_orphans(&game_path, &orphans, OrphanAction::Quarantine).unwrap();
+        assert_eq!(cleaned, ["S1Game/Old.gpk"]);
+        let quarantine_path = quarantine_path.unwrap();
+        assert!(quarantine_path.starts_with(game_path.join(QUARANTINE_DIR)));
+        assert!(quarantine_path.join("S1Game/Old.gpk").is_file());
+        assert!(!game_path.join("S1Game/Old.gpk").exists());
+
+        // The quarantine itself is not reported again
+        let rules = IgnoreRules::load(&game_path, None).unwrap();
+        assert_eq!(paths(&find_orphans(&game_path, &[file_info("TERA.exe")], &rules)), ["Old.dll"]);
+
+        let orphans = [OrphanedFile { path: "Old.dll".to_string(), size: 7 }];
+        let (cleaned, quarantine_path) = clean_orphans(&game_path, &orphans, OrphanAction::Delete).unwrap();
+        assert_eq!(cleaned, ["Old.dll"]);
+        assert!(quarantine_path.is_none());
+        assert!(!game_path.join("Old.dll").exists());
+
+        fs::remove_dir_all(&game_path).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+// Standard library imports
+use std::path::{Path, PathBuf};
+
+use super::manifest::FileInfo;
+
+/// Device names Windows resolves regardless of folder and extension.
+const RESERVED_NAMES: [&str; 26] = [
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5",
+    "COM6", "COM7", "COM8", "COM9", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7",
+    "LPT8", "LPT9",
+];
+
+/// Characters that are invalid in Windows file names. `:` also covers drive
+/// prefixes and alternate data streams.
+const INVALID_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// Checks a manifest path and returns it as a relative path.
+///
+/// Manifest paths are relative, `/` separated paths below the game folder.
+/// Anything that could resolve outside of it, or to something other than the
+/// named file on Windows, is rejected.
+///
+/// # Returns
+///
+/// The relative path, or an error describing why the path was rejected.
+pub fn sanitize_manifest_path(raw: &str) -> Result<PathBuf, String> {
+    let reject = |reason: &str| Err(format!("Rejected manifest path {:?}: {}", raw, reason));
+
+    if raw.is_empty() {
+        return reject("empty path");
+    }
+    if raw.chars().any(|c| c.is_control()) {
+        return reject("contains control characters");
+    }
+    if raw.starts_with('/') || raw.starts_with('\\') {
+        return reject("absolute path");
+    }
+
+    let mut relative = PathBuf::new();
+    for component in raw.split(['/', '\\']) {
+        match component {
+            "" => return reject("empty path component"),
+            "." | ".." => return reject("relative path component"),
+            _ => {}
+        }
+        if component.contains(':') {
+            return reject("drive prefix or stream name");
+        }
+        if component.contains(INVALID_CHARS) {
+            return reject("invalid character");
+        }
+        // Windows silently drops trailing dots and spaces
+        if component.ends_with('.') || component.ends_with(' ') {
+            return reject("trailing dot or space");
+        }
+        let stem = component.split('.').next().unwrap_or(component).trim_end();
+        if RESERVED_NAMES.iter().any(|name| name.eq_ignore_ascii_case(stem)) {
+            return reject("reserved Windows name");
+        }
+        relative.push(component);
+    }
+
+    Ok(relative)
+}
+
+/// Resolves a manifest path inside the game folder.
+///
+/// On top of `sanitize_manifest_path`, the deepest existing part of the path
+/// is canonicalized, so symlinks or junctions inside the install cannot be
+/// used to write outside of it.
+///
+/// # Returns
+///
+/// The absolute path of the file in the game folder.
+pub fn resolve_game_file(game_path: &Path, raw: &str) -> Result<PathBuf, String> {
+    let relative = sanitize_manifest_path(raw)?;
+    let full_path = game_path.join(&relative);
+
+    let root = game_path
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve game path {:?}: {}", game_path, e))?;
+
+    let existing = full_path
+        .ancestors()
+        .find(|path| path.symlink_metadata().is_ok())
+        .unwrap_or(game_path);
+    let resolved = existing
+        .canonicalize()
+        .map_err(|e| format!("Rejected manifest path {:?}: {}", raw, e))?;
+
+    if !resolved.starts_with(&root) {
+        return Err(format!("Rejected manifest path {:?}: resolves outside of the game folder", raw));
+    }
+
+    Ok(full_path)
+}
+
+/// Checks every path of a manifest.
+///
+/// # Returns
+///
+/// An error listing all rejected entries, so a tampered manifest is refused as a whole.
+pub fn validate_manifest_paths(files: &[FileInfo]) -> Result<(), String> {
+    let rejected: Vec<String> = files
+        .iter()
+        .filter_map(|file_info| sanitize_manifest_path(&file_info.path).err())
+        .collect();
+
+    if rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Hash file contains {} unsafe path(s):\n{}", rejected.len(), rejected.join("\n")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(paths: &[&str]) -> Vec<FileInfo> {
+        let files: Vec<_> = paths
+            .iter()
+            .map(|path| serde_json::json!({ "path": path, "hash": "00", "size": 0, "url": "http://localhost/x" }))
+            .collect();
+        serde_json::from_value(serde_json::Value::Array(files)).unwrap()
+    }
+
+    #[test]
+    fn accepts_regular_game_paths() {
+        for path in [
+            "S1Game/CookedPC/Art_Data/Packages/S1Data/S1Data.gpk",
+            "Binaries/TERA.exe",
+            "S1Game\\Localization\\EUR\\S1Data.int",
+            "S1Game/Config/.hidden",
+            "S1Game/CONSOLE.ini",
+        ] {
+            assert!(sanitize_manifest_path(path).is_ok(), "{}", path);
+        }
+        assert!(validate_manifest_paths(&manifest(&["Binaries/TERA.exe", "S1Game/S1.ini"])).is_ok());
+    }
+
+    #[test]
+    fn rejects_parent_components() {
+        for path in ["../evil.dll", "../../Windows/System32/evil.dll", "S1Game/../../evil.dll", "S1Game\\..\\..\\evil.dll", "S1Game/.."] {
+            assert!(sanitize_manifest_path(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn rejects_absolute_and_prefixed_paths() {
+        for path in [
+            "/etc/passwd",
+            "\\Windows\\System32\\evil.dll",
+            "\\\\server\\share\\evil.dll",
+            "C:/Windows/System32/evil.dll",
+            "C:\\Windows\\evil.dll",
+            "C:evil.dll",
+            "\\\\?\\C:\\evil.dll",
+            "S1Game/file.txt:stream",
+        ] {
+            assert!(sanitize_manifest_path(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_windows_names() {
+        for path in [
+            "CON",
+            "S1Game/nul",
+            "S1Game/aux.txt",
+            "Binaries/COM1.dll",
+            "LPT9/file",
+            "S1Game/Prn .log",
+            "COM0",
+            "S1Game/lpt0.ini",
+            "conin$",
+            "S1Game/CONOUT$.txt",
+        ] {
+            assert!(sanitize_manifest_path(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn rejects_ambiguous_components() {
+        for path in ["", "S1Game//file", "S1Game/", "./file", "S1Game/file.", "S1Game/file ", "S1Game/fi*le", "S1Game/a\0b", "S1Game/a\nb"] {
+            assert!(sanitize_manifest_path(path).is_err(), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn reports_every_rejected_entry() {
+        let files = manifest(&["Binaries/TERA.exe", "../evil.dll", "C:/evil.dll", "S1Game/CON"]);
+        let error = validate_manifest_paths(&files).unwrap_err();
+        assert!(error.contains("3 unsafe path(s)"), "{}", error);
+        assert!(error.contains("../evil.dll") && error.contains("C:/evil.dll") && error.contains("S1Game/CON"));
+        assert!(!error.contains("TERA.exe"));
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_game_folder() {
+        let game_path = std::env::temp_dir().join(format!("teralaunch-paths-{}", std::process::id()));
+        std::fs::create_dir_all(game_path.join("S1Game")).unwrap();
+
+        let resolved = resolve_game_file(&game_path, "S1Game/New/File.gpk").unwrap();
+        assert_eq!(resolved, game_path.join("S1Game").join("New").join("File.gpk"));
+        assert!(resolve_game_file(&game_path, "../outside.dll").is_err());
+
+        std::fs::remove_dir_all(&game_path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_escapes() {
+        let base = std::env::temp_dir().join(format!("teralaunch-symlink-{}", std::process::id()));
+        let game_path = base.join("game");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(&game_path).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, game_path.join("S1Game")).unwrap();
+
+        let error = resolve_game_file(&game_path, "S1Game/evil.dll").unwrap_err();
+        assert!(error.contains("outside of the game folder"), "{}", error);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn rejects_junction_escapes() {
+        let base = std::env::temp_dir().join(format!("teralaunch-junction-{}", std::process::id()));
+        let game_path = base.join("game");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(&game_path).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        // Junctions need no privileges, unlike directory symlinks
+        let status = std::process::Command::new("cmd")
+            .args(["/C", "mklink", "/J"])
+            .arg(game_path.join("S1Game"))
+            .arg(&outside)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let error = resolve_game_file(&game_path, "S1Game/evil.dll").unwrap_err();
+        assert!(error.contains("outside of the game folder"), "{}", error);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+// Standard library imports
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+// Third-party imports
+use serde::Serialize;
+
+use super::manifest::FileInfo;
+use super::paths::resolve_game_file;
+
+/// Space left free on top of the estimate, for the hash cache, the logs and
+/// the file system's own bookkeeping.
+pub const SPACE_MARGIN: u64 = 64 * 1024 * 1024;
+
+/// Environment variables naming folders the game must not be installed into.
+/// Windows only lets elevated processes write there, and silently redirects
+/// some writes to the VirtualStore instead of failing.
+const PROTECTED_FOLDER_VARS: [&str; 4] = ["ProgramFiles", "ProgramFiles(x86)", "ProgramW6432", "SystemRoot"];
+
+/// A reason the update cannot start, with a stable `code` for the frontend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum PreflightIssue {
+    /// The game folder is inside a folder like Program Files.
+    ProtectedLocation { path: String, protected_folder: String },
+    /// Files cannot be created in the game folder.
+    NotWritable { path: String, reason: String },
+    /// The volume of the game folder is too full for the update.
+    InsufficientSpace { path: String, required: u64, available: u64 },
+}
+
+impl PreflightIssue {
+    /// English description, for logs and command errors.
+    pub fn message(&self) -> String {
+        match self {
+            PreflightIssue::ProtectedLocation { path, protected_folder } => {
+                format!("{} is inside the protected folder {}", path, protected_folder)
+            }
+            PreflightIssue::NotWritable { path, reason } => format!("{} is not writable: {}", path, reason),
+            PreflightIssue::InsufficientSpace { path, required, available } => format!(
+                "Not enough space for {}: {} bytes required, {} bytes available",
+                path, required, available
+            ),
+        }
+    }
+}
+
+/// Disk space an update needs on top of what is already on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SpaceEstimate {
+    /// Growth of the game folder once every file is in place.
+    pub final_growth: u64,
+    /// Scratch files (`.part`, `.unpacked`, `.assemble`, `.patch`) of the
+    /// downloads running at the same time, and the files a staged update
+    /// replaces.
+    pub temporary: u64,
+    /// Both of the above plus [`SPACE_MARGIN`].
+    pub required: u64,
+}
+
+/// Outcome of the checks run before an update writes anything.
+#[derive(Debug, Clone, Serialize)]
+pub struct PreflightReport {
+    pub game_path: String,
+    pub space: SpaceEstimate,
+    /// Free space of the volume, `None` where it cannot be queried.
+    pub available_space: Option<u64>,
+    pub issues: Vec<PreflightIssue>,
+}
+
+impl PreflightReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// All issues on one line, for command errors.
+    pub fn summary(&self) -> String {
+        self.issues.iter().map(PreflightIssue::message).collect::<Vec<_>>().join("; ")
+    }
+}
+
+/// Largest amount of scratch space one download holds at a time.
+///
+/// The new file is written next to the old one and only replaces it once
+/// verified. A compressed transfer keeps the compressed part file while it
+/// is unpacked, a patched file keeps the diff while the patch is applied.
+pub fn temporary_space(file_info: &FileInfo) -> u64 {
+    let compressed = file_info.compressed.as_ref().map_or(0, |c| c.size);
+    let largest_patch = file_info.patches.iter().map(|p| p.size).max().unwrap_or(0);
+    file_info.size + compressed.max(largest_patch)
+}
+
+/// Estimates the space needed to bring `files` up to date in `game_path`
+/// with `connections` downloads running at the same time.
+///
+/// Files being replaced only count with the size they grow by. Part files
+/// left by an interrupted update are already on disk and are subtracted from
+/// the scratch space of their download. A `staged` update keeps every new
+/// file next to the one it replaces until the commit, so the previous copies
+/// count as scratch space as well.
+pub fn estimate_space(game_path: &Path, files: &[FileInfo], connections: usize, staged: bool) -> SpaceEstimate {
+    let mut final_growth = 0u64;
+    let mut kept = 0u64;
+    let mut scratch: Vec<u64> = Vec::with_capacity(files.len());
+
+    for file_info in files {
+        let target = resolve_game_file(game_path, &file_info.path).ok();
+        let existing = target.as_deref().and_then(|path| fs::metadata(path).ok()).map_or(0, |m| m.len());
+        final_growth += file_info.size.saturating_sub(existing);
+
+        if staged {
+            kept += file_info.size.min(existing);
+            scratch.push(temporary_space(file_info) - file_info.size);
+            continue;
+        }
+
+        let resumed = target
+            .as_deref()
+            .and_then(|path| {
+                let file_name = path.file_name()?.to_string_lossy().into_owned();
+                fs::metadata(path.with_file_name(format!("{}.part", file_name))).ok()
+            })
+            .map_or(0, |m| m.len());
+        scratch.push(temporary_space(file_info).saturating_sub(resumed));
+    }
+
+    // Downloads run largest first, so the biggest ones are in flight together
+    scratch.sort_unstable_by(|a, b| b.cmp(a));
+    let temporary = kept + scratch.iter().take(connections.max(1)).sum::<u64>();
+
+    SpaceEstimate { final_growth, temporary, required: final_growth + temporary + SPACE_MARGIN }
+}
+
+/// The folder itself if it exists, otherwise its closest existing parent.
+/// A first install targets a folder that is yet to be created.
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.is_dir())
+}
+
+/// Protected folders of this system, read from the environment.
+fn protected_folders() -> Vec<PathBuf> {
+    PROTECTED_FOLDER_VARS
+        .iter()
+        .filter_map(env::var_os)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Compares path components case-insensitively, like Windows does.
+fn is_within(path: &Path, folder: &Path) -> bool {
+    let normalize = |path: &Path| -> Vec<String> {
+        path.components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .map(|component| component.as_os_str().to_string_lossy().to_lowercase())
+            .collect()
+    };
+    let (path, folder) = (normalize(path), normalize(folder));
+    !folder.is_empty() && path.starts_with(&folder)
+}
+
+/// Returns the protected folder `path` lies in, if any.
+pub fn protected_folder(path: &Path) -> Option<PathBuf> {
+    protected_folders().into_iter().find(|folder| is_within(path, folder))
+}
+
+/// Checks that files can be created in `folder` by writing and removing a
+/// probe file.
+pub fn check_writable(folder: &Path) -> Result<(), String> {
+    let probe = folder.join(format!(".teralaunch-write-test-{}", std::process::id()));
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .and_then(|mut file| file.write_all(b"ok"));
+    let _ = fs::remove_file(&probe);
+    result.map_err(|e| e.to_string())
+}
+
+/// Free space available to the current user on the volume holding `path`.
+#[cfg(windows)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+    use winapi::um::winnt::ULARGE_INTEGER;
+
+    let folder = existing_ancestor(path)?;
+    let wide: Vec<u16> = OsStr::new(folder).encode_wide().chain(Some(0)).collect();
+
+    // SAFETY: the path is NUL terminated and the out parameters live on the stack.
+    unsafe {
+        let mut free_to_caller: ULARGE_INTEGER = std::mem::zeroed();
+        let mut total: ULARGE_INTEGER = std::mem::zeroed();
+        let mut total_free: ULARGE_INTEGER = std::mem::zeroed();
+        if GetDiskFreeSpaceExW(wide.as_ptr(), &mut free_to_caller, &mut total, &mut total_free) == 0 {
+            return None;
+        }
+        Some(*free_to_caller.QuadPart())
+    }
+}
+
+#[cfg(not(windows))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Runs every check for updating `files` in `game_path`.
+///
+/// Nothing is downloaded when the report has issues. The game folder may not
+/// exist yet, in which case its closest existing parent is checked.
+pub fn run_preflight(game_path: &Path, files: &[FileInfo], connections: usize, staged: bool) -> PreflightReport {
+    let display_path = game_path.display().to_string();
+    let mut issues = Vec::new();
+
+    if let Some(folder) = protected_folder(game_path) {
+        issues.push(PreflightIssue::ProtectedLocation {
+            path: display_path.clone(),
+            protected_folder: folder.display().to_string(),
+        });
+    }
+
+    match existing_ancestor(game_path) {
+        Some(folder) => {
+            if let Err(reason) = check_writable(folder) {
+                issues.push(PreflightIssue::NotWritable { path: folder.display().to_string(), reason });
+            }
+        }
+        None => issues.push(PreflightIssue::NotWritable {
+            path: display_path.clone(),
+            reason: "no existing parent folder".to_string(),
+        }),
+    }
+
+    let space = estimate_space(game_path, files, connections, staged);
+    let available_space = available_space(game_path);
+    if let Some(available) = available_space.filter(|&available| available < space.required) {
+        issues.push(PreflightIssue::InsufficientSpace {
+            path: display_path.clone(),
+            required: space.required,
+            available,
+        });
+    }
+
+    PreflightReport { game_path: display_path, space, available_space, issues }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::compression::{CompressedInfo, Compression};
+    use crate::patch::hashing::HashAlgorithm;
+
+    fn file(path: &str, size: u64, compressed: Option<u64>) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            hash: String::new(),
+            size,
+            url: String::new(),
+            patches: Vec::new(),
+            compressed: compressed.map(|size| CompressedInfo {
+                format: Compression::Zstd,
+                url: String::new(),
+                size,
+            }),
+            chunks: Vec::new(),
+            hash_algorithm: HashAlgorithm::default(),
+        }
+    }
+
+    #[test]
+    fn compressed_downloads_need_both_copies() {
+        assert_eq!(temporary_space(&file("a", 100, None)), 100);
+        assert_eq!(temporary_space(&file("a", 100, Some(40))), 140);
+    }
+
+    #[test]
+    fn estimate_counts_growth_and_concurrent_scratch() {
+        let game_path = std::env::temp_dir().join(format!("teralaunch-space-{}", std::process::id()));
+        fs::create_dir_all(&game_path).unwrap();
+        fs::write(game_path.join("old.dat"), vec![0u8; 30]).unwrap();
+        fs::write(game_path.join("big.dat.part"), vec![0u8; 50]).unwrap();
+
+        let files = [file("old.dat", 100, None), file("big.dat", 500, None), file("new.dat", 10, Some(5))];
+        let estimate = estimate_space(&game_path, &files, 2, false);
+        let staged = estimate_space(&game_path, &files, 2, true);
+        fs::remove_dir_all(&game_path).unwrap();
+
+        assert_eq!(estimate.final_growth, 70 + 500 + 10);
+        // The two largest downloads, minus the part file already on disk
+        assert_eq!(estimate.temporary, 450 + 100);
+        assert_eq!(estimate.required, estimate.final_growth + estimate.temporary + SPACE_MARGIN);
+
+        // Every new file is kept next to the old one, plus the compressed part file
+        assert_eq!(staged.final_growth, estimate.final_growth);
+        assert_eq!(staged.temporary, 30 + 5);
+    }
+
+    #[test]
+    fn protected_folders_match_case_insensitively() {
+        let folder = Path::new("/Program Files");
+        assert!(is_within(Path::new("/program files/TERA"), folder));
+        assert!(is_within(Path::new("/Program Files"), folder));
+        assert!(!is_within(Path::new("/Program Files Games/TERA"), folder));
+        assert!(!is_within(Path::new("/Games/TERA"), folder));
+    }
+
+    #[test]
+    fn missing_game_folder_is_checked_through_its_parent() {
+        let base = std::env::temp_dir().join(format!("teralaunch-preflight-{}", std::process::id()));
+        fs::create_dir_all(&base).unwrap();
+        let game_path = base.join("TERA").join("Client");
+
+        assert_eq!(existing_ancestor(&game_path), Some(base.as_path()));
+        let report = run_preflight(&game_path, &[file("a", 1, None)], 4, true);
+        let probe_left = fs::read_dir(&base).unwrap().count();
+        fs::remove_dir_all(&base).unwrap();
+
+        assert!(report.is_ok(), "{}", report.summary());
+        assert_eq!(probe_left, 0);
+    }
+
+    #[test]
+    fn issues_serialize_with_a_code() {
+        let issue = PreflightIssue::InsufficientSpace { path: "C:\\TERA".to_string(), required: 2, available: 1 };
+        let json = serde_json::to_value(&issue).unwrap();
+        assert_eq!(json["code"], "insufficient_space");
+        assert_eq!(json["required"], 2);
+    }
+}
@@ -0,0 +1,308 @@
+// Standard library imports
+use std::convert::Infallible;
+use std::fs::Metadata;
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+// Third-party imports
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{info, warn};
+use percent_encoding::percent_decode_str;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::builder::FILES_DIR;
+use super::paths::resolve_game_file;
+
+/// Size of the blocks a response body is streamed in.
+const SEND_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Byte range of a file to send, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+/// Parses a `Range` header against a file of `size` bytes.
+///
+/// # Returns
+///
+/// `Ok(None)` if the header should be ignored and the whole file sent, which
+/// includes multi-range requests, or `Err(())` if the range cannot be satisfied.
+fn parse_range(header: &str, size: u64) -> Result<Option<ByteRange>, ()> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        ("", "") => return Ok(None),
+        // Last `suffix` bytes
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().map_err(|_| ())?;
+            if suffix == 0 || size == 0 {
+                return Err(());
+            }
+            ByteRange { start: size.saturating_sub(suffix), end: size - 1 }
+        }
+        (start, end) => {
+            let start: u64 = start.parse().map_err(|_| ())?;
+            let end: u64 = if end.is_empty() { u64::MAX } else { end.parse().map_err(|_| ())? };
+            if start >= size || end < start {
+                return Err(());
+            }
+            ByteRange { start, end: end.min(size - 1) }
+        }
+    };
+    Ok(Some(range))
+}
+
+/// Validator of a file, sent as `ETag` and matched against `If-Range`.
+fn entity_tag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    format!("\"{:x}-{:x}\"", metadata.len(), modified)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => "application/json",
+        Some("sig") | Some("txt") | Some("ini") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::from(status.canonical_reason().unwrap_or_default()));
+    *response.status_mut() = status;
+    response
+}
+
+/// Maps a request path to a file below `root`.
+///
+/// `/files/<path>` falls back to `<root>/<path>`, so a game folder with an in
+/// place generated hash file can be served as is, next to publish trees
+/// written by `build-manifest`.
+fn resolve_request_path(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode_str(request_path).decode_utf8().ok()?;
+    let relative = decoded.trim_start_matches('/');
+
+    let path = resolve_game_file(root, relative).ok()?;
+    if path.is_file() {
+        return Some(path);
+    }
+    let fallback = relative.strip_prefix(FILES_DIR)?.strip_prefix('/')?;
+    resolve_game_file(root, fallback).ok().filter(|path| path.is_file())
+}
+
+async fn handle(root: Arc<PathBuf>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = respond(&root, &request).await;
+    info!("{} {} -> {}", request.method(), request.uri().path(), response.status().as_u16());
+    Ok(response)
+}
+
+async fn respond(root: &Path, request: &Request<Body>) -> Response<Body> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return status_response(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    // The launcher checks the file server root before updating
+    if request.uri().path() == "/" {
+        return status_response(StatusCode::OK);
+    }
+    let Some(path) = resolve_request_path(root, request.uri().path()) else {
+        return status_response(StatusCode::NOT_FOUND);
+    };
+    let mut file = match File::open(&path).await {
+        Ok(file) => file,
+        Err(_) => return status_response(StatusCode::NOT_FOUND),
+    };
+    let metadata = match file.metadata().await {
+        Ok(metadata) => metadata,
+        Err(_) => return status_response(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let size = metadata.len();
+    let etag = entity_tag(&metadata);
+    let last_modified = metadata.modified().ok().map(httpdate::fmt_http_date);
+
+    // A range is only valid for the version of the file the client started with
+    let range_applies = match request.headers().get(IF_RANGE).and_then(|v| v.to_str().ok()) {
+        Some(validator) => validator == etag || Some(validator) == last_modified.as_deref(),
+        None => true,
+    };
+    let range = match request.headers().get(RANGE).and_then(|v| v.to_str().ok()) {
+        Some(header) if range_applies => match parse_range(header, size) {
+            Ok(range) => range,
+            Err(()) => {
+                let mut response = status_response(StatusCode::RANGE_NOT_SATISFIABLE);
+                if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", size)) {
+                    response.headers_mut().insert(CONTENT_RANGE, value);
+                }
+                return response;
+            }
+        },
+        _ => None,
+    };
+
+    let (start, length) = range.map_or((0, size), |r| (r.start, r.end - r.start + 1));
+    let mut builder = Response::builder()
+        .status(if range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK })
+        .header(ACCEPT_RANGES, "bytes")
+        .header(CONTENT_TYPE, content_type(&path))
+        .header(CONTENT_LENGTH, length)
+        .header(ETAG, &etag);
+    if let Some(last_modified) = &last_modified {
+        builder = builder.header(LAST_MODIFIED, last_modified);
+    }
+    if let Some(range) = range {
+        builder = builder.header(CONTENT_RANGE, format!("bytes {}-{}/{}", range.start, range.end, size));
+    }
+
+    let body = if request.method() == Method::HEAD || length == 0 {
+        Body::empty()
+    } else {
+        if file.seek(SeekFrom::Start(start)).await.is_err() {
+            return status_response(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            let mut remaining = length;
+            let mut buffer = vec![0; SEND_BUFFER_SIZE];
+            while remaining > 0 {
+                let wanted = remaining.min(SEND_BUFFER_SIZE as u64) as usize;
+                let read = match file.read(&mut buffer[..wanted]).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => read,
+                };
+                // The client went away
+                if sender.send_data(Bytes::copy_from_slice(&buffer[..read])).await.is_err() {
+                    return;
+                }
+                remaining -= read as u64;
+            }
+            if remaining > 0 {
+                warn!("File changed while it was being sent");
+                sender.abort();
+            }
+        });
+        body
+    };
+
+    builder.body(body).unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// Serves the files below `root` over HTTP until the returned future is dropped.
+///
+/// Meant for a publish tree written by `build-manifest` or a game folder with a
+/// generated hash file, on a LAN or as a local stand-in for the file server.
+/// Only GET and HEAD are answered; single byte ranges are honoured so
+/// interrupted downloads resume.
+///
+/// # Returns
+///
+/// The bound address, useful with port 0, and the server future.
+pub fn bind(
+    root: PathBuf,
+    addr: SocketAddr,
+) -> Result<(SocketAddr, impl std::future::Future<Output = Result<(), String>>), String> {
+    let root = Arc::new(root.canonicalize().map_err(|e| format!("Failed to open {:?}: {}", root, e))?);
+
+    let make_service = make_service_fn(move |_| {
+        let root = Arc::clone(&root);
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(Arc::clone(&root), request))) }
+    });
+
+    let server = Server::try_bind(&addr)
+        .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?
+        .serve(make_service);
+    let local_addr = server.local_addr();
+
+    Ok((local_addr, async move { server.await.map_err(|e| e.to_string()) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some(ByteRange { start: 0, end: 99 })));
+        assert_eq!(parse_range("bytes=500-", 1000), Ok(Some(ByteRange { start: 500, end: 999 })));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some(ByteRange { start: 900, end: 999 })));
+        assert_eq!(parse_range("bytes=-5000", 1000), Ok(Some(ByteRange { start: 0, end: 999 })));
+        assert_eq!(parse_range("bytes=900-5000", 1000), Ok(Some(ByteRange { start: 900, end: 999 })));
+    }
+
+    #[test]
+    fn ignores_unsupported_ranges() {
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-9", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=-", 1000), Ok(None));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=10-5", 1000), Err(()));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(()));
+        assert_eq!(parse_range("bytes=0-", 0), Err(()));
+        assert_eq!(parse_range("bytes=a-b", 1000), Err(()));
+    }
+
+    #[tokio::test]
+    async fn serves_files_and_ranges() {
+        let root = std::env::temp_dir().join(format!("teralaunch-serve-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("S1Game")).unwrap();
+        let contents: Vec<u8> = (0..=255u8).cycle().take(10_000).collect();
+        std::fs::write(root.join("S1Game").join("S1Data.gpk"), &contents).unwrap();
+
+        let (addr, server) = bind(root.clone(), "127.0.0.1:0".parse().unwrap()).unwrap();
+        let server = tokio::spawn(server);
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let url = format!("http://{}/files/S1Game/S1Data.gpk", addr);
+
+        let index = client.get(format!("http://{}/", addr)).send().await.unwrap();
+        assert_eq!(index.status(), 200);
+
+        let full = client.get(&url).send().await.unwrap();
+        assert_eq!(full.status(), 200);
+        let etag = full.headers()["etag"].to_str().unwrap().to_string();
+        assert_eq!(full.bytes().await.unwrap().as_ref(), &contents[..]);
+
+        let partial = client.get(&url).header("range", "bytes=9000-").header("if-range", &etag).send().await.unwrap();
+        assert_eq!(partial.status(), 206);
+        assert_eq!(partial.headers()["content-range"], "bytes 9000-9999/10000");
+        assert_eq!(partial.bytes().await.unwrap().as_ref(), &contents[9000..]);
+
+        let stale = client.get(&url).header("range", "bytes=9000-").header("if-range", "\"other\"").send().await.unwrap();
+        assert_eq!(stale.status(), 200);
+
+        let unsatisfiable = client.get(&url).header("range", "bytes=20000-").send().await.unwrap();
+        assert_eq!(unsatisfiable.status(), 416);
+
+        for path in ["/files/../outside", "/files/S1Game/missing.gpk", "/%2e%2e/etc/passwd"] {
+            let response = client.get(format!("http://{}{}", addr, path)).send().await.unwrap();
+            assert_eq!(response.status(), 404, "{}", path);
+        }
+
+        server.abort();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
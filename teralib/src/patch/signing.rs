@@ -0,0 +1,199 @@
+// Standard library imports
+use std::fs;
+use std::path::Path;
+
+// Third-party imports
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use log::info;
+use rand::rngs::OsRng;
+
+use super::archive::ARCHIVE_FILE_NAME;
+use super::launcher_update::LAUNCHER_FILE_NAME;
+use super::manifest::{MANIFEST_FILE_NAME, VERSION_FILE_NAME};
+
+/// Extension of the detached signature published next to the hash file.
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+fn decode_hex<const N: usize>(value: &str, what: &str) -> Result<[u8; N], String> {
+    let bytes = hex::decode(value.trim()).map_err(|e| format!("Invalid {}: {}", what, e))?;
+    bytes
+        .try_into()
+        .map_err(|_| format!("Invalid {}: expected {} bytes", what, N))
+}
+
+/// Verifies the detached signature of a manifest.
+///
+/// The signature covers the exact bytes served as hash file, so it is checked
+/// before the manifest is parsed. File contents, patches, chunks and compressed
+/// variants are all pinned by hashes inside the manifest.
+///
+/// # Arguments
+///
+/// * `manifest` - The raw hash file.
+/// * `signature` - Hex encoded Ed25519 signature.
+/// * `public_key` - Hex encoded Ed25519 public key embedded in the launcher.
+pub fn verify_manifest(manifest: &[u8], signature: &str, public_key: &str) -> Result<(), String> {
+    if public_key.trim().is_empty() {
+        return Err("No manifest public key configured, refusing to use an unsigned hash file".to_string());
+    }
+
+    let public_key = VerifyingKey::from_bytes(&decode_hex(public_key, "manifest public key")?)
+        .map_err(|e| format!("Invalid manifest public key: {}", e))?;
+    let signature = Signature::from_bytes(&decode_hex(signature, "hash file signature")?);
+
+    public_key
+        .verify(manifest, &signature)
+        .map_err(|_| "Hash file signature verification failed".to_string())
+}
+
+/// Signs a manifest with the operator's private key.
+///
+/// # Returns
+///
+/// The hex encoded detached signature.
+pub fn sign_manifest(manifest: &[u8], signing_key: &SigningKey) -> String {
+    hex::encode(signing_key.sign(manifest).to_bytes())
+}
+
+/// Loads a private key written by `generate_signing_key`.
+pub fn load_signing_key(path: &Path) -> Result<SigningKey, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read signing key: {}", e))?;
+    Ok(SigningKey::from_bytes(&decode_hex(&contents, "signing key")?))
+}
+
+/// Generates a new key pair and writes the private key to `path`.
+///
+/// An existing key is never overwritten, as losing it means every installed
+/// launcher has to be rebuilt with the new public key.
+///
+/// # Returns
+///
+/// The hex encoded public key, to be set as `MANIFEST_PUBLIC_KEY` in config.json.
+pub fn generate_signing_key(path: &Path) -> Result<String, String> {
+    if path.exists() {
+        return Err(format!("Signing key already exists: {:?}", path));
+    }
+
+    let signing_key = SigningKey::generate(&mut OsRng);
+    fs::write(path, hex::encode(signing_key.to_bytes()))
+        .map_err(|e| format!("Failed to write signing key: {}", e))?;
+
+    Ok(hex::encode(signing_key.verifying_key().to_bytes()))
+}
+
+/// Returns true for the documents published next to the hash file that the
+/// launcher verifies: the hash file, the version head and the deltas.
+fn is_signed_document(name: &str) -> bool {
+    name == MANIFEST_FILE_NAME
+        || name == VERSION_FILE_NAME
+        || name == ARCHIVE_FILE_NAME
+        || name == LAUNCHER_FILE_NAME
+        || (name.starts_with("hash-file.from-") && name.ends_with(".json"))
+}
+
+/// Signs every published document in `folder`, writing a `.sig` file next to each.
+///
+/// # Returns
+///
+/// The names of the signed documents.
+pub fn sign_documents(folder: &Path, signing_key: &SigningKey) -> Result<Vec<String>, String> {
+    let mut signed = Vec::new();
+    for entry in fs::read_dir(folder).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+        if !is_signed_document(&name) {
+            continue;
+        }
+
+        let document = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        let signature_path = folder.join(format!("{}.{}", name, SIGNATURE_EXTENSION));
+        fs::write(&signature_path, sign_manifest(&document, signing_key)).map_err(|e| e.to_string())?;
+        info!("Signed: {:?}", signature_path);
+        signed.push(name);
+    }
+
+    if !signed.iter().any(|name| name == MANIFEST_FILE_NAME) {
+        return Err(format!("{} not found, generate it first", MANIFEST_FILE_NAME));
+    }
+    signed.sort();
+    Ok(signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn public_key() -> String {
+        hex::encode(signing_key().verifying_key().to_bytes())
+    }
+
+    #[test]
+    fn signed_manifest_verifies() {
+        let manifest = br#"{"version":2,"files":[]}"#;
+        let signature = sign_manifest(manifest, &signing_key());
+        assert_eq!(verify_manifest(manifest, &signature, &public_key()), Ok(()));
+        // Signature files may end with a newline
+        assert_eq!(verify_manifest(manifest, &format!("{}\n", signature), &public_key()), Ok(()));
+    }
+
+    #[test]
+    fn missing_or_malformed_public_keys_are_refused() {
+        let manifest = b"{}";
+        let signature = sign_manifest(manifest, &signing_key());
+
+        assert!(verify_manifest(manifest, &signature, "").unwrap_err().contains("No manifest public key"));
+        assert!(verify_manifest(manifest, &signature, " \n").unwrap_err().contains("No manifest public key"));
+        assert!(verify_manifest(manifest, &signature, "not hex").unwrap_err().contains("Invalid manifest public key"));
+        assert!(verify_manifest(manifest, &signature, &public_key()[..62]).unwrap_err().contains("expected 32 bytes"));
+
+        let other_key = hex::encode(SigningKey::from_bytes(&[8; 32]).verifying_key().to_bytes());
+        assert!(verify_manifest(manifest, &signature, &other_key).is_err());
+    }
+
+    #[test]
+    fn tampered_manifests_and_signatures_are_refused() {
+        let manifest = br#"{"version":2,"files":[]}"#.to_vec();
+        let signature = sign_manifest(&manifest, &signing_key());
+
+        let mut tampered = manifest.clone();
+        tampered[11] = b'3';
+        assert_eq!(
+            verify_manifest(&tampered, &signature, &public_key()),
+            Err("Hash file signature verification failed".to_string())
+        );
+
+        let mut signature_bytes = hex::decode(&signature).unwrap();
+        signature_bytes[0] ^= 1;
+        assert!(verify_manifest(&manifest, &hex::encode(&signature_bytes), &public_key()).is_err());
+        assert!(verify_manifest(&manifest, &signature[..126], &public_key()).is_err());
+        assert!(verify_manifest(&manifest, "", &public_key()).is_err());
+    }
+
+    #[test]
+    fn generated_keys_sign_published_documents() {
+        let folder = std::env::temp_dir().join(format!("teralaunch-signing-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let key_path = folder.join("signing.key");
+
+        let public_key = generate_signing_key(&key_path).unwrap();
+        assert!(generate_signing_key(&key_path).is_err());
+        let signing_key = load_signing_key(&key_path).unwrap();
+
+        assert!(sign_documents(&folder, &signing_key).is_err());
+        fs::write(folder.join(MANIFEST_FILE_NAME), b"{}").unwrap();
+        fs::write(folder.join("hash-file.from-1.json"), b"{}").unwrap();
+        fs::write(folder.join("notes.txt"), b"not published").unwrap();
+        assert_eq!(sign_documents(&folder, &signing_key).unwrap(), ["hash-file.from-1.json", MANIFEST_FILE_NAME]);
+
+        let signature = fs::read_to_string(folder.join(format!("{}.{}", MANIFEST_FILE_NAME, SIGNATURE_EXTENSION))).unwrap();
+        assert_eq!(verify_manifest(b"{}", &signature, &public_key), Ok(()));
+        assert!(!folder.join("notes.txt.sig").exists());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,428 @@
+// Standard library imports
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+// Third-party imports
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::ignore_rules::STAGING_DIR;
+use super::paths::{resolve_game_file, sanitize_manifest_path};
+
+/// Folder of the staging area mirroring the game folder with the new files.
+const STAGED_FILES_DIR: &str = "files";
+
+/// Folder of the staging area receiving the replaced files during a commit.
+const BACKUP_DIR: &str = "backup";
+
+/// Journal of a running commit, its presence on start means the commit was interrupted.
+const JOURNAL_FILE_NAME: &str = "journal.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CommitState {
+    /// Files are being swapped, the game folder may mix both releases.
+    Committing,
+    /// Every file is in place, only the backups are left to remove.
+    Committed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalEntry {
+    path: String,
+    /// Whether the game folder had the file before the commit.
+    replaces_existing: bool,
+}
+
+impl JournalEntry {
+    /// Returns true once the staged copy took the place of the live file. A
+    /// replaced file only counts as moved in once its previous version is in
+    /// the backup, as the live file is still the old one otherwise.
+    fn is_moved_in(&self, live: &Path, staged: &Path, backup: &Path) -> bool {
+        live.exists() && !staged.exists() && (backup.exists() || !self.replaces_existing)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Journal {
+    state: CommitState,
+    /// Release being installed, recorded once a resumed commit completes.
+    version: Option<u64>,
+    files: Vec<JournalEntry>,
+}
+
+/// What `StagingArea::recover` did with an interrupted commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// The commit was completed from the staged files, installing the given release.
+    Resumed(Option<u64>),
+    /// The previous files were restored from the backups.
+    RolledBack,
+    /// The commit had completed, its leftovers were removed.
+    Cleaned,
+}
+
+/// Staging area of an update in `<game>/$Staging`.
+///
+/// Files are downloaded and verified below `files/` while the game folder is
+/// left alone. Once all of them are staged, `commit` swaps them in: replaced
+/// files are moved to `backup/` and the staged ones into the game folder, with
+/// a journal recording the file set. A commit cut short, e.g. by a crash or a
+/// power loss, is finished or undone by `recover` on the next start.
+#[derive(Debug, Clone)]
+pub struct StagingArea {
+    game_path: PathBuf,
+    root: PathBuf,
+}
+
+impl StagingArea {
+    pub fn new(game_path: &Path) -> Self {
+        StagingArea { game_path: game_path.to_path_buf(), root: game_path.join(STAGING_DIR) }
+    }
+
+    /// Folder the update downloads are written to, laid out like the game folder.
+    pub fn files_path(&self) -> PathBuf {
+        self.root.join(STAGED_FILES_DIR)
+    }
+
+    fn backup_path(&self) -> PathBuf {
+        self.root.join(BACKUP_DIR)
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.root.join(JOURNAL_FILE_NAME)
+    }
+
+    /// Creates the staging folder and returns it. Staged files of an earlier,
+    /// failed update are kept, so their downloads do not start over.
+    pub fn prepare(&self) -> Result<PathBuf, String> {
+        if self.journal_path().exists() {
+            return Err("An interrupted update has to be recovered first".to_string());
+        }
+        let files_path = self.files_path();
+        fs::create_dir_all(&files_path).map_err(|e| format!("Failed to create {:?}: {}", files_path, e))?;
+        Ok(files_path)
+    }
+
+    /// Returns the staged copy of a manifest path.
+    pub fn staged_file(&self, path: &str) -> Result<PathBuf, String> {
+        Ok(self.files_path().join(sanitize_manifest_path(path)?))
+    }
+
+    /// Moves the staged `paths` into the game folder.
+    ///
+    /// Nothing is touched unless every file is staged. If a move fails, the
+    /// files already moved are put back and the previous files restored.
+    pub fn commit(&self, paths: &[String], version: Option<u64>) -> Result<(), String> {
+        for path in paths {
+            if !self.staged_file(path)?.is_file() {
+                return Err(format!("{} is not staged", path));
+            }
+        }
+        let backup_path = self.backup_path();
+        fs::create_dir_all(&backup_path).map_err(|e| format!("Failed to create {:?}: {}", backup_path, e))?;
+
+        let files = paths
+            .iter()
+            .map(|path| {
+                Ok(JournalEntry {
+                    path: path.clone(),
+                    replaces_existing: resolve_game_file(&self.game_path, path)?.exists(),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let journal = Journal { state: CommitState::Committing, version, files };
+        self.write_journal(&journal)?;
+        info!("Committing {} staged file(s)", journal.files.len());
+
+        match self.roll_forward(&journal) {
+            Ok(()) => self.finish(journal),
+            Err(e) => {
+                // The journal stays if the rollback fails too, the next start tries again
+                if let Err(rollback_error) = self.roll_back(&journal) {
+                    return Err(format!("Update commit failed ({}) and could not be rolled back: {}", e, rollback_error));
+                }
+                self.remove_journal()?;
+                Err(format!("Update commit failed, previous files restored: {}", e))
+            }
+        }
+    }
+
+    /// Finishes or undoes a commit interrupted in an earlier run.
+    ///
+    /// # Returns
+    ///
+    /// What was done, or `None` when no commit was pending.
+    pub fn recover(&self) -> Result<Option<Recovery>, String> {
+        let journal = match self.read_journal()? {
+            Some(journal) => journal,
+            None => return Ok(None),
+        };
+
+        match journal.state {
+            CommitState::Committed => {
+                self.finish(journal)?;
+                Ok(Some(Recovery::Cleaned))
+            }
+            CommitState::Committing => {
+                info!("Resuming an interrupted commit of {} file(s)", journal.files.len());
+                let version = journal.version;
+                match self.roll_forward(&journal) {
+                    Ok(()) => {
+                        self.finish(journal)?;
+                        Ok(Some(Recovery::Resumed(version)))
+                    }
+                    Err(e) => {
+                        warn!("Resuming the commit failed ({}), rolling back", e);
+                        self.roll_back(&journal)?;
+                        self.remove_journal()?;
+                        Ok(Some(Recovery::RolledBack))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves every journal entry from the staging area into the game folder,
+    /// skipping entries a previous attempt already moved.
+    fn roll_forward(&self, journal: &Journal) -> Result<(), String> {
+        for entry in &journal.files {
+            let (live, staged, backup) = self.entry_paths(&entry.path)?;
+
+            if !staged.exists() {
+                if entry.is_moved_in(&live, &staged, &backup) {
+                    continue;
+                }
+                return Err(format!("Staged copy of {} is missing", entry.path));
+            }
+            if live.exists() && !backup.exists() {
+                move_file(&live, &backup)?;
+            }
+            move_file(&staged, &live)?;
+        }
+        Ok(())
+    }
+
+    /// Restores the previous file set. New files go back to the staging area,
+    /// so a later update can still use them.
+    fn roll_back(&self, journal: &Journal) -> Result<(), String> {
+        for entry in journal.files.iter().rev() {
+            let (live, staged, backup) = self.entry_paths(&entry.path)?;
+
+            if entry.is_moved_in(&live, &staged, &backup) {
+                move_file(&live, &staged)?;
+            }
+            if backup.exists() {
+                move_file(&backup, &live)?;
+            }
+        }
+        remove_dir(&self.backup_path())?;
+        info!("Rolled back {} file(s)", journal.files.len());
+        Ok(())
+    }
+
+    /// Marks the commit done and removes the backups and what is left of the
+    /// staging area, the journal last.
+    fn finish(&self, mut journal: Journal) -> Result<(), String> {
+        if journal.state != CommitState::Committed {
+            journal.state = CommitState::Committed;
+            self.write_journal(&journal)?;
+        }
+        remove_dir(&self.backup_path())?;
+        remove_dir(&self.files_path())?;
+        self.remove_journal()?;
+        remove_dir(&self.root)?;
+        info!("Committed {} file(s)", journal.files.len());
+        Ok(())
+    }
+
+    /// Live, staged and backup path of a manifest path.
+    fn entry_paths(&self, path: &str) -> Result<(PathBuf, PathBuf, PathBuf), String> {
+        Ok((
+            resolve_game_file(&self.game_path, path)?,
+            self.staged_file(path)?,
+            self.backup_path().join(sanitize_manifest_path(path)?),
+        ))
+    }
+
+    fn read_journal(&self) -> Result<Option<Journal>, String> {
+        let path = self.journal_path();
+        match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map(Some)
+                .map_err(|e| format!("Invalid update journal {:?}: {}", path, e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {:?}: {}", path, e)),
+        }
+    }
+
+    /// Writes the journal through a temporary file, so it is never seen half written.
+    fn write_journal(&self, journal: &Journal) -> Result<(), String> {
+        let path = self.journal_path();
+        let temp_path = path.with_extension("json.tmp");
+        let contents = serde_json::to_vec(journal).map_err(|e| e.to_string())?;
+
+        let mut file = File::create(&temp_path).map_err(|e| format!("Failed to write {:?}: {}", temp_path, e))?;
+        file.write_all(&contents)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("Failed to write {:?}: {}", temp_path, e))?;
+        drop(file);
+
+        fs::rename(&temp_path, &path).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    fn remove_journal(&self) -> Result<(), String> {
+        match fs::remove_file(self.journal_path()) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(format!("Failed to remove the update journal: {}", e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn move_file(source: &Path, destination: &Path) -> Result<(), String> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    fs::rename(source, destination).map_err(|e| format!("Failed to move {:?} to {:?}: {}", source, destination, e))
+}
+
+fn remove_dir(path: &Path) -> Result<(), String> {
+    match fs::remove_dir_all(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(format!("Failed to remove {:?}: {}", path, e)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_folder(name: &str) -> PathBuf {
+        let game_path = std::env::temp_dir().join(format!("teralaunch-staging-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&game_path);
+        fs::create_dir_all(game_path.join("S1Game")).unwrap();
+        fs::write(game_path.join("S1Game/S1Data.gpk"), "old data").unwrap();
+        fs::write(game_path.join("S1Game/Kept.gpk"), "kept").unwrap();
+        game_path
+    }
+
+    fn stage(staging: &StagingArea, path: &str, contents: &str) {
+        staging.prepare().unwrap();
+        let staged = staging.staged_file(path).unwrap();
+        fs::create_dir_all(staged.parent().unwrap()).unwrap();
+        fs::write(staged, contents).unwrap();
+    }
+
+    fn read(game_path: &Path, path: &str) -> Option<String> {
+        fs::read_to_string(game_path.join(path)).ok()
+    }
+
+    fn paths() -> Vec<String> {
+        vec!["S1Game/S1Data.gpk".to_string(), "S1Game/New/New.gpk".to_string()]
+    }
+
+    #[test]
+    fn commit_swaps_in_the_staged_files() {
+        let game_path = game_folder("commit");
+        let staging = StagingArea::new(&game_path);
+        stage(&staging, "S1Game/S1Data.gpk", "new data");
+        stage(&staging, "S1Game/New/New.gpk", "new file");
+
+        staging.commit(&paths(), Some(7)).unwrap();
+
+        assert_eq!(read(&game_path, "S1Game/S1Data.gpk").as_deref(), Some("new data"));
+        assert_eq!(read(&game_path, "S1Game/New/New.gpk").as_deref(), Some("new file"));
+        assert_eq!(read(&game_path, "S1Game/Kept.gpk").as_deref(), Some("kept"));
+        assert!(!game_path.join(STAGING_DIR).exists());
+        fs::remove_dir_all(&game_path).unwrap();
+    }
+
+    #[test]
+    fn commit_refuses_missing_staged_files() {
+        let game_path = game_folder("missing");
+        let staging = StagingArea::new(&game_path);
+        stage(&staging, "S1Game/S1Data.gpk", "new data");
+
+        assert!(staging.commit(&paths(), None).is_err());
+        assert_eq!(read(&game_path, "S1Game/S1Data.gpk").as_deref(), Some("old data"));
+        assert_eq!(staging.recover().unwrap(), None);
+        fs::remove_dir_all(&game_path).unwrap();
+    }
+
+    /// Journal and file system as left by a crash after the first file was swapped.
+    fn interrupted_commit(name: &str, version: Option<u64>) -> (PathBuf, StagingArea) {
+        let game_path = game_folder(name);
+        let staging = StagingArea::new(&game_path);
+        stage(&staging, "S1Game/S1Data.gpk", "new data");
+        stage(&staging, "S1Game/New/New.gpk", "new file");
+
+        let journal = Journal {
+            state: CommitState::Committing,
+            version,
+            files: vec![
+                JournalEntry { path: "S1Game/S1Data.gpk".to_string(), replaces_existing: true },
+                JournalEntry { path: "S1Game/New/New.gpk".to_string(), replaces_existing: false },
+            ],
+        };
+        staging.write_journal(&journal).unwrap();
+        let (live, staged, backup) = staging.entry_paths("S1Game/S1Data.gpk").unwrap();
+        move_file(&live, &backup).unwrap();
+        move_file(&staged, &live).unwrap();
+        (game_path, staging)
+    }
+
+    #[test]
+    fn recover_resumes_an_interrupted_commit() {
+        let (game_path, staging) = interrupted_commit("resume", Some(3));
+        assert!(staging.prepare().is_err());
+
+        assert_eq!(staging.recover().unwrap(), Some(Recovery::Resumed(Some(3))));
+        assert_eq!(read(&game_path, "S1Game/S1Data.gpk").as_deref(), Some("new data"));
+        assert_eq!(read(&game_path, "S1Game/New/New.gpk").as_deref(), Some("new file"));
+        assert!(!game_path.join(STAGING_DIR).exists());
+        assert_eq!(staging.recover().unwrap(), None);
+        fs::remove_dir_all(&game_path).unwrap();
+    }
+
+    #[test]
+    fn recover_rolls_back_when_staged_files_are_gone() {
+        let (game_path, staging) = interrupted_commit("rollback", None);
+        fs::remove_file(staging.staged_file("S1Game/New/New.gpk").unwrap()).unwrap();
+
+        assert_eq!(staging.recover().unwrap(), Some(Recovery::RolledBack));
+        assert_eq!(read(&game_path, "S1Game/S1Data.gpk").as_deref(), Some("old data"));
+        assert_eq!(read(&game_path, "S1Game/New/New.gpk"), None);
+        // The verified new file is kept for the next attempt
+        assert_eq!(
+            fs::read_to_string(staging.staged_file("S1Game/S1Data.gpk").unwrap()).unwrap(),
+            "new data"
+        );
+        assert!(staging.prepare().is_ok());
+        fs::remove_dir_all(&game_path).unwrap();
+    }
+
+    #[test]
+    fn recover_does_not_skip_a_replaced_file_whose_staged_copy_is_lost() {
+        let game_path = game_folder("lost");
+        let staging = StagingArea::new(&game_path);
+        stage(&staging, "S1Game/S1Data.gpk", "new data");
+        stage(&staging, "S1Game/New/New.gpk", "new file");
+        let journal = Journal {
+            state: CommitState::Committing,
+            version: Some(4),
+            files: vec![
+                JournalEntry { path: "S1Game/S1Data.gpk".to_string(), replaces_existing: true },
+                JournalEntry { path: "S1Game/New/New.gpk".to_string(), replaces_existing: false },
+            ],
+        };
+        staging.write_journal(&journal).unwrap();
+        // Lost before it was swapped in, the live file is still the old release
+        fs::remove_file(staging.staged_file("S1Game/S1Data.gpk").unwrap()).unwrap();
+
+        assert_eq!(staging.recover().unwrap(), Some(Recovery::RolledBack));
+        assert_eq!(read(&game_path, "S1Game/S1Data.gpk").as_deref(), Some("old data"));
+        assert_eq!(read(&game_path, "S1Game/New/New.gpk"), None);
+        fs::remove_dir_all(&game_path).unwrap();
+    }
+}
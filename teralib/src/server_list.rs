@@ -0,0 +1,431 @@
+use log::info;
+use reqwest::Url;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::{env, error::Error, path::PathBuf, time::Duration};
+
+/// The newest server list document version understood by the launcher.
+///
+/// Documents without a `version` field are treated as version 1, the original
+/// JSON layout served by the launcher web backend.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// A server list, independent of the format it was loaded from.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerListDocument {
+    /// Schema version of the document.
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    /// The servers shown in the in-game server selection.
+    pub servers: Vec<ServerEntry>,
+    /// Sort criterion passed to the client, if the source specifies one.
+    #[serde(default)]
+    pub sort_criterion: Option<u32>,
+}
+
+/// A single server of a `ServerListDocument`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerEntry {
+    pub id: u32,
+    pub name: String,
+    /// Display title, defaults to `name` when absent.
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub queue: String,
+    #[serde(default)]
+    pub population: String,
+    /// IPv4 address of the server, takes precedence over `host`.
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Host name of the server, used when no `address` is given.
+    #[serde(default)]
+    pub host: Option<String>,
+    pub port: u32,
+    #[serde(default = "default_available", deserialize_with = "deserialize_available")]
+    pub available: bool,
+    #[serde(default)]
+    pub unavailable_message: String,
+}
+
+impl ServerEntry {
+    /// Returns the display title of the server.
+    pub fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Version 1 server entry, where every display field is required.
+#[derive(Deserialize)]
+struct LegacyServerEntry {
+    id: u32,
+    name: String,
+    title: String,
+    category: String,
+    queue: String,
+    population: String,
+    #[serde(default)]
+    address: Option<String>,
+    #[serde(default)]
+    host: Option<String>,
+    port: u32,
+    #[serde(default, deserialize_with = "deserialize_available")]
+    available: bool,
+    #[serde(default)]
+    unavailable_message: String,
+}
+
+impl From<LegacyServerEntry> for ServerEntry {
+    fn from(entry: LegacyServerEntry) -> Self {
+        ServerEntry {
+            id: entry.id,
+            name: entry.name,
+            title: Some(entry.title),
+            category: entry.category,
+            queue: entry.queue,
+            population: entry.population,
+            address: entry.address,
+            host: entry.host,
+            port: entry.port,
+            available: entry.available,
+            unavailable_message: entry.unavailable_message,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyServerListDocument {
+    servers: Vec<LegacyServerEntry>,
+    #[serde(default)]
+    sort_criterion: Option<u32>,
+}
+
+/// Root element of the classic XML server list (`<serverlist>`).
+#[derive(Deserialize)]
+struct XmlServerList {
+    #[serde(rename = "server", default)]
+    servers: Vec<XmlServer>,
+}
+
+/// A `<server>` element of the classic XML server list.
+#[derive(Deserialize)]
+struct XmlServer {
+    id: u32,
+    #[serde(default)]
+    ip: Option<String>,
+    #[serde(default)]
+    host: Option<String>,
+    port: u32,
+    #[serde(default)]
+    category: XmlText,
+    name: XmlText,
+    #[serde(default)]
+    crowdness: XmlText,
+    #[serde(default)]
+    open: XmlText,
+    #[serde(default)]
+    server_stat: Option<String>,
+    #[serde(default)]
+    popup: Option<String>,
+}
+
+/// Text content of an element that may also carry attributes (e.g. `sort`).
+#[derive(Default, Deserialize)]
+struct XmlText {
+    #[serde(rename = "$text", default)]
+    value: String,
+}
+
+impl From<XmlServer> for ServerEntry {
+    fn from(server: XmlServer) -> Self {
+        let name = server.name.value.trim().to_string();
+        // A non-zero server_stat marks the server as down in the classic format
+        let available = server
+            .server_stat
+            .as_deref()
+            .map(|stat| parse_hex_or_decimal(stat) == Some(0))
+            .unwrap_or(true);
+
+        ServerEntry {
+            id: server.id,
+            title: Some(name.clone()),
+            name,
+            category: server.category.value.trim().to_string(),
+            queue: server.open.value.trim().to_string(),
+            population: server.crowdness.value.trim().to_string(),
+            address: server.ip.map(|ip| ip.trim().to_string()),
+            host: server.host.map(|host| host.trim().to_string()),
+            port: server.port,
+            available,
+            unavailable_message: server.popup.unwrap_or_default().trim().to_string(),
+        }
+    }
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+fn default_available() -> bool {
+    true
+}
+
+/// Accepts `available` either as a boolean or as the integer flag used by version 1.
+fn deserialize_available<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Bool(b) => Ok(b),
+        Value::Number(n) => Ok(n.as_u64().unwrap_or(0) != 0),
+        Value::Null => Ok(false),
+        other => Err(serde::de::Error::custom(format!(
+            "invalid 'available' value: {}",
+            other
+        ))),
+    }
+}
+
+fn parse_hex_or_decimal(value: &str) -> Option<u32> {
+    let value = value.trim();
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Parses a JSON server list document.
+///
+/// Documents without a `version` field are parsed with the strict version 1 rules,
+/// newer documents are checked against `CURRENT_VERSION`.
+///
+/// # Arguments
+///
+/// * `json` - Reference to serde_json::Value with server list data.
+///
+/// # Returns
+///
+/// The parsed `ServerListDocument`, or an error if the document is invalid.
+pub fn parse_json(json: &Value) -> Result<ServerListDocument, Box<dyn Error>> {
+    let version = match json.get("version") {
+        Some(v) => v.as_u64().ok_or("Invalid 'version' field")? as u32,
+        None => legacy_version(),
+    };
+
+    match version {
+        1 => {
+            let legacy = LegacyServerListDocument::deserialize(json)?;
+            Ok(ServerListDocument {
+                version,
+                servers: legacy.servers.into_iter().map(ServerEntry::from).collect(),
+                sort_criterion: legacy.sort_criterion,
+            })
+        }
+        2..=CURRENT_VERSION => Ok(ServerListDocument::deserialize(json)?),
+        v => Err(format!(
+            "Unsupported server list version {} (newest supported is {})",
+            v, CURRENT_VERSION
+        )
+        .into()),
+    }
+}
+
+/// Parses a classic XML server list (`<serverlist><server>...</server></serverlist>`).
+///
+/// # Arguments
+///
+/// * `xml` - The XML document as a string slice.
+///
+/// # Returns
+///
+/// The parsed `ServerListDocument`, or an error if the document is invalid.
+pub fn parse_xml(xml: &str) -> Result<ServerListDocument, Box<dyn Error>> {
+    let list: XmlServerList = quick_xml::de::from_str(xml)?;
+    if list.servers.is_empty() {
+        return Err("No servers found in XML".into());
+    }
+
+    Ok(ServerListDocument {
+        version: CURRENT_VERSION,
+        servers: list.servers.into_iter().map(ServerEntry::from).collect(),
+        sort_criterion: None,
+    })
+}
+
+/// Parses a server list document, detecting whether it is JSON or XML.
+///
+/// # Arguments
+///
+/// * `data` - The raw document as returned by the server list source.
+///
+/// # Returns
+///
+/// The parsed `ServerListDocument`, or an error if the document is invalid.
+pub fn parse_document(data: &str) -> Result<ServerListDocument, Box<dyn Error>> {
+    let data = data.trim_start_matches('\u{feff}').trim_start();
+    if data.starts_with('<') {
+        parse_xml(data)
+    } else {
+        let json: Value = serde_json::from_str(data)?;
+        parse_json(&json)
+    }
+}
+
+/// Resolves a `file://` server list source to a local path.
+///
+/// Relative paths are resolved against the directory of the launcher executable.
+fn resolve_file_source(source: &str) -> Result<PathBuf, Box<dyn Error>> {
+    // `file:///C:/tera/servers.xml` style URLs carry no host
+    let url = Url::parse(source)?;
+    if let None | Some("") | Some("localhost") = url.host_str() {
+        if let Ok(path) = url.to_file_path() {
+            return Ok(path);
+        }
+    }
+
+    // `file://servers.xml` is taken as a path relative to the launcher
+    let path = PathBuf::from(source.trim_start_matches("file://"));
+    if path.is_absolute() {
+        return Ok(path);
+    }
+
+    let exe_path = env::current_exe()?;
+    let exe_dir = exe_path.parent().ok_or("Failed to resolve launcher directory")?;
+    Ok(exe_dir.join(path))
+}
+
+/// Loads and parses the server list from the given source.
+///
+/// The source can be an `http(s)://` URL or a `file://` path to a static
+/// document, so operators can run without a web backend.
+///
+/// # Arguments
+///
+/// * `source` - The server list source, usually the `SERVER_LIST_URL` config value.
+///
+/// # Returns
+///
+/// The parsed `ServerListDocument`, or an error if loading or parsing failed.
+pub async fn load(source: &str) -> Result<ServerListDocument, Box<dyn Error>> {
+    let data = if source.starts_with("file://") {
+        let path = resolve_file_source(source)?;
+        info!("Loading server list from file: {:?}", path);
+        tokio::fs::read_to_string(&path).await?
+    } else {
+        info!("Loading server list from URL: {}", source);
+        let client = reqwest::Client::new();
+        let response = client
+            .get(source)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(format!("Unsuccessful HTTP response: {}", response.status()).into());
+        }
+
+        response.text().await?
+    };
+
+    parse_document(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn version_1_requires_every_display_field() {
+        let document = parse_json(&json!({
+            "servers": [{
+                "id": 1, "name": "Mystel", "title": "Mystel (PvE)", "category": "PvE",
+                "queue": "0", "population": "low", "address": "127.0.0.1", "port": 7801, "available": 1
+            }],
+            "sort_criterion": 3
+        }))
+        .unwrap();
+        assert_eq!(document.version, 1);
+        assert_eq!(document.sort_criterion, Some(3));
+        assert_eq!(document.servers[0].title(), "Mystel (PvE)");
+        assert!(document.servers[0].available);
+
+        let missing_title = json!({
+            "servers": [{ "id": 1, "name": "Mystel", "category": "PvE", "queue": "0", "population": "low", "port": 7801 }]
+        });
+        assert!(parse_json(&missing_title).is_err());
+    }
+
+    #[test]
+    fn version_2_fills_in_defaults() {
+        let document = parse_json(&json!({
+            "version": 2,
+            "servers": [
+                { "id": 1, "name": "Mystel", "host": "tera.example.com", "port": 7801 },
+                { "id": 2, "name": "Seren", "port": 7802, "available": null, "unavailable_message": "Maintenance" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(document.version, 2);
+        assert_eq!(document.sort_criterion, None);
+
+        let mystel = &document.servers[0];
+        assert_eq!(mystel.title(), "Mystel");
+        assert_eq!(mystel.host.as_deref(), Some("tera.example.com"));
+        assert_eq!(mystel.category, "");
+        assert!(mystel.available);
+
+        let seren = &document.servers[1];
+        assert!(!seren.available);
+        assert_eq!(seren.unavailable_message, "Maintenance");
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let error = parse_json(&json!({ "version": CURRENT_VERSION + 1, "servers": [] })).unwrap_err();
+        assert!(error.to_string().contains("Unsupported server list version 3"));
+        assert!(parse_json(&json!({ "version": "2", "servers": [] })).is_err());
+    }
+
+    #[test]
+    fn parses_the_classic_xml_list() {
+        let document = parse_document(
+            "\u{feff}<serverlist>
+                <server>
+                    <id>1</id><ip>127.0.0.1</ip><port>7801</port>
+                    <category sort=\"1\">PvE</category><name raw_name=\"Mystel\"> Mystel </name>
+                    <crowdness sort=\"1\">low</crowdness><open sort=\"1\">0</open>
+                    <server_stat>0x00000000</server_stat>
+                </server>
+                <server>
+                    <id>2</id><host>tera.example.com</host><port>7802</port><name>Seren</name>
+                    <server_stat>0x00000001</server_stat><popup> Maintenance </popup>
+                </server>
+            </serverlist>",
+        )
+        .unwrap();
+        assert_eq!(document.version, CURRENT_VERSION);
+
+        let mystel = &document.servers[0];
+        assert_eq!(mystel.title(), "Mystel");
+        assert_eq!(mystel.address.as_deref(), Some("127.0.0.1"));
+        assert_eq!(mystel.category, "PvE");
+        assert_eq!(mystel.population, "low");
+        assert!(mystel.available);
+
+        let seren = &document.servers[1];
+        assert_eq!(seren.host.as_deref(), Some("tera.example.com"));
+        assert!(!seren.available);
+        assert_eq!(seren.unavailable_message, "Maintenance");
+
+        assert!(parse_document("<serverlist></serverlist>").is_err());
+    }
+
+    #[test]
+    fn byte_order_mark_is_stripped_from_json() {
+        let document = parse_document("\u{feff}{\"version\": 2, \"servers\": [{\"id\": 1, \"name\": \"Mystel\", \"port\": 7801}]}").unwrap();
+        assert_eq!(document.servers.len(), 1);
+    }
+}
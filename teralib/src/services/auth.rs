@@ -0,0 +1,294 @@
+// Standard library imports
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+// Third-party imports
+use log::info;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::get_config_value;
+use crate::error::LauncherError;
+use crate::patch::hash_cache::CACHE_DIR_NAME;
+
+const SESSION_FILE_NAME: &str = "session.json";
+
+/// Returns where the command line keeps the session between runs, next to the
+/// hash cache.
+pub fn session_file_path() -> Result<PathBuf, LauncherError> {
+    dirs_next::data_local_dir()
+        .map(|data_dir| data_dir.join(CACHE_DIR_NAME).join(SESSION_FILE_NAME))
+        .ok_or_else(|| LauncherError::Config("Local data folder not found".to_string()))
+}
+
+/// Answer of the login server.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LoginResponse {
+    #[serde(rename = "Return")]
+    pub return_value: bool,
+    #[serde(rename = "ReturnCode")]
+    pub return_code: i32,
+    #[serde(rename = "Msg")]
+    pub msg: String,
+    #[serde(rename = "CharacterCount")]
+    pub character_count: String,
+    #[serde(rename = "Permission")]
+    pub permission: i32,
+    #[serde(rename = "Privilege")]
+    pub privilege: i32,
+    #[serde(rename = "UserNo")]
+    pub user_no: i32,
+    #[serde(rename = "UserName")]
+    pub user_name: String,
+    #[serde(rename = "AuthKey")]
+    pub auth_key: String,
+}
+
+impl LoginResponse {
+    /// Returns the session of a successful login, or the message of the server.
+    pub fn into_auth_info(self) -> Result<AuthInfo, LauncherError> {
+        if !self.return_value || self.msg != "success" {
+            let msg = if self.msg.is_empty() { format!("Login failed ({})", self.return_code) } else { self.msg };
+            return Err(LauncherError::Auth(msg));
+        }
+        Ok(AuthInfo {
+            character_count: self.character_count,
+            user_no: self.user_no,
+            user_name: self.user_name,
+            auth_key: self.auth_key,
+        })
+    }
+}
+
+/// The logged in account, handed to the game on launch.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct AuthInfo {
+    pub character_count: String,
+    pub user_no: i32,
+    pub user_name: String,
+    pub auth_key: String,
+}
+
+// The auth key is left out, so a session never ends up in a log
+impl fmt::Debug for AuthInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthInfo")
+            .field("character_count", &self.character_count)
+            .field("user_no", &self.user_no)
+            .field("user_name", &self.user_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Logs in against the account server and keeps the session of the account.
+#[derive(Default)]
+pub struct AuthService {
+    client: Client,
+    /// Login endpoint to use instead of `LOGIN_ACTION_URL`.
+    login_url: Option<String>,
+    auth_info: RwLock<AuthInfo>,
+}
+
+impl AuthService {
+    pub fn new() -> Self {
+        AuthService::default()
+    }
+
+    /// Logs in against the given endpoint, e.g. a test server.
+    pub fn with_login_url(login_url: &str) -> Self {
+        AuthService { login_url: Some(login_url.to_string()), ..AuthService::default() }
+    }
+
+    /// Sends the credentials, form-encoded, to `LOGIN_ACTION_URL`.
+    ///
+    /// # Returns
+    ///
+    /// The body of the answer, normally a `LoginResponse` as JSON. The
+    /// session is not changed; see `set_auth_info`.
+    pub async fn login(&self, username: &str, password: &str) -> Result<String, LauncherError> {
+        let url = self.login_url.clone().unwrap_or_else(|| get_config_value("LOGIN_ACTION_URL"));
+
+        let res = self.client
+            .post(url)
+            .form(&[("login", username), ("password", password)])
+            .send().await?;
+
+        let status = res.status();
+        let body = res.text().await?;
+
+        match serde_json::from_str::<Value>(&body) {
+            Ok(json) => Ok(json.to_string()),
+            Err(_) if !status.is_success() => Err(LauncherError::Auth(format!("Login server answered {}", status))),
+            Err(_) => Ok(body),
+        }
+    }
+
+    /// Logs in and keeps the session of the account.
+    pub async fn authenticate(&self, username: &str, password: &str) -> Result<AuthInfo, LauncherError> {
+        let body = self.login(username, password).await?;
+        let response: LoginResponse = serde_json::from_str(&body)
+            .map_err(|e| LauncherError::Auth(format!("Invalid login response: {}", e)))?;
+
+        let auth_info = response.into_auth_info()?;
+        self.set_auth_info(auth_info.clone());
+        Ok(auth_info)
+    }
+
+    pub fn set_auth_info(&self, auth_info: AuthInfo) {
+        // The auth key is a credential and never logged
+        info!("Auth info set:");
+        info!("User Name: {}", auth_info.user_name);
+        info!("User No: {}", auth_info.user_no);
+        info!("Character Count: {}", auth_info.character_count);
+
+        *self.auth_info.write().unwrap() = auth_info;
+    }
+
+    pub fn auth_info(&self) -> AuthInfo {
+        self.auth_info.read().unwrap().clone()
+    }
+
+    /// Returns true once a login succeeded or a session was restored.
+    pub fn is_logged_in(&self) -> bool {
+        !self.auth_info.read().unwrap().auth_key.is_empty()
+    }
+
+    /// Forgets the session.
+    pub fn logout(&self) {
+        *self.auth_info.write().unwrap() = AuthInfo::default();
+    }
+
+    /// Writes the session to `path`, so another process can launch the game
+    /// with it. The file holds the auth key in clear text.
+    pub fn save_session(&self, path: &Path) -> Result<(), LauncherError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let session = serde_json::to_string(&self.auth_info())
+            .map_err(|e| LauncherError::Auth(e.to_string()))?;
+        fs::write(path, session)?;
+        Ok(())
+    }
+
+    /// Restores a session written by `save_session`.
+    ///
+    /// # Returns
+    ///
+    /// False when there is no saved session.
+    pub fn load_session(&self, path: &Path) -> Result<bool, LauncherError> {
+        let session = match fs::read_to_string(path) {
+            Ok(session) => session,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let auth_info: AuthInfo = serde_json::from_str(&session)
+            .map_err(|e| LauncherError::Auth(format!("Invalid session file {:?}: {}", path, e)))?;
+
+        *self.auth_info.write().unwrap() = auth_info;
+        Ok(self.is_logged_in())
+    }
+
+    /// Forgets the session and removes its file.
+    pub fn clear_session(&self, path: &Path) -> Result<(), LauncherError> {
+        self.logout();
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(return_value: bool, msg: &str) -> LoginResponse {
+        LoginResponse {
+            return_value,
+            return_code: 0,
+            msg: msg.to_string(),
+            character_count: "0|2800,3".to_string(),
+            permission: 0,
+            privilege: 0,
+            user_no: 7,
+            user_name: "player".to_string(),
+            auth_key: "key".to_string(),
+        }
+    }
+
+    #[test]
+    fn only_successful_logins_yield_a_session() {
+        let auth_info = response(true, "success").into_auth_info().unwrap();
+        assert_eq!(auth_info.user_no, 7);
+        assert_eq!(auth_info.auth_key, "key");
+
+        match response(false, "invalid password").into_auth_info() {
+            Err(LauncherError::Auth(msg)) => assert_eq!(msg, "invalid password"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn auth_key_is_never_printed() {
+        let auth_info = response(true, "success").into_auth_info().unwrap();
+        let printed = format!("{:?}", auth_info);
+        assert!(printed.contains("player"));
+        assert!(!printed.contains("key"));
+    }
+
+    #[tokio::test]
+    async fn credentials_are_form_encoded() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            // The form body is the last part of the request
+            while !String::from_utf8_lossy(&request).contains("password=") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let body = r#"{"Return":true,"ReturnCode":0,"Msg":"success","CharacterCount":"0","Permission":0,"Privilege":0,"UserNo":7,"UserName":"a&b","AuthKey":"key"}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let auth = AuthService::with_login_url(&format!("http://{}/login", addr));
+        let auth_info = auth.authenticate("a&b", "p=1&x").await.unwrap();
+        assert_eq!(auth_info.user_name, "a&b");
+        assert!(auth.is_logged_in());
+
+        let request = server.await.unwrap();
+        assert!(request.contains("application/x-www-form-urlencoded"));
+        assert!(request.ends_with("login=a%26b&password=p%3D1%26x"));
+    }
+
+    #[test]
+    fn session_survives_a_restart() {
+        let path = std::env::temp_dir()
+            .join(format!("teralaunch-session-{}", std::process::id()))
+            .join(SESSION_FILE_NAME);
+        let auth = AuthService::new();
+        auth.set_auth_info(response(true, "success").into_auth_info().unwrap());
+        auth.save_session(&path).unwrap();
+
+        let restored = AuthService::new();
+        assert!(restored.load_session(&path).unwrap());
+        assert_eq!(restored.auth_info().user_name, "player");
+
+        restored.clear_session(&path).unwrap();
+        assert!(!restored.is_logged_in());
+        assert!(!AuthService::new().load_session(&path).unwrap());
+    }
+}
@@ -0,0 +1,136 @@
+// Standard library imports
+use std::sync::Arc;
+
+// Third-party imports
+use log::{error, info};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+
+use super::auth::AuthInfo;
+use super::progress::ProgressReporter;
+use super::settings::SettingsService;
+use crate::error::LauncherError;
+use crate::{get_game_status_receiver, reset_global_state, run_game};
+
+/// Starts the game and tracks whether it is running.
+pub struct LaunchService {
+    status_receiver: Mutex<watch::Receiver<bool>>,
+    is_launching: Arc<Mutex<bool>>,
+}
+
+impl Default for LaunchService {
+    fn default() -> Self {
+        LaunchService {
+            status_receiver: Mutex::new(get_game_status_receiver()),
+            is_launching: Arc::new(Mutex::new(false)),
+        }
+    }
+}
+
+impl LaunchService {
+    pub fn new() -> Self {
+        LaunchService::default()
+    }
+
+    /// Returns true while the game is starting or running.
+    pub async fn is_game_running(&self) -> bool {
+        let status = *self.status_receiver.lock().await.borrow();
+        let is_launching = *self.is_launching.lock().await;
+        status || is_launching
+    }
+
+    /// Clears the launching flag, e.g. after a failed launch or a logout.
+    pub async fn reset_launch_state(&self) {
+        *self.is_launching.lock().await = false;
+    }
+
+    /// Starts the game from the configured game folder with the given session.
+    ///
+    /// # Returns
+    ///
+    /// The task watching the game, which ends once the game has exited.
+    pub async fn launch(
+        &self,
+        settings: &SettingsService,
+        auth_info: AuthInfo,
+        reporter: Arc<dyn ProgressReporter>,
+    ) -> Result<JoinHandle<()>, LauncherError> {
+        let mut is_launching = self.is_launching.lock().await;
+        if *is_launching {
+            return Err(LauncherError::GameLaunching);
+        }
+        *is_launching = true;
+
+        let is_running = *self.status_receiver.lock().await.borrow();
+
+        if is_running {
+            *is_launching = false;
+            return Err(LauncherError::GameRunning);
+        }
+
+        let account_name = auth_info.user_no.to_string();
+        let characters_count = auth_info.character_count;
+        let ticket = auth_info.auth_key;
+        let (game_path, game_lang) = match settings.load_config() {
+            Ok(config) => config,
+            Err(e) => {
+                *is_launching = false;
+                return Err(e);
+            }
+        };
+
+        let full_game_path = game_path.join("Binaries").join("Tera.exe");
+
+        if !full_game_path.exists() {
+            *is_launching = false;
+            return Err(LauncherError::GameNotFound(full_game_path));
+        }
+
+        let full_game_path_str = match full_game_path.to_str() {
+            Some(path) => path.to_string(),
+            None => {
+                *is_launching = false;
+                return Err(LauncherError::Launch("Invalid path to game executable".to_string()));
+            }
+        };
+
+        let is_launching_clone = Arc::clone(&self.is_launching);
+
+        Ok(tokio::task::spawn(async move {
+            reporter.game_status_changed(true);
+
+            info!("run_game reached");
+            match
+                run_game(
+                    &account_name,
+                    &characters_count,
+                    &ticket,
+                    &game_lang,
+                    &full_game_path_str
+                ).await
+            {
+                Ok(exit_status) => {
+                    let result = format!("Game exited with status: {:?}", exit_status);
+                    reporter.game_status(&result);
+                    info!("{}", result);
+                }
+                Err(e) => {
+                    let error = format!("Error launching game: {:?}", e);
+                    reporter.game_status(&error);
+                    error!("{}", error);
+                }
+            }
+
+            info!("Game ended");
+            reporter.game_ended();
+
+            let mut is_launching = is_launching_clone.lock().await;
+            *is_launching = false;
+            reporter.game_status_changed(false);
+
+            reset_global_state();
+
+            info!("Game launch state reset");
+        }))
+    }
+}
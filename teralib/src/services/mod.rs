@@ -0,0 +1,5 @@
+pub mod auth;
+pub mod launch;
+pub mod patch;
+pub mod progress;
+pub mod settings;
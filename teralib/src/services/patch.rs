@@ -0,0 +1,1754 @@
+// Standard library imports
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+// Third-party imports
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use rayon::prelude::*;
+use reqwest::Client;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use super::progress::{
+    format_bytes, DownloadReport, FileCheckProgress, FileCheckSummary, HashFileProgress, ProgressReporter, UpdateInfo,
+};
+use super::settings::SettingsService;
+use crate::config::get_config_value;
+use crate::error::LauncherError;
+use crate::patch::archive::{extract_archive, ArchiveInfo, ARCHIVE_DIR, ARCHIVE_FILE_NAME};
+use crate::patch::builder::{build_manifest, read_manifest, BuildOptions};
+use crate::patch::chunk_index::ChunkIndex;
+use crate::patch::compression::Compression;
+use crate::patch::download::{
+    download_file, download_file_with_retry, report_progress, DownloadControl, DownloadFailure, DownloadSession,
+    DownloadSettings, DownloadTracker, MirrorList, CANCELLED,
+};
+use crate::patch::hash_cache::{CachedFileInfo, HashCache};
+use crate::patch::hashing::{hash_file, io_thread_pool, HashAlgorithm};
+use crate::patch::ignore_rules::STAGING_DIR;
+use crate::patch::launcher_update::{compare_versions, LauncherRelease, LauncherSwap, CONFIRM_TIMEOUT, LAUNCHER_FILE_NAME};
+use crate::patch::layout::detect_client_layout;
+use crate::patch::manifest::{
+    delta_file_name, sibling_url, FileInfo, Manifest, ManifestDelta, VersionInfo, MANIFEST_FILE_NAME,
+    MAX_DELTA_CHAIN, VERSION_FILE_NAME,
+};
+use crate::patch::orphans::{clean_orphans, find_orphans, OrphanAction, OrphanedFile};
+use crate::patch::paths::{resolve_game_file, validate_manifest_paths};
+use crate::patch::preflight::{run_preflight, PreflightReport};
+use crate::patch::signing::{generate_signing_key, load_signing_key, sign_documents, verify_manifest, SIGNATURE_EXTENSION};
+use crate::patch::staging::{Recovery, StagingArea};
+
+/// Result of `PatchService::repair`.
+#[derive(Debug, Serialize)]
+pub struct RepairReport {
+    pub files_to_update: Vec<FileInfo>,
+    pub orphans: Vec<OrphanedFile>,
+    pub orphaned_size: u64,
+}
+
+/// Result of `PatchService::clean_orphaned_files`.
+#[derive(Debug, Serialize)]
+pub struct OrphanCleanupReport {
+    pub cleaned: Vec<String>,
+    pub quarantine_path: Option<String>,
+}
+
+/// Result of `PatchService::install`.
+#[derive(Debug, Serialize)]
+pub struct InstallReport {
+    pub game_path: String,
+    pub extracted_files: usize,
+    pub downloaded_files: usize,
+}
+
+/// Result of `PatchService::adopt`.
+#[derive(Debug, Serialize)]
+pub struct AdoptReport {
+    /// Client root found from the selected folder, now the game path.
+    pub game_path: String,
+    pub total_files: usize,
+    pub matching_files: usize,
+    pub missing_files: usize,
+    pub missing_size: u64,
+    pub changed_files: usize,
+    pub changed_size: u64,
+    pub files_to_update: Vec<FileInfo>,
+}
+
+/// Manifest entries a file check compares against, see `load_update_files`.
+struct UpdateSelection {
+    files: Vec<FileInfo>,
+    /// Paths the release no longer contains, only known in delta mode.
+    removed: Vec<String>,
+    version: VersionInfo,
+    /// `current`, `delta`, `full` or `repair`.
+    mode: &'static str,
+}
+
+/// Refuses a release older than the installed one.
+///
+/// Old releases stay validly signed, so a server or a man in the middle could
+/// otherwise replay one to bring back files fixed since.
+fn check_not_downgrade(version: u64, installed_version: Option<u64>) -> Result<(), String> {
+    match installed_version {
+        Some(installed_version) if version < installed_version => Err(format!(
+            "Server offers version {} but version {} is installed, refusing to downgrade",
+            version, installed_version
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Settings of a hash file generation, see `PatchService::generate_hash_file`.
+#[derive(Debug, Clone, Default)]
+pub struct HashFileOptions {
+    pub compression: Option<Compression>,
+    pub chunked: bool,
+    /// Build number, defaults to the previous one plus one.
+    pub version: Option<u64>,
+    pub release_notes: String,
+    pub hash_algorithm: HashAlgorithm,
+}
+
+/// Checks, downloads and installs the game files and the launcher itself.
+///
+/// Holds the state shared between update runs: the hash cache, the release
+/// found by the last file check, the pause and cancel flags and the launcher
+/// build waiting for a restart.
+pub struct PatchService {
+    settings: SettingsService,
+    control: DownloadControl,
+    /// Key the signed documents are verified with, `MANIFEST_PUBLIC_KEY` from config.json.
+    manifest_public_key: String,
+    /// Verification cache, loaded from disk by the first file check.
+    hash_cache: Mutex<Option<HashCache>>,
+    /// Cache file to use instead of the one in the local app data folder.
+    hash_cache_path: Option<PathBuf>,
+    /// Release the last file check compared against, installed once its files are downloaded.
+    pending_version: Mutex<Option<u64>>,
+    /// Paths removed by that release, quarantined once it is installed.
+    pending_removed: Mutex<Vec<String>>,
+    /// Launcher build downloaded and verified, swapped in on restart.
+    staged_launcher: Mutex<Option<LauncherRelease>>,
+}
+
+impl PatchService {
+    pub fn new(settings: SettingsService) -> Self {
+        PatchService {
+            settings,
+            control: DownloadControl::new(),
+            manifest_public_key: get_config_value("MANIFEST_PUBLIC_KEY"),
+            hash_cache: Mutex::new(None),
+            hash_cache_path: None,
+            pending_version: Mutex::new(None),
+            pending_removed: Mutex::new(Vec::new()),
+            staged_launcher: Mutex::new(None),
+        }
+    }
+
+    pub fn settings(&self) -> &SettingsService {
+        &self.settings
+    }
+
+    /// Pause, resume and cancel of the running update.
+    pub fn control(&self) -> &DownloadControl {
+        &self.control
+    }
+
+    /// Fetches a signed document and verifies it against the embedded public key.
+    ///
+    /// # Returns
+    ///
+    /// The verified document, or `None` when the server does not have it.
+    async fn fetch_signed_document(&self, client: &Client, url: &str) -> Result<Option<Vec<u8>>, String> {
+        let settings = self.settings.download_settings();
+        let signature_url = format!("{}.{}", url, SIGNATURE_EXTENSION);
+        let fetch = |url: String| async move {
+            let res = client
+                .get(&url)
+                .send().await
+                .map_err(|e| e.to_string())?;
+            if res.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let res = res.error_for_status().map_err(|e| e.to_string())?;
+            res.bytes().await.map(Some).map_err(|e| e.to_string())
+        };
+
+        let mut attempt = 0;
+        let (document, signature) = loop {
+            let result = async {
+                let document = match fetch(url.to_string()).await? {
+                    Some(document) => document,
+                    None => return Ok(None),
+                };
+                let signature = fetch(signature_url.clone()).await?
+                    .ok_or_else(|| format!("Missing signature for {}", url))?;
+                Ok::<_, String>(Some((document, signature)))
+            }.await;
+
+            match result {
+                Ok(Some(downloaded)) => break downloaded,
+                Ok(None) => return Ok(None),
+                Err(e) if attempt < settings.retries => {
+                    attempt += 1;
+                    let delay = settings.retry_delay(attempt);
+                    error!("Failed to fetch {} ({}), retry {}/{} in {:?}", url, e, attempt, settings.retries, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        // Nothing from the document is looked at before its signature checks out
+        let signature = String::from_utf8_lossy(&signature);
+        verify_manifest(&document, &signature, &self.manifest_public_key)
+            .map_err(|e| format!("{}: {}", url, e))?;
+        info!("Signature verified for {}", url);
+
+        Ok(Some(document.to_vec()))
+    }
+
+    async fn get_server_hash_file(&self) -> Result<Manifest, String> {
+        let client = reqwest::Client::new();
+        let manifest = self.fetch_signed_document(&client, &self.settings.hash_file_url())
+            .await?
+            .ok_or("Hash file not found on the server")?;
+        serde_json::from_slice(&manifest).map_err(|e| format!("Invalid server hash file format: {}", e))
+    }
+
+    /// Fetches the changes between the installed version and the latest release.
+    ///
+    /// # Returns
+    ///
+    /// The combined delta, or `None` when the latest release cannot be reached
+    /// through published deltas and the full manifest has to be used.
+    async fn get_manifest_delta(&self, installed_version: u64, latest: &VersionInfo) -> Result<Option<ManifestDelta>, String> {
+        let client = reqwest::Client::new();
+        let hash_file_url = self.settings.hash_file_url();
+
+        let mut combined: Option<ManifestDelta> = None;
+        let mut current = installed_version;
+        for _ in 0..MAX_DELTA_CHAIN {
+            if current == latest.version {
+                return Ok(combined);
+            }
+
+            let url = sibling_url(&hash_file_url, &delta_file_name(current));
+            let mut delta: ManifestDelta = match self.fetch_signed_document(&client, &url).await? {
+                Some(document) => serde_json::from_slice(&document).map_err(|e| format!("Invalid delta {}: {}", url, e))?,
+                None => {
+                    info!("No delta published from version {}", current);
+                    return Ok(None);
+                }
+            };
+            if delta.from_version != current {
+                return Err(format!("Delta {} starts at version {} instead of {}", url, delta.from_version, current));
+            }
+            if delta.version <= current {
+                return Err(format!("Delta {} does not lead to a newer version", url));
+            }
+            if delta.version > latest.version {
+                return Err(format!("Delta {} leads past the latest version {}", url, latest.version));
+            }
+            let hash_algorithm = delta.hash_algorithm;
+            delta.files.iter_mut().for_each(|f| f.hash_algorithm = hash_algorithm);
+
+            current = delta.version;
+            combined = Some(match combined {
+                Some(combined) => combined.chain(delta)?,
+                None => delta,
+            });
+        }
+
+        warn!("Delta chain from version {} is longer than {} releases", installed_version, MAX_DELTA_CHAIN);
+        Ok(None)
+    }
+
+    /// Selects the manifest entries a file check compares against.
+    ///
+    /// With a known installed version only the entries changed since that release
+    /// are returned, if the server publishes a delta chain up to the latest release.
+    /// Otherwise, and always in repair mode, the full manifest is used. A release
+    /// older than the installed one is refused in every mode.
+    ///
+    /// # Returns
+    ///
+    /// The entries, the paths removed since the installed release, the release
+    /// they lead to and the mode used.
+    async fn load_update_files(&self, installed_version: Option<u64>, repair: bool) -> Result<UpdateSelection, String> {
+        if let Some(installed_version) = installed_version.filter(|_| !repair) {
+            let client = reqwest::Client::new();
+            let version_url = sibling_url(&self.settings.hash_file_url(), VERSION_FILE_NAME);
+
+            match self.fetch_signed_document(&client, &version_url).await {
+                Ok(Some(document)) => {
+                    let latest: VersionInfo = serde_json::from_slice(&document)
+                        .map_err(|e| format!("Invalid version file: {}", e))?;
+                    check_not_downgrade(latest.version, Some(installed_version))?;
+                    if latest.version == installed_version {
+                        info!("Version {} is already installed", installed_version);
+                        return Ok(UpdateSelection { files: Vec::new(), removed: Vec::new(), version: latest, mode: "current" });
+                    }
+
+                    match self.get_manifest_delta(installed_version, &latest).await {
+                        Ok(Some(delta)) => {
+                            info!("Updating from version {} to {} with {} changed file(s)",
+                                  installed_version, delta.version, delta.files.len());
+                            if !delta.removed.is_empty() {
+                                info!("{} file(s) were removed since version {}", delta.removed.len(), installed_version);
+                            }
+                            let version = VersionInfo {
+                                version: delta.version,
+                                parent_version: latest.parent_version,
+                                release_notes: delta.release_notes,
+                            };
+                            return Ok(UpdateSelection { files: delta.files, removed: delta.removed, version, mode: "delta" });
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!("Failed to follow the delta chain ({}), using the full hash file", e),
+                    }
+                }
+                Ok(None) => info!("No version file published, using the full hash file"),
+                Err(e) => warn!("Failed to fetch the version file ({}), using the full hash file", e),
+            }
+        }
+
+        let mut manifest = self.get_server_hash_file().await?;
+        check_not_downgrade(manifest.version, installed_version)?;
+        let hash_algorithm = manifest.hash_algorithm;
+        manifest.files.iter_mut().for_each(|f| f.hash_algorithm = hash_algorithm);
+        let version = VersionInfo::from(&manifest);
+        let mode = if repair { "repair" } else { "full" };
+        Ok(UpdateSelection { files: manifest.files, removed: Vec::new(), version, mode })
+    }
+
+    /// Finishes or rolls back a staged update whose commit was interrupted, e.g.
+    /// by a crash or a power loss. Runs on start and before every staged update.
+    pub fn recover_interrupted_update(&self, game_path: &Path) -> Result<(), String> {
+        match StagingArea::new(game_path).recover()? {
+            Some(Recovery::Resumed(version)) => {
+                info!("Completed an interrupted update of {:?}", game_path);
+                if let Some(version) = version {
+                    self.settings.save_installed_version(version)?;
+                }
+            }
+            Some(Recovery::RolledBack) => warn!("Rolled back an interrupted update of {:?}", game_path),
+            Some(Recovery::Cleaned) | None => {}
+        }
+        Ok(())
+    }
+
+    /// Swaps the staged files into the game folder, see `StagingArea::commit`.
+    async fn commit_staged_files(&self, staging: StagingArea, files: &[FileInfo]) -> Result<(), String> {
+        let paths: Vec<String> = files.iter().map(|f| f.path.clone()).collect();
+        let version = *self.pending_version.lock().await;
+        info!("Committing {} staged file(s)", paths.len());
+        tokio::task::spawn_blocking(move || staging.commit(&paths, version))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    /// Records the release found by the last check as installed, and
+    /// quarantines the files it removed.
+    async fn commit_pending_version(&self) {
+        let removed = std::mem::take(&mut *self.pending_removed.lock().await);
+        if let Some(version) = self.pending_version.lock().await.take() {
+            self.quarantine_removed_files(&removed);
+            match self.settings.save_installed_version(version) {
+                Ok(()) => info!("Installed version is now {}", version),
+                Err(e) => error!("Failed to record installed version {}: {}", version, e),
+            }
+        }
+    }
+
+    /// Moves files removed by a release to the orphan quarantine, so they can
+    /// still be restored by hand. Ignored and already missing files are left alone.
+    fn quarantine_removed_files(&self, removed: &[String]) {
+        if removed.is_empty() {
+            return;
+        }
+        let (game_path, rules) = match self.settings.game_path().map_err(String::from).and_then(|game_path| {
+            let rules = self.settings.ignore_rules(&game_path)?;
+            Ok((game_path, rules))
+        }) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!("Failed to remove deleted files: {}", e);
+                return;
+            }
+        };
+
+        let orphans: Vec<OrphanedFile> = removed
+            .iter()
+            .filter(|path| !rules.is_ignored_manifest_path(path))
+            .filter_map(|path| {
+                let local_path = match resolve_game_file(&game_path, path) {
+                    Ok(local_path) => local_path,
+                    Err(e) => {
+                        warn!("{}", e);
+                        return None;
+                    }
+                };
+                let metadata = fs::metadata(local_path).ok().filter(|m| m.is_file())?;
+                Some(OrphanedFile { path: path.clone(), size: metadata.len() })
+            })
+            .collect();
+        if orphans.is_empty() {
+            return;
+        }
+
+        match clean_orphans(&game_path, &orphans, OrphanAction::Quarantine) {
+            Ok((cleaned, Some(quarantine_path))) => {
+                info!("Moved {} file(s) removed by the release to {:?}", cleaned.len(), quarantine_path)
+            }
+            Ok((_, None)) => {}
+            Err(e) => error!("Failed to remove deleted files: {}", e),
+        }
+    }
+
+    /// Returns the hash cache, loading it from disk if no file check ran yet.
+    async fn load_hash_cache(&self) -> HashCache {
+        let hash_cache_path = self.hash_cache_path.as_deref();
+        self.hash_cache
+            .lock()
+            .await
+            .get_or_insert_with(|| hash_cache_path.map_or_else(HashCache::load, HashCache::load_from))
+            .clone()
+    }
+
+    /// Replaces the hash cache and writes it to disk.
+    async fn store_hash_cache(&self, cache: HashCache) {
+        let saved = match &self.hash_cache_path {
+            Some(hash_cache_path) => cache.save_to(hash_cache_path),
+            None => cache.save(),
+        };
+        if let Err(e) = saved {
+            warn!("Failed to save cache to disk: {}", e);
+        }
+        *self.hash_cache.lock().await = Some(cache);
+    }
+
+    /// Records freshly downloaded files in the hash cache, so the next check does
+    /// not hash them again and their chunks can be reused.
+    async fn record_updated_files(&self, game_path: &Path, files: &[FileInfo]) {
+        if files.is_empty() {
+            return;
+        }
+
+        let mut cache = self.load_hash_cache().await;
+        let entries = cache.install_mut(game_path);
+        for file_info in files {
+            let metadata = match fs::metadata(game_path.join(&file_info.path)) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let entry = CachedFileInfo::new(file_info.hash.clone(), file_info.hash_algorithm, &metadata, file_info.chunks.clone());
+            if let Some(entry) = entry {
+                entries.insert(file_info.path.clone(), entry);
+            }
+        }
+
+        self.store_hash_cache(cache).await;
+    }
+
+    /// Compares the game folder against the server manifest.
+    ///
+    /// Only the files changed since the installed release are checked, unless
+    /// `repair` is set or no delta chain leads to the latest release.
+    ///
+    /// # Returns
+    ///
+    /// The files to download.
+    pub async fn files_to_update(
+        &self,
+        repair: bool,
+        reporter: &Arc<dyn ProgressReporter>,
+    ) -> Result<Vec<FileInfo>, LauncherError> {
+        debug!("Starting get_files_to_update");
+
+        let start_time = Instant::now();
+
+        // Get the path to the game folder, which is the folder that contains the Tera game
+        // files. This is the folder that we will be comparing with the server hash file
+        // to determine which files need to be updated.
+        let local_game_path = self.settings.game_path()?;
+        debug!("Local game path: {:?}", local_game_path);
+
+        debug!("Attempting to read server hash file");
+        let installed_version = self.settings.installed_version();
+        let UpdateSelection { files, removed, version: latest, mode } =
+            self.load_update_files(installed_version, repair).await.map_err(LauncherError::Patch)?;
+        info!("Server hash file parsed, {} files found ({} mode)", files.len(), mode);
+
+        *self.pending_version.lock().await = Some(latest.version).filter(|&version| version > 0);
+        *self.pending_removed.lock().await = removed;
+        reporter.update_info(&UpdateInfo {
+            installed_version,
+            version: latest.version,
+            release_notes: latest.release_notes,
+            mode,
+        });
+
+        // Every path is checked before any of them is joined onto the game path
+        if let Err(e) = validate_manifest_paths(&files) {
+            error!("{}", e);
+            return Err(LauncherError::Patch(e));
+        }
+
+        // Files excluded locally, e.g. user settings, are left alone
+        let rules = self.settings.ignore_rules(&local_game_path).map_err(LauncherError::Config)?;
+        let files: Vec<FileInfo> = files.into_iter().filter(|f| !rules.is_ignored_manifest_path(&f.path)).collect();
+
+        debug!("Starting file comparison");
+        let mut hash_cache = self.load_hash_cache().await;
+        let cache = Arc::new(RwLock::new(std::mem::take(hash_cache.install_mut(&local_game_path))));
+
+        let processed_count = Arc::new(AtomicUsize::new(0));
+        let files_to_update_count = Arc::new(AtomicUsize::new(0));
+        let total_size = Arc::new(AtomicU64::new(0));
+
+        // Reads are spread over as many threads as the game drive handles well
+        let pool = io_thread_pool(self.settings.storage_kind(&local_game_path)).map_err(LauncherError::Patch)?;
+        let files_to_update: Vec<FileInfo> = pool.install(|| files.par_iter()
+            .filter_map(|file_info| {
+                let path = file_info.path.as_str();
+                let server_hash = file_info.hash.as_str();
+                let size = file_info.size;
+
+                let local_file_path = local_game_path.join(path);
+
+                let current_count = processed_count.fetch_add(1, Ordering::SeqCst) + 1;
+                if current_count.is_multiple_of(100) || current_count == files.len() {
+                    reporter.file_check_progress(&FileCheckProgress {
+                        current_file: path.to_string(),
+                        progress: (current_count as f64 / files.len() as f64) * 100.0,
+                        current_count,
+                        total_files: files.len(),
+                        elapsed_time: start_time.elapsed().as_secs_f64(),
+                        files_to_update: files_to_update_count.load(Ordering::SeqCst),
+                    });
+                }
+
+                if !local_file_path.exists() {
+                    files_to_update_count.fetch_add(1, Ordering::SeqCst);
+                    total_size.fetch_add(size, Ordering::SeqCst);
+                    return Some(file_info.clone());
+                }
+
+                let metadata = match fs::metadata(&local_file_path) {
+                    Ok(m) => m,
+                    Err(_) => {
+                        files_to_update_count.fetch_add(1, Ordering::SeqCst);
+                        total_size.fetch_add(size, Ordering::SeqCst);
+                        return Some(file_info.clone());
+                    }
+                };
+
+                // Files unchanged since they were last hashed are decided from the cache
+                let cache_read = cache.read().unwrap();
+                let cached_hash = cache_read
+                    .get(path)
+                    .filter(|cached_info| cached_info.hash_algorithm == file_info.hash_algorithm && cached_info.matches(&metadata))
+                    .map(|cached_info| (cached_info.hash == server_hash, cached_info.chunks.is_empty()));
+                drop(cache_read);
+
+                match cached_hash {
+                    Some((true, missing_chunks)) => {
+                        // Remember the chunk list so the file can serve chunks to other updates
+                        if missing_chunks && !file_info.chunks.is_empty() {
+                            if let Some(cached_info) = cache.write().unwrap().get_mut(path) {
+                                cached_info.chunks = file_info.chunks.clone();
+                            }
+                        }
+                        return None;
+                    }
+                    Some((false, _)) => {
+                        files_to_update_count.fetch_add(1, Ordering::SeqCst);
+                        total_size.fetch_add(size, Ordering::SeqCst);
+                        return Some(file_info.clone());
+                    }
+                    None => {}
+                }
+
+                if metadata.len() != size {
+                    files_to_update_count.fetch_add(1, Ordering::SeqCst);
+                    total_size.fetch_add(size, Ordering::SeqCst);
+                    return Some(file_info.clone());
+                }
+
+                let local_hash = match hash_file(&local_file_path, file_info.hash_algorithm) {
+                    Ok(hash) => hash,
+                    Err(_) => {
+                        files_to_update_count.fetch_add(1, Ordering::SeqCst);
+                        total_size.fetch_add(size, Ordering::SeqCst);
+                        return Some(file_info.clone());
+                    }
+                };
+
+                let chunks = if local_hash == server_hash { file_info.chunks.clone() } else { Vec::new() };
+                if let Some(entry) = CachedFileInfo::new(local_hash.clone(), file_info.hash_algorithm, &metadata, chunks) {
+                    cache.write().unwrap().insert(path.to_string(), entry);
+                }
+
+                if local_hash != server_hash {
+                    files_to_update_count.fetch_add(1, Ordering::SeqCst);
+                    total_size.fetch_add(size, Ordering::SeqCst);
+                    Some(file_info.clone())
+                } else {
+                    None
+                }
+            })
+            .collect());
+
+        // Save the updated cache to disk
+        *hash_cache.install_mut(&local_game_path) = std::mem::take(&mut *cache.write().unwrap());
+        self.store_hash_cache(hash_cache).await;
+
+        let total_time = start_time.elapsed();
+        info!("File comparison completed. Files to update: {}", files_to_update.len());
+
+        reporter.file_check_completed(&FileCheckSummary {
+            total_files: files.len(),
+            files_to_update: files_to_update.len(),
+            total_size: total_size.load(Ordering::SeqCst),
+            total_time_seconds: total_time.as_secs(),
+            average_time_per_file_ms: (total_time.as_millis() as f64) / (files.len() as f64),
+        });
+
+        if files_to_update.is_empty() {
+            self.commit_pending_version().await;
+        }
+
+        Ok(files_to_update)
+    }
+
+    /// Forgets all cached hashes of the install and verifies every file against
+    /// the full manifest.
+    pub async fn force_full_verify(&self, reporter: &Arc<dyn ProgressReporter>) -> Result<Vec<FileInfo>, LauncherError> {
+        let game_path = self.settings.game_path()?;
+        info!("Invalidating hash cache for {:?}", game_path);
+
+        let mut cache = self.load_hash_cache().await;
+        cache.invalidate(&game_path);
+        self.store_hash_cache(cache).await;
+
+        self.files_to_update(true, reporter).await
+    }
+
+    /// Lists the local files that are not part of the full manifest.
+    async fn find_manifest_orphans(&self, game_path: &Path) -> Result<Vec<OrphanedFile>, String> {
+        let manifest = self.get_server_hash_file().await?;
+        validate_manifest_paths(&manifest.files)?;
+
+        let rules = self.settings.ignore_rules(game_path)?;
+        let game_path = game_path.to_path_buf();
+        tokio::task::spawn_blocking(move || find_orphans(&game_path, &manifest.files, &rules))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Verifies every game file without the hash cache and reports orphaned files.
+    ///
+    /// Nothing is removed here; the orphans are handled by `clean_orphaned_files`
+    /// once the user has seen the report.
+    pub async fn repair(&self, reporter: &Arc<dyn ProgressReporter>) -> Result<RepairReport, LauncherError> {
+        let game_path = self.settings.game_path()?;
+        info!("Repairing game files in {:?}", game_path);
+
+        let files_to_update = self.force_full_verify(reporter).await?;
+        let orphans = self.find_manifest_orphans(&game_path).await.map_err(LauncherError::Patch)?;
+        let orphaned_size = orphans.iter().map(|o| o.size).sum();
+
+        Ok(RepairReport { files_to_update, orphans, orphaned_size })
+    }
+
+    /// Quarantines or deletes orphaned files from a repair report.
+    ///
+    /// The list is checked against the manifest again, so a stale report can never
+    /// remove files that belong to the game.
+    pub async fn clean_orphaned_files(&self, paths: Vec<String>, action: OrphanAction) -> Result<OrphanCleanupReport, LauncherError> {
+        let game_path = self.settings.game_path()?;
+        let requested: HashSet<String> = paths.into_iter().collect();
+
+        let orphans: Vec<OrphanedFile> = self.find_manifest_orphans(&game_path)
+            .await
+            .map_err(LauncherError::Patch)?
+            .into_iter()
+            .filter(|o| requested.contains(&o.path))
+            .collect();
+        if orphans.len() < requested.len() {
+            warn!("{} requested path(s) are no longer orphaned, skipping them", requested.len() - orphans.len());
+        }
+
+        let (cleaned, quarantine_path) = clean_orphans(&game_path, &orphans, action).map_err(LauncherError::Io)?;
+        Ok(OrphanCleanupReport {
+            cleaned,
+            quarantine_path: quarantine_path.map(|p| p.to_string_lossy().into_owned()),
+        })
+    }
+
+    /// Checks free space, writability and the install location for updating
+    /// `files_to_update`, before anything is downloaded.
+    async fn run_preflight(&self, game_path: &Path, files_to_update: &[FileInfo], settings: &DownloadSettings) -> Result<PreflightReport, String> {
+        let (game_path, files_to_update) = (game_path.to_path_buf(), files_to_update.to_vec());
+        let (connections, staged) = (settings.connections, settings.staged);
+        let report = tokio::task::spawn_blocking(move || run_preflight(&game_path, &files_to_update, connections, staged))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        info!(
+            "Update preflight: {} bytes required, {:?} bytes available",
+            report.space.required, report.available_space
+        );
+        for issue in &report.issues {
+            warn!("Update preflight: {}", issue.message());
+        }
+        Ok(report)
+    }
+
+    /// Runs the disk checks of an update of `files_to_update` on their own.
+    pub async fn preflight(&self, files_to_update: &[FileInfo]) -> Result<PreflightReport, LauncherError> {
+        let game_path = self.settings.game_path()?;
+        self.run_preflight(&game_path, files_to_update, &self.settings.download_settings())
+            .await
+            .map_err(LauncherError::Patch)
+    }
+
+    /// Downloads a single file straight into the game folder, continuing the
+    /// progress counters of the caller.
+    pub async fn update_file(
+        &self,
+        file_info: &FileInfo,
+        total_files: usize,
+        current_file_index: usize,
+        total_size: u64,
+        downloaded_size: u64,
+        reporter: &Arc<dyn ProgressReporter>,
+    ) -> Result<u64, LauncherError> {
+        let game_path = self.settings.game_path()?;
+
+        let client = reqwest::Client::builder()
+            .no_proxy()
+            .build()?;
+
+        let tracker = Arc::new(DownloadTracker::new(
+            total_files,
+            total_size,
+            downloaded_size,
+            current_file_index.saturating_sub(1),
+        ));
+        let progress = tokio::spawn(report_progress(Arc::clone(reporter), Arc::clone(&tracker)));
+
+        let result = download_file(&client, &game_path, file_info, file_info.transfer_url(), &tracker, &self.control).await;
+        progress.abort();
+
+        // Report a final progress for this file
+        reporter.download_progress(&tracker.payload(0.0));
+
+        if result.is_ok() {
+            self.record_updated_files(&game_path, std::slice::from_ref(file_info)).await;
+        }
+
+        result.map_err(LauncherError::patch)
+    }
+
+    /// Downloads `files_to_update`, usually the result of `files_to_update`.
+    ///
+    /// The disk checks run first. Files are downloaded concurrently and, unless
+    /// disabled in the settings, staged and swapped in once all of them are
+    /// verified. The checked release is then recorded as installed. The first
+    /// file that fails after all retries stops the batch; the other partial
+    /// downloads are kept for the next run.
+    ///
+    /// # Returns
+    ///
+    /// The size of every file, in the order of `files_to_update`.
+    pub async fn download_files(
+        &self,
+        files_to_update: Vec<FileInfo>,
+        reporter: &Arc<dyn ProgressReporter>,
+    ) -> Result<Vec<u64>, LauncherError> {
+        let total_files = files_to_update.len();
+        // Progress is measured in bytes on the wire, i.e. compressed sizes where available
+        let total_size: u64 = files_to_update.iter().map(|f| f.transfer_size()).sum();
+
+        if total_files == 0 {
+            info!("No files to download");
+            self.commit_pending_version().await;
+            reporter.download_complete();
+            return Ok(vec![]);
+        }
+
+        let game_path = self.settings.game_path()?;
+        let settings = self.settings.download_settings();
+
+        let preflight = self.run_preflight(&game_path, &files_to_update, &settings).await.map_err(LauncherError::Patch)?;
+        if !preflight.is_ok() {
+            reporter.update_preflight_failed(&preflight);
+            return Err(LauncherError::Preflight(preflight.summary()));
+        }
+
+        info!("Downloading {} file(s) over {} connection(s)", total_files, settings.connections);
+        self.control.reset(&settings);
+
+        // One client for the whole run, so connections are pooled between files
+        let client = reqwest::Client::builder()
+            .no_proxy()
+            .pool_max_idle_per_host(settings.connections)
+            .build()?;
+
+        let mirrors = MirrorList::new(&self.settings.files_server_url(), self.settings.file_mirror_urls());
+
+        // Staged updates leave the game folder alone until every file is verified
+        let staging = if settings.staged {
+            self.recover_interrupted_update(&game_path).map_err(LauncherError::Patch)?;
+            Some(StagingArea::new(&game_path))
+        } else {
+            None
+        };
+        let output_path = match &staging {
+            Some(staging) => staging.prepare().map_err(LauncherError::Io)?,
+            None => game_path.clone(),
+        };
+
+        let chunk_index = if files_to_update.iter().any(|f| !f.chunks.is_empty()) {
+            let cache = self.load_hash_cache().await;
+            let (index_game_path, index_files) = (game_path.clone(), files_to_update.clone());
+            tokio::task::spawn_blocking(move || ChunkIndex::build(&index_game_path, cache.install(&index_game_path), &index_files))
+                .await?
+        } else {
+            ChunkIndex::default()
+        };
+
+        let session = DownloadSession {
+            client,
+            game_path,
+            output_path,
+            settings,
+            mirrors,
+            chunks: Arc::new(chunk_index),
+        };
+
+        let tracker = Arc::new(DownloadTracker::new(total_files, total_size, 0, 0));
+        let progress = tokio::spawn(report_progress(Arc::clone(reporter), Arc::clone(&tracker)));
+
+        // Largest files first, so a big archive never ends up alone on the last connection
+        let mut queue: Vec<(usize, FileInfo)> = files_to_update.into_iter().enumerate().collect();
+        queue.sort_by_key(|(_, file_info)| std::cmp::Reverse(file_info.transfer_size()));
+
+        let mut results = futures_util::stream::iter(queue)
+            .map(|(index, file_info)| {
+                let session = &session;
+                let tracker = &tracker;
+                let control = &self.control;
+                async move {
+                    let result = download_file_with_retry(session, &file_info, tracker, control).await;
+                    (index, file_info, result)
+                }
+            })
+            .buffer_unordered(session.settings.connections);
+
+        // Sizes are returned in the order of `files_to_update`
+        let mut downloaded_sizes = vec![0; total_files];
+        let mut updated_files = Vec::new();
+        let mut failures: Vec<DownloadFailure> = Vec::new();
+        while let Some((index, file_info, result)) = results.next().await {
+            match result {
+                Ok(size) => {
+                    downloaded_sizes[index] = size;
+                    updated_files.push(file_info);
+                }
+                Err(failure) => {
+                    failures.push(failure);
+                    break;
+                }
+            }
+        }
+        drop(results);
+
+        // Staged files only count once they are committed
+        if staging.is_none() {
+            self.record_updated_files(&session.game_path, &updated_files).await;
+        }
+
+        progress.abort();
+        reporter.download_progress(&tracker.payload(0.0));
+
+        if self.control.is_cancelled() {
+            info!("Update cancelled, partial downloads are kept for the next run");
+            reporter.download_cancelled();
+            return Err(LauncherError::Cancelled);
+        }
+
+        if !failures.is_empty() {
+            error!("{} of {} file(s) failed to download, stopping the update", failures.len(), total_files);
+            let failed_paths: Vec<String> = failures.iter().map(|f| f.path.clone()).collect();
+            reporter.download_report(&DownloadReport { total_files, failed_files: failures });
+
+            return Err(LauncherError::Network(format!("{} file(s) failed to download: {}", failed_paths.len(), failed_paths.join(", "))));
+        }
+
+        if let Some(staging) = staging {
+            self.commit_staged_files(staging, &updated_files).await.map_err(LauncherError::Patch)?;
+            self.record_updated_files(&session.game_path, &updated_files).await;
+        }
+
+        self.commit_pending_version().await;
+        info!("Download complete for {} file(s)", total_files);
+        reporter.download_complete();
+
+        Ok(downloaded_sizes)
+    }
+
+    /// Fetches the description of the base archive, `None` when the server does
+    /// not publish one.
+    async fn get_base_archive(&self) -> Result<Option<ArchiveInfo>, String> {
+        let client = reqwest::Client::new();
+        let url = sibling_url(&self.settings.hash_file_url(), ARCHIVE_FILE_NAME);
+        match self.fetch_signed_document(&client, &url).await? {
+            Some(document) => serde_json::from_slice(&document)
+                .map(Some)
+                .map_err(|e| format!("Invalid base archive file: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Downloads the parts of a base archive into the staging folder, keeping
+    /// parts a previous attempt completed.
+    ///
+    /// # Returns
+    ///
+    /// The paths of the parts, in order.
+    async fn download_base_archive(
+        &self,
+        game_path: &Path,
+        archive: &ArchiveInfo,
+        reporter: &Arc<dyn ProgressReporter>,
+    ) -> Result<Vec<PathBuf>, String> {
+        let download_path = game_path.join(STAGING_DIR);
+        fs::create_dir_all(&download_path).map_err(|e| format!("Failed to create {:?}: {}", download_path, e))?;
+
+        let parts = archive_part_files(archive);
+        let part_paths: Vec<PathBuf> = parts.iter().map(|part| download_path.join(&part.path)).collect();
+
+        let mut missing = Vec::new();
+        for (part, path) in parts.iter().zip(&part_paths) {
+            let (hash_path, algorithm) = (path.clone(), part.hash_algorithm);
+            let complete = path.exists()
+                && tokio::task::spawn_blocking(move || hash_file(&hash_path, algorithm))
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .is_ok_and(|hash| hash == part.hash);
+            if !complete {
+                missing.push(part.clone());
+            }
+        }
+
+        let settings = self.settings.download_settings();
+        info!("Downloading {} of {} base archive part(s)", missing.len(), parts.len());
+        self.control.reset(&settings);
+
+        let client = reqwest::Client::builder()
+            .no_proxy()
+            .pool_max_idle_per_host(settings.connections)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let session = DownloadSession {
+            client,
+            game_path: download_path.clone(),
+            output_path: download_path,
+            settings,
+            mirrors: MirrorList::new(&self.settings.files_server_url(), self.settings.file_mirror_urls()),
+            chunks: Arc::new(ChunkIndex::default()),
+        };
+
+        let total_size = missing.iter().map(|part| part.size).sum();
+        let tracker = Arc::new(DownloadTracker::new(missing.len(), total_size, 0, 0));
+        let progress = tokio::spawn(report_progress(Arc::clone(reporter), Arc::clone(&tracker)));
+
+        let mut results = futures_util::stream::iter(missing)
+            .map(|part| {
+                let (session, tracker, control) = (&session, &tracker, &self.control);
+                async move { download_file_with_retry(session, &part, tracker, control).await }
+            })
+            .buffer_unordered(session.settings.connections);
+        let mut failures: Vec<DownloadFailure> = Vec::new();
+        while let Some(result) = results.next().await {
+            if let Err(failure) = result {
+                failures.push(failure);
+            }
+        }
+        drop(results);
+
+        progress.abort();
+        reporter.download_progress(&tracker.payload(0.0));
+
+        if self.control.is_cancelled() {
+            return Err(CANCELLED.to_string());
+        }
+        if let Some(failure) = failures.first() {
+            return Err(format!("Failed to download the base archive: {}: {}", failure.path, failure.error));
+        }
+        Ok(part_paths)
+    }
+
+    /// Unpacks a downloaded base archive, reporting through `file_check_progress`.
+    async fn extract_base_archive(
+        &self,
+        game_path: &Path,
+        archive: &ArchiveInfo,
+        parts: Vec<PathBuf>,
+        reporter: &Arc<dyn ProgressReporter>,
+    ) -> Result<usize, String> {
+        let rules = self.settings.ignore_rules(game_path)?;
+        let (reporter, game_path, total_files) = (Arc::clone(reporter), game_path.to_path_buf(), archive.files);
+        let start_time = Instant::now();
+
+        tokio::task::spawn_blocking(move || {
+            extract_archive(&parts, &game_path, &rules, |path, current_count| {
+                if current_count.is_multiple_of(100) || current_count == total_files {
+                    reporter.file_check_progress(&FileCheckProgress {
+                        current_file: path.to_string(),
+                        progress: (current_count as f64 / total_files.max(1) as f64) * 100.0,
+                        current_count,
+                        total_files,
+                        elapsed_time: start_time.elapsed().as_secs_f64(),
+                        files_to_update: 0,
+                    });
+                }
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    /// Installs the game into `path`, usually an empty folder.
+    ///
+    /// The settings are pointed at the folder first, so an interrupted install
+    /// continues as a regular update. After the disk checks, the base archive is
+    /// unpacked if the server publishes one and the folder holds no game files
+    /// yet. The file check and the download of everything still missing then run
+    /// like any update, with the same progress.
+    pub async fn install(
+        &self,
+        path: &str,
+        use_archive: bool,
+        reporter: &Arc<dyn ProgressReporter>,
+    ) -> Result<InstallReport, LauncherError> {
+        let game_path = PathBuf::from(path);
+        fs::create_dir_all(&game_path)?;
+        self.settings.write_install_config(&game_path)?;
+        info!("Installing the game to {:?}", game_path);
+
+        let manifest = self.get_server_hash_file().await.map_err(LauncherError::Patch)?;
+        validate_manifest_paths(&manifest.files).map_err(LauncherError::Patch)?;
+        let has_game_files = manifest.files.iter().any(|f| game_path.join(&f.path).exists());
+        let archive = match use_archive && !has_game_files {
+            true => self.get_base_archive().await.map_err(LauncherError::Patch)?,
+            false => None,
+        };
+
+        // The whole client and the archive parts have to fit before anything is downloaded
+        let mut required_files = manifest.files;
+        if let Some(archive) = &archive {
+            required_files.extend(archive_part_files(archive));
+        }
+        let preflight = self.run_preflight(&game_path, &required_files, &self.settings.download_settings())
+            .await
+            .map_err(LauncherError::Patch)?;
+        if !preflight.is_ok() {
+            reporter.update_preflight_failed(&preflight);
+            return Err(LauncherError::Preflight(preflight.summary()));
+        }
+
+        let mut extracted_files = 0;
+        if let Some(archive) = archive {
+            info!("Installing from the base archive of version {} ({} bytes)", archive.version, archive.download_size());
+            let parts = self.download_base_archive(&game_path, &archive, reporter)
+                .await
+                .map_err(LauncherError::patch)?;
+            extracted_files = self.extract_base_archive(&game_path, &archive, parts, reporter)
+                .await
+                .map_err(LauncherError::Patch)?;
+
+            let archive_path = game_path.join(STAGING_DIR).join(ARCHIVE_DIR);
+            if let Err(e) = fs::remove_dir_all(&archive_path) {
+                warn!("Failed to remove {:?}: {}", archive_path, e);
+            }
+        }
+
+        let files_to_update = self.files_to_update(false, reporter).await?;
+        let downloaded_files = files_to_update.len();
+        self.download_files(files_to_update, reporter).await?;
+
+        info!("Installation complete: {} file(s) extracted, {} downloaded", extracted_files, downloaded_files);
+        Ok(InstallReport { game_path: path.to_string(), extracted_files, downloaded_files })
+    }
+
+    /// Takes over a copy of the client the player already has.
+    ///
+    /// The client root is looked for in and around `path`, made the game path
+    /// and verified against the full manifest. The file check fills the hash
+    /// cache, so only the missing or changed files are downloaded afterwards
+    /// by `download_files`.
+    pub async fn adopt(&self, path: &str, reporter: &Arc<dyn ProgressReporter>) -> Result<AdoptReport, LauncherError> {
+        let manifest = self.get_server_hash_file().await.map_err(LauncherError::Patch)?;
+        validate_manifest_paths(&manifest.files).map_err(LauncherError::Patch)?;
+
+        let selected = PathBuf::from(path);
+        let files = manifest.files;
+        let (layout, files) = tokio::task::spawn_blocking(move || (detect_client_layout(&selected, &files), files)).await?;
+        let layout = layout.ok_or_else(|| LauncherError::GameNotFound(PathBuf::from(path)))?;
+        info!("Found the game client in {:?} ({}/{} probed files)", layout.root, layout.found, layout.probed);
+
+        // The release of the copy is unknown, so it is checked against the full manifest
+        self.settings.write_install_config(&layout.root)?;
+        let files_to_update = self.files_to_update(true, reporter).await?;
+
+        let rules = self.settings.ignore_rules(&layout.root).map_err(LauncherError::Config)?;
+        let total_files = files.iter().filter(|f| !rules.is_ignored_manifest_path(&f.path)).count();
+        let (missing, changed): (Vec<&FileInfo>, Vec<&FileInfo>) =
+            files_to_update.iter().partition(|f| !layout.root.join(&f.path).exists());
+
+        let report = AdoptReport {
+            game_path: layout.root.to_string_lossy().into_owned(),
+            total_files,
+            matching_files: total_files.saturating_sub(files_to_update.len()),
+            missing_files: missing.len(),
+            missing_size: missing.iter().map(|f| f.size).sum(),
+            changed_files: changed.len(),
+            changed_size: changed.iter().map(|f| f.size).sum(),
+            files_to_update,
+        };
+        info!("Adopted {:?}: {} matching, {} missing ({}), {} changed ({})",
+              report.game_path, report.matching_files, report.missing_files, format_bytes(report.missing_size),
+              report.changed_files, format_bytes(report.changed_size));
+        Ok(report)
+    }
+
+    /// Returns true if the file server answers for the hash file. The server
+    /// root itself is not probed, as file servers need not list it.
+    pub async fn check_server_connection(&self) -> Result<bool, LauncherError> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        let response = client.head(self.settings.hash_file_url()).send().await?;
+        Ok(response.status().is_success())
+    }
+
+    /// Generates the hash file of the game folder, with its version file, the
+    /// delta from the previous hash file and the requested artifacts.
+    pub async fn generate_hash_file(
+        &self,
+        options: HashFileOptions,
+        reporter: &Arc<dyn ProgressReporter>,
+    ) -> Result<String, LauncherError> {
+        let game_path = self.settings.game_path()?;
+        info!("Game path: {:?}", game_path);
+        let output_path = game_path.join(MANIFEST_FILE_NAME);
+        info!("Output path: {:?}", output_path);
+
+        // The hash file of the previous run is the parent release
+        let options = BuildOptions {
+            source: game_path.clone(),
+            output: game_path.clone(),
+            base_url: get_config_value("FILE_SERVER_URL"),
+            copy_files: false,
+            link_files: false,
+            previous: read_manifest(&output_path).ok(),
+            version: options.version,
+            release_notes: options.release_notes,
+            compression: options.compression,
+            // An in-place build has no published copies of the previous release to diff
+            patch_format: None,
+            chunked: options.chunked,
+            archive_part_size: None,
+            hash_algorithm: options.hash_algorithm,
+            rules: self.settings.ignore_rules(&game_path).map_err(LauncherError::Config)?,
+            storage: self.settings.storage_kind(&game_path),
+        };
+
+        let reporter = Arc::clone(reporter);
+        let result = tokio::task::spawn_blocking(move || {
+            build_manifest(&options, |progress| {
+                reporter.hash_file_progress(&HashFileProgress {
+                    current_file: progress.current_file.to_string(),
+                    progress: (progress.processed_files as f64 / progress.total_files as f64) * 100.0,
+                    processed_files: progress.processed_files,
+                    total_files: progress.total_files,
+                    total_size: progress.total_size,
+                });
+                Ok(())
+            })
+        })
+        .await?;
+
+        let summary = result.map_err(|e| {
+            error!("Error during file processing: {:?}", e);
+            LauncherError::Patch(e)
+        })?;
+
+        Ok(format!(
+            "Hash file generated successfully. Processed {} files with a total size of {} bytes in {:?}",
+            summary.processed_files, summary.total_size, summary.duration
+        ))
+    }
+
+    /// Signs the generated hash file, its version file and deltas, writing a
+    /// `.sig` file next to each of them.
+    pub fn sign_hash_file(&self, key_path: &Path) -> Result<String, LauncherError> {
+        let game_path = self.settings.game_path()?;
+        let signing_key = load_signing_key(key_path).map_err(LauncherError::Config)?;
+
+        let signed = sign_documents(&game_path, &signing_key).map_err(LauncherError::Patch)?;
+        Ok(format!("Signed {}, publish the .sig files next to them", signed.join(", ")))
+    }
+
+    /// Creates a manifest signing key and returns the public key for config.json.
+    pub fn create_signing_key(&self, key_path: &Path) -> Result<String, LauncherError> {
+        let public_key = generate_signing_key(key_path).map_err(LauncherError::Io)?;
+        info!("Signing key written to {:?}", key_path);
+        Ok(public_key)
+    }
+
+    /// Fetches the published launcher build if it is newer than `current_version`.
+    /// A build that already failed to start is not offered again.
+    pub async fn check_launcher_update(&self, current_version: &str) -> Result<Option<LauncherRelease>, LauncherError> {
+        let client = reqwest::Client::new();
+        let url = sibling_url(&self.settings.hash_file_url(), LAUNCHER_FILE_NAME);
+        let release: LauncherRelease = match self.fetch_signed_document(&client, &url).await.map_err(LauncherError::LauncherUpdate)? {
+            Some(document) => serde_json::from_slice(&document)
+                .map_err(|e| LauncherError::LauncherUpdate(format!("Invalid launcher file: {}", e)))?,
+            None => return Ok(None),
+        };
+
+        if compare_versions(&release.version, current_version).is_le() {
+            return Ok(None);
+        }
+        if self.settings.failed_launcher_version().as_deref() == Some(release.version.as_str()) {
+            info!("Skipping launcher {}, it failed to start before", release.version);
+            return Ok(None);
+        }
+        info!("Launcher {} is available (running {})", release.version, current_version);
+        Ok(Some(release))
+    }
+
+    /// Downloads the newest launcher build next to the running one. A build
+    /// already downloaded is kept.
+    pub async fn download_launcher_update(
+        &self,
+        current_version: &str,
+        reporter: &Arc<dyn ProgressReporter>,
+    ) -> Result<LauncherRelease, LauncherError> {
+        let update_error = |e: &str| LauncherError::LauncherUpdate(e.to_string());
+        let release = self.check_launcher_update(current_version)
+            .await?
+            .ok_or_else(|| update_error("No launcher update available"))?;
+        let swap = launcher_swap()?;
+
+        let (verify_swap, verify_release) = (swap.clone(), release.clone());
+        let staged = tokio::task::spawn_blocking(move || verify_swap.verify_staged(&verify_release))
+            .await?
+            .map_err(LauncherError::LauncherUpdate)?;
+        if !staged {
+            let staged_path = swap.staged_path();
+            let folder = staged_path.parent().ok_or_else(|| update_error("Launcher folder not found"))?;
+            let file_info = FileInfo {
+                path: staged_path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .ok_or_else(|| update_error("Invalid launcher path"))?
+                    .to_string(),
+                hash: release.hash.clone(),
+                size: release.size,
+                url: release.url.clone(),
+                patches: Vec::new(),
+                compressed: None,
+                chunks: Vec::new(),
+                hash_algorithm: release.hash_algorithm,
+            };
+
+            info!("Downloading launcher {} from {}", release.version, release.url);
+            self.control.reset(&self.settings.download_settings());
+            let tracker = Arc::new(DownloadTracker::new(1, release.size, 0, 0));
+            let progress = tokio::spawn(report_progress(Arc::clone(reporter), Arc::clone(&tracker)));
+            let result = download_file(&reqwest::Client::new(), folder, &file_info, &release.url, &tracker, &self.control).await;
+            progress.abort();
+            result.map_err(|e| match LauncherError::patch(e) {
+                LauncherError::Patch(e) => LauncherError::LauncherUpdate(format!("Failed to download launcher {}: {}", release.version, e)),
+                cancelled => cancelled,
+            })?;
+        }
+
+        *self.staged_launcher.lock().await = Some(release.clone());
+        Ok(release)
+    }
+
+    /// Moves the downloaded launcher into place and starts it.
+    ///
+    /// `on_started` is called once the new build runs, e.g. to hide the window.
+    /// This launcher then waits for the new one to confirm its start with
+    /// `confirm_launcher_start` and returns `Ok` so the caller can exit. If the
+    /// new build exits early or does not confirm in time, the previous build is
+    /// put back, its version is remembered so it is not offered again, and an
+    /// error is returned.
+    pub async fn restart_to_update_launcher<F: FnOnce()>(&self, current_version: &str, on_started: F) -> Result<(), LauncherError> {
+        let release = self.staged_launcher
+            .lock()
+            .await
+            .clone()
+            .ok_or_else(|| LauncherError::LauncherUpdate("No launcher update downloaded".to_string()))?;
+        let swap = launcher_swap()?;
+
+        let (verify_swap, verify_release) = (swap.clone(), release.clone());
+        let staged = tokio::task::spawn_blocking(move || verify_swap.verify_staged(&verify_release))
+            .await?
+            .map_err(LauncherError::LauncherUpdate)?;
+        if !staged {
+            *self.staged_launcher.lock().await = None;
+            return Err(LauncherError::LauncherUpdate("The downloaded launcher no longer matches its release".to_string()));
+        }
+
+        swap.swap(&release.version, current_version).map_err(LauncherError::LauncherUpdate)?;
+        let mut child = match std::process::Command::new(swap.exe_path()).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                swap.roll_back().map_err(LauncherError::LauncherUpdate)?;
+                return Err(LauncherError::LauncherUpdate(format!("Failed to start launcher {}: {}", release.version, e)));
+            }
+        };
+        info!("Started launcher {}, waiting for it to confirm", release.version);
+        on_started();
+
+        let wait_swap = swap.clone();
+        let confirmed = tokio::task::spawn_blocking(move || wait_swap.wait_for_confirmation(&mut child, CONFIRM_TIMEOUT))
+            .await?
+            .map_err(LauncherError::LauncherUpdate)?;
+        if confirmed {
+            return Ok(());
+        }
+
+        swap.roll_back().map_err(LauncherError::LauncherUpdate)?;
+        *self.staged_launcher.lock().await = None;
+        if let Err(e) = self.settings.save_failed_launcher_version(&release.version) {
+            error!("Failed to remember launcher {} as failed: {}", release.version, e);
+        }
+        Err(LauncherError::LauncherUpdate(format!(
+            "Launcher {} failed to start, version {} was restored",
+            release.version, current_version
+        )))
+    }
+
+    /// Confirms a launcher build on trial, called once the front-end is up.
+    ///
+    /// # Returns
+    ///
+    /// The version that was just installed, if this start completes a launcher update.
+    pub fn confirm_launcher_start(&self) -> Result<Option<String>, LauncherError> {
+        launcher_swap()?.confirm().map_err(LauncherError::LauncherUpdate)
+    }
+
+    /// Removes what is left of the last launcher update.
+    pub fn clean_up_launcher_update(&self) {
+        match launcher_swap().and_then(|swap| swap.cleanup().map_err(LauncherError::LauncherUpdate)) {
+            Ok(Some(journal)) => info!("Last launcher update to {} ended {:?}", journal.version, journal.state),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to clean up the last launcher update: {}", e),
+        }
+    }
+}
+
+/// Returns the swap of the running launcher binary.
+fn launcher_swap() -> Result<LauncherSwap, LauncherError> {
+    env::current_exe()
+        .map(|exe| LauncherSwap::new(&exe))
+        .map_err(|e| LauncherError::LauncherUpdate(format!("Failed to locate the launcher: {}", e)))
+}
+
+/// The parts of a base archive as downloadable entries, below `archive/`.
+fn archive_part_files(archive: &ArchiveInfo) -> Vec<FileInfo> {
+    archive
+        .parts
+        .iter()
+        .enumerate()
+        .map(|(index, part)| FileInfo {
+            path: format!("{}/{}", ARCHIVE_DIR, ArchiveInfo::part_file_name(index)),
+            hash: part.hash.clone(),
+            size: part.size,
+            url: part.url.clone(),
+            patches: Vec::new(),
+            compressed: None,
+            chunks: Vec::new(),
+            hash_algorithm: archive.hash_algorithm,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::builder::FILES_DIR;
+    use crate::patch::delta::{PatchFormat, PATCHES_DIR};
+    use crate::patch::hashing::StorageKind;
+    use crate::patch::ignore_rules::{IgnoreRules, QUARANTINE_DIR};
+    use crate::patch::server;
+    use crate::services::progress::ProgressPayload;
+    use std::net::SocketAddr;
+
+    /// Remembers the mode of every file check and the last download events.
+    #[derive(Default)]
+    struct Recorder {
+        modes: std::sync::Mutex<Vec<&'static str>>,
+        progress: std::sync::Mutex<Option<ProgressPayload>>,
+        report: std::sync::Mutex<Option<DownloadReport>>,
+    }
+
+    impl ProgressReporter for Recorder {
+        fn update_info(&self, info: &UpdateInfo) {
+            self.modes.lock().unwrap().push(info.mode);
+        }
+
+        fn download_progress(&self, progress: &ProgressPayload) {
+            *self.progress.lock().unwrap() = Some(progress.clone());
+        }
+
+        fn download_report(&self, report: &DownloadReport) {
+            *self.report.lock().unwrap() = Some(report.clone());
+        }
+    }
+
+    /// A game folder, a publish tree served over HTTP and a signing key.
+    struct Fixture {
+        folder: PathBuf,
+        game_path: PathBuf,
+        publish_path: PathBuf,
+        server_url: String,
+        public_key: String,
+        recorder: Arc<Recorder>,
+        reporter: Arc<dyn ProgressReporter>,
+    }
+
+    impl Fixture {
+        async fn new(name: &str) -> Self {
+            let folder = std::env::temp_dir().join(format!("teralaunch-patch-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&folder);
+            let game_path = folder.join("game");
+            let publish_path = folder.join("publish");
+            fs::create_dir_all(&game_path).unwrap();
+            fs::create_dir_all(&publish_path).unwrap();
+
+            let public_key = generate_signing_key(&folder.join("signing.key")).unwrap();
+            let (addr, server) = server::bind(publish_path.clone(), SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+            tokio::spawn(server);
+
+            let recorder = Arc::new(Recorder::default());
+            Fixture {
+                game_path,
+                publish_path,
+                server_url: format!("http://{}", addr),
+                public_key,
+                reporter: recorder.clone(),
+                recorder,
+                folder,
+            }
+        }
+
+        /// Builds and signs release `version` from `files`, with a delta from the
+        /// release published before it.
+        fn publish(&self, version: u64, files: &[(&str, &str)]) {
+            self.publish_with(version, files, None);
+        }
+
+        /// Like `publish`, also writing diffs from the previous release.
+        fn publish_with(&self, version: u64, files: &[(&str, &str)], patch_format: Option<PatchFormat>) {
+            let source = self.folder.join(format!("release-{}", version));
+            for (path, contents) in files {
+                fs::create_dir_all(source.join(path).parent().unwrap()).unwrap();
+                fs::write(source.join(path), contents).unwrap();
+            }
+
+            let options = BuildOptions {
+                rules: IgnoreRules::load(&source, None).unwrap(),
+                source,
+                output: self.publish_path.clone(),
+                base_url: self.server_url.clone(),
+                copy_files: true,
+                link_files: false,
+                previous: read_manifest(&self.publish_path.join(MANIFEST_FILE_NAME)).ok(),
+                version: Some(version),
+                release_notes: format!("Release {}", version),
+                compression: None,
+                patch_format,
+                chunked: false,
+                archive_part_size: None,
+                hash_algorithm: HashAlgorithm::default(),
+                storage: StorageKind::Ssd,
+            };
+            build_manifest(&options, |_| Ok(())).unwrap();
+            let signing_key = load_signing_key(&self.folder.join("signing.key")).unwrap();
+            sign_documents(&self.publish_path, &signing_key).unwrap();
+        }
+
+        /// Puts the published copy of `path` into the game folder, as a download would.
+        fn install_file(&self, path: &str) {
+            let local_path = self.game_path.join(path);
+            fs::create_dir_all(local_path.parent().unwrap()).unwrap();
+            fs::copy(self.publish_path.join(FILES_DIR).join(path), local_path).unwrap();
+        }
+
+        fn service(&self) -> PatchService {
+            self.service_with("")
+        }
+
+        /// Creates the service with extra lines for the `[download]` section.
+        fn service_with(&self, download_settings: &str) -> PatchService {
+            let config_path = self.folder.join("tera_config.ini");
+            fs::write(
+                &config_path,
+                format!(
+                    "[game]\npath={}\nlang=EUR\n[download]\nserver_url={}\nretries=0\n{}",
+                    self.game_path.to_string_lossy().replace('\\', "/"),
+                    self.server_url,
+                    download_settings
+                ),
+            )
+            .unwrap();
+
+            PatchService {
+                manifest_public_key: self.public_key.clone(),
+                hash_cache_path: Some(self.folder.join("file_cache.json")),
+                ..PatchService::new(SettingsService::with_config_path(config_path))
+            }
+        }
+
+        async fn check(&self, patch: &PatchService, repair: bool) -> Vec<String> {
+            let files = patch.files_to_update(repair, &self.reporter).await.unwrap();
+            let mut paths: Vec<String> = files.into_iter().map(|f| f.path).collect();
+            paths.sort();
+            paths
+        }
+
+        fn modes(&self) -> Vec<&'static str> {
+            self.recorder.modes.lock().unwrap().clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn full_check_then_nothing_to_do_once_installed() {
+        let fixture = Fixture::new("current").await;
+        fixture.publish(1, &[("a.txt", "alpha"), ("S1Game/b.txt", "bravo")]);
+        let patch = fixture.service();
+
+        assert_eq!(fixture.check(&patch, false).await, ["S1Game/b.txt", "a.txt"]);
+        assert_eq!(patch.settings().installed_version(), None);
+
+        fixture.install_file("a.txt");
+        fixture.install_file("S1Game/b.txt");
+        assert!(fixture.check(&patch, false).await.is_empty());
+        assert_eq!(patch.settings().installed_version(), Some(1));
+
+        assert!(fixture.check(&patch, false).await.is_empty());
+        assert_eq!(fixture.modes(), ["full", "full", "current"]);
+    }
+
+    #[tokio::test]
+    async fn delta_check_quarantines_removed_files() {
+        let fixture = Fixture::new("delta").await;
+        fixture.publish(1, &[("a.txt", "alpha"), ("b.txt", "bravo"), ("c.txt", "charlie")]);
+        let patch = fixture.service();
+        for path in ["a.txt", "b.txt", "c.txt"] {
+            fixture.install_file(path);
+        }
+        assert!(fixture.check(&patch, false).await.is_empty());
+
+        fixture.publish(2, &[("a.txt", "alpha, changed"), ("c.txt", "charlie")]);
+        assert_eq!(fixture.check(&patch, false).await, ["a.txt"]);
+        assert_eq!(patch.settings().installed_version(), Some(1));
+        assert!(fixture.game_path.join("b.txt").exists());
+
+        fixture.install_file("a.txt");
+        assert!(fixture.check(&patch, false).await.is_empty());
+        assert_eq!(patch.settings().installed_version(), Some(2));
+        assert!(!fixture.game_path.join("b.txt").exists());
+        assert!(fixture.game_path.join("c.txt").exists());
+
+        let quarantined: Vec<_> = walkdir::WalkDir::new(fixture.game_path.join(QUARANTINE_DIR))
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name() == "b.txt")
+            .collect();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(fixture.modes(), ["full", "delta", "delta"]);
+    }
+
+    #[tokio::test]
+    async fn inconsistent_deltas_fall_back_to_the_full_hash_file() {
+        let fixture = Fixture::new("delta-mismatch").await;
+        fixture.publish(1, &[("a.txt", "alpha"), ("b.txt", "bravo")]);
+        let patch = fixture.service();
+        fixture.install_file("a.txt");
+        fixture.install_file("b.txt");
+        assert!(fixture.check(&patch, false).await.is_empty());
+
+        fixture.publish(2, &[("a.txt", "alpha, changed"), ("b.txt", "bravo")]);
+        let delta_path = fixture.publish_path.join(delta_file_name(1));
+        let published: serde_json::Value = serde_json::from_slice(&fs::read(&delta_path).unwrap()).unwrap();
+        let signing_key = load_signing_key(&fixture.folder.join("signing.key")).unwrap();
+
+        // Validly signed, but starting at another release or leading past the latest one
+        for (field, value) in [("from_version", 0), ("version", 3)] {
+            let mut delta = published.clone();
+            delta[field] = value.into();
+            fs::write(&delta_path, delta.to_string()).unwrap();
+            sign_documents(&fixture.publish_path, &signing_key).unwrap();
+            assert_eq!(fixture.check(&patch, false).await, ["a.txt"], "{}", field);
+        }
+        assert_eq!(fixture.modes(), ["full", "full", "full"]);
+    }
+
+    #[tokio::test]
+    async fn repair_finds_damage_a_current_check_skips() {
+        let fixture = Fixture::new("repair").await;
+        fixture.publish(1, &[("a.txt", "alpha"), ("b.txt", "bravo")]);
+        let patch = fixture.service();
+        fixture.install_file("a.txt");
+        fixture.install_file("b.txt");
+        assert!(fixture.check(&patch, false).await.is_empty());
+
+        // Same size, so only hashing the file reveals it
+        fs::write(fixture.game_path.join("b.txt"), "BRAVO").unwrap();
+        assert!(fixture.check(&patch, false).await.is_empty());
+        assert_eq!(fixture.check(&patch, true).await, ["b.txt"]);
+
+        let files = patch.force_full_verify(&fixture.reporter).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(fixture.modes(), ["full", "current", "repair", "repair"]);
+    }
+
+    #[tokio::test]
+    async fn replayed_older_release_is_refused() {
+        let fixture = Fixture::new("replay").await;
+        fixture.publish(1, &[("a.txt", "alpha")]);
+        let signed_documents = [MANIFEST_FILE_NAME, VERSION_FILE_NAME].map(|name| {
+            let signature_name = format!("{}.{}", name, SIGNATURE_EXTENSION);
+            [name.to_string(), signature_name].map(|name| {
+                let contents = fs::read(fixture.publish_path.join(&name)).unwrap();
+                (name, contents)
+            })
+        });
+
+        fixture.publish(2, &[("a.txt", "alpha, fixed")]);
+        let patch = fixture.service();
+        fixture.install_file("a.txt");
+        assert!(fixture.check(&patch, false).await.is_empty());
+        assert_eq!(patch.settings().installed_version(), Some(2));
+
+        // Release 1 is still validly signed
+        for (name, contents) in signed_documents.iter().flatten() {
+            fs::write(fixture.publish_path.join(name), contents).unwrap();
+        }
+        for repair in [false, true] {
+            match patch.files_to_update(repair, &fixture.reporter).await {
+                Err(LauncherError::Patch(e)) => assert!(e.contains("refusing to downgrade"), "{}", e),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(patch.settings().installed_version(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn concurrent_downloads_complete_the_release() {
+        let fixture = Fixture::new("download").await;
+        let files = [("a.txt", "alpha"), ("S1Game/b.txt", "bravo, the largest file"), ("S1Game/c.txt", "c"), ("d.txt", "delta")];
+        fixture.publish(1, &files);
+        let patch = fixture.service_with("connections=3\n");
+
+        let files_to_update = patch.files_to_update(false, &fixture.reporter).await.unwrap();
+        let expected_sizes: Vec<u64> = files_to_update.iter().map(|f| f.size).collect();
+        assert_eq!(patch.download_files(files_to_update, &fixture.reporter).await.unwrap(), expected_sizes);
+
+        let total_size: u64 = files.iter().map(|(_, contents)| contents.len() as u64).sum();
+        let progress = fixture.recorder.progress.lock().unwrap().clone().unwrap();
+        assert_eq!((progress.downloaded_bytes, progress.total_bytes), (total_size, total_size));
+        assert_eq!((progress.current_file_index, progress.total_files), (4, 4));
+
+        for (path, contents) in files {
+            assert_eq!(fs::read_to_string(fixture.game_path.join(path)).unwrap(), contents);
+        }
+        assert_eq!(patch.settings().installed_version(), Some(1));
+        assert!(fixture.check(&patch, false).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_file_stops_the_batch() {
+        let fixture = Fixture::new("download-failure").await;
+        fixture.publish(1, &[("a.txt", "alpha"), ("b.txt", "bravo, the largest file"), ("c.txt", "charlie")]);
+        // The largest file is scheduled first and is missing on the server
+        fs::remove_file(fixture.publish_path.join(FILES_DIR).join("b.txt")).unwrap();
+        let patch = fixture.service_with("connections=1\n");
+
+        let files_to_update = patch.files_to_update(false, &fixture.reporter).await.unwrap();
+        match patch.download_files(files_to_update, &fixture.reporter).await {
+            Err(LauncherError::Network(e)) => assert!(e.contains("b.txt"), "{}", e),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let report = fixture.recorder.report.lock().unwrap().clone().unwrap();
+        let failed: Vec<&str> = report.failed_files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!((report.total_files, failed), (3, vec!["b.txt"]));
+        let progress = fixture.recorder.progress.lock().unwrap().clone().unwrap();
+        assert_eq!((progress.current_file_index, progress.downloaded_bytes), (1, 0));
+
+        // Nothing was swapped in and the release is not recorded
+        for path in ["a.txt", "b.txt", "c.txt"] {
+            assert!(!fixture.game_path.join(path).exists(), "{}", path);
+        }
+        assert_eq!(patch.settings().installed_version(), None);
+    }
+
+    #[tokio::test]
+    async fn changed_files_are_patched_from_the_installed_copy() {
+        let fixture = Fixture::new("patch").await;
+        let old: String = (0..5000).map(|i| format!("line {}\n", i)).collect();
+        let new = old.replace("line 2500\n", "line 2500, changed\n");
+        fixture.publish(1, &[("S1Game/data.txt", &old)]);
+        fixture.install_file("S1Game/data.txt");
+        fixture.publish_with(2, &[("S1Game/data.txt", &new)], Some(PatchFormat::Bsdiff));
+        // Only the diff is left to download the file from
+        fs::remove_file(fixture.publish_path.join(FILES_DIR).join("S1Game/data.txt")).unwrap();
+        let diff_path = fixture.publish_path.join(PATCHES_DIR).join("S1Game/data.txt.from-1.bsdiff");
+        let diff = fs::read(&diff_path).unwrap();
+        let patch = fixture.service_with("connections=1\n");
+
+        // A tampered diff is refused before it is applied
+        fs::write(&diff_path, vec![0; diff.len()]).unwrap();
+        let files_to_update = patch.files_to_update(false, &fixture.reporter).await.unwrap();
+        assert!(patch.download_files(files_to_update, &fixture.reporter).await.is_err());
+        assert_eq!(fs::read_to_string(fixture.game_path.join("S1Game/data.txt")).unwrap(), old);
+
+        fs::write(&diff_path, &diff).unwrap();
+        let files_to_update = patch.files_to_update(false, &fixture.reporter).await.unwrap();
+        assert_eq!(patch.download_files(files_to_update, &fixture.reporter).await.unwrap(), [new.len() as u64]);
+        assert_eq!(fs::read_to_string(fixture.game_path.join("S1Game/data.txt")).unwrap(), new);
+        assert_eq!(patch.settings().installed_version(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn server_connection_is_checked_against_the_hash_file() {
+        let fixture = Fixture::new("connection").await;
+        let patch = fixture.service();
+        assert!(!patch.check_server_connection().await.unwrap());
+
+        fixture.publish(1, &[("a.txt", "alpha")]);
+        assert!(patch.check_server_connection().await.unwrap());
+    }
+}
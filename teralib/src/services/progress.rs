@@ -0,0 +1,123 @@
+// Third-party imports
+use serde::Serialize;
+
+use crate::patch::download::DownloadFailure;
+use crate::patch::preflight::PreflightReport;
+
+/// Progress of a download run, see `DownloadTracker::payload`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressPayload {
+    pub file_name: String,
+    pub progress: f64,
+    pub speed: f64,
+    pub downloaded_bytes: u64,
+    pub total_bytes: u64,
+    pub total_files: usize,
+    pub elapsed_time: f64,
+    pub current_file_index: usize,
+}
+
+/// Progress of a file check or of the extraction of a base archive.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileCheckProgress {
+    pub current_file: String,
+    pub progress: f64,
+    pub current_count: usize,
+    pub total_files: usize,
+    pub elapsed_time: f64,
+    pub files_to_update: usize,
+}
+
+/// Statistics of a finished file check.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileCheckSummary {
+    pub total_files: usize,
+    pub files_to_update: usize,
+    pub total_size: u64,
+    pub total_time_seconds: u64,
+    pub average_time_per_file_ms: f64,
+}
+
+/// Release a file check compares the install against.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateInfo {
+    pub installed_version: Option<u64>,
+    pub version: u64,
+    pub release_notes: String,
+    /// `current`, `delta`, `full` or `repair`.
+    pub mode: &'static str,
+}
+
+/// Files that could not be downloaded after all retries.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadReport {
+    pub total_files: usize,
+    pub failed_files: Vec<DownloadFailure>,
+}
+
+/// Progress of a hash file generation.
+#[derive(Debug, Clone, Serialize)]
+pub struct HashFileProgress {
+    pub current_file: String,
+    pub progress: f64,
+    pub processed_files: usize,
+    pub total_files: usize,
+    pub total_size: u64,
+}
+
+/// Receives the progress of the services.
+///
+/// The Tauri app forwards every call as the frontend event of the same name,
+/// the command line turns them into progress bars. All methods do nothing by
+/// default, so a front-end only implements what it shows.
+pub trait ProgressReporter: Send + Sync {
+    /// The release found by a file check, before the files are compared.
+    fn update_info(&self, _info: &UpdateInfo) {}
+
+    /// Called every 100 files of a file check or an archive extraction.
+    fn file_check_progress(&self, _progress: &FileCheckProgress) {}
+
+    fn file_check_completed(&self, _summary: &FileCheckSummary) {}
+
+    /// The disk checks before an update failed, nothing was downloaded.
+    fn update_preflight_failed(&self, _report: &PreflightReport) {}
+
+    /// Called every 100 ms while files are downloaded.
+    fn download_progress(&self, _progress: &ProgressPayload) {}
+
+    /// Some files failed to download, the update was not completed.
+    fn download_report(&self, _report: &DownloadReport) {}
+
+    fn download_cancelled(&self) {}
+
+    fn download_complete(&self) {}
+
+    fn hash_file_progress(&self, _progress: &HashFileProgress) {}
+
+    /// The game was started or has exited.
+    fn game_status_changed(&self, _running: bool) {}
+
+    /// How the game exited, or why it failed to start.
+    fn game_status(&self, _status: &str) {}
+
+    fn game_ended(&self) {}
+}
+
+/// Reporter ignoring all progress.
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {}
+
+/// Formats a byte count for humans, e.g. `1.50 MB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit_index = 0;
+
+    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit_index += 1;
+    }
+
+    format!("{:.2} {}", size, UNITS[unit_index])
+}
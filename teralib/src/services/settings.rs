@@ -0,0 +1,353 @@
+// Standard library imports
+use std::env;
+use std::path::{Path, PathBuf};
+
+// Third-party imports
+use ini::Ini;
+use log::info;
+
+use crate::config::{get_config_list, get_config_value};
+use crate::error::LauncherError;
+use crate::patch::download::DownloadSettings;
+use crate::patch::hashing::StorageKind;
+use crate::patch::ignore_rules::IgnoreRules;
+use crate::patch::manifest::MANIFEST_FILE_NAME;
+
+/// Name of the user settings file.
+pub const CONFIG_FILE_NAME: &str = "tera_config.ini";
+
+/// Looks for tera_config.ini in the working directory, its parent and next to
+/// the launcher, in that order.
+fn find_config_file() -> Option<PathBuf> {
+    let current_dir = env::current_dir().ok()?;
+    let config_in_current = current_dir.join(CONFIG_FILE_NAME);
+    if config_in_current.exists() {
+        return Some(config_in_current);
+    }
+
+    let parent_dir = current_dir.parent()?;
+    let config_in_parent = parent_dir.join(CONFIG_FILE_NAME);
+    if config_in_parent.exists() {
+        return Some(config_in_parent);
+    }
+
+    if let Ok(exe_path) = env::current_exe() {
+        if let Some(exe_dir) = exe_path.parent() {
+            let config_in_exe_dir = exe_dir.join(CONFIG_FILE_NAME);
+            if config_in_exe_dir.exists() {
+                return Some(config_in_exe_dir);
+            }
+        }
+    }
+
+    None
+}
+
+/// Reads and writes the user settings in tera_config.ini.
+///
+/// The file is read again on every call, so changes made by another front-end
+/// are picked up.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsService {
+    /// Settings file to use instead of looking for one.
+    config_path: Option<PathBuf>,
+}
+
+impl SettingsService {
+    pub fn new() -> Self {
+        SettingsService::default()
+    }
+
+    /// Uses the given settings file, which `write_install_config` creates if
+    /// it does not exist yet.
+    pub fn with_config_path(config_path: PathBuf) -> Self {
+        SettingsService { config_path: Some(config_path) }
+    }
+
+    /// Returns the settings file in use, if there is one.
+    pub fn config_path(&self) -> Option<PathBuf> {
+        match &self.config_path {
+            Some(config_path) => config_path.exists().then(|| config_path.clone()),
+            None => find_config_file(),
+        }
+    }
+
+    fn load(&self) -> Option<Ini> {
+        Ini::load_from_file(self.config_path()?).ok()
+    }
+
+    fn get(&self, section: &str, key: &str) -> Option<String> {
+        self.load()?.get_from(Some(section), key).map(str::to_string)
+    }
+
+    /// Changes the settings file and writes it back.
+    pub fn update<F: FnOnce(&mut Ini)>(&self, update: F) -> Result<(), LauncherError> {
+        let config_path = self.config_path().ok_or(LauncherError::ConfigNotFound)?;
+        let mut conf = Ini::load_from_file(&config_path)?;
+
+        update(&mut conf);
+
+        conf.write_to_file(&config_path)?;
+        Ok(())
+    }
+
+    /// Returns the game path and language.
+    pub fn load_config(&self) -> Result<(PathBuf, String), LauncherError> {
+        let config_path = self.config_path().ok_or(LauncherError::ConfigNotFound)?;
+        let conf = Ini::load_from_file(&config_path)?;
+
+        let missing = |what: &str| LauncherError::Config(format!("{} not found in config", what));
+        let section = conf.section(Some("game")).ok_or_else(|| missing("Game section"))?;
+
+        let game_path = section.get("path").ok_or_else(|| missing("Game path"))?;
+
+        let game_path = PathBuf::from(game_path);
+
+        let game_lang = section.get("lang").ok_or_else(|| missing("Game language"))?.to_string();
+
+        Ok((game_path, game_lang))
+    }
+
+    pub fn game_path(&self) -> Result<PathBuf, LauncherError> {
+        let (game_path, _) = self.load_config()?;
+        Ok(game_path)
+    }
+
+    pub fn save_game_path(&self, path: &str) -> Result<(), LauncherError> {
+        self.update(|conf| {
+            conf.with_section(Some("game")).set("path", path);
+        })
+    }
+
+    pub fn language(&self) -> Result<String, LauncherError> {
+        let (_, game_lang) = self.load_config()?;
+        Ok(game_lang)
+    }
+
+    pub fn save_language(&self, language: &str) -> Result<(), LauncherError> {
+        self.update(|conf| {
+            conf.with_section(Some("game")).set("lang", language);
+        })
+    }
+
+    /// Returns the release installed in the game folder, recorded after a
+    /// complete update. A release recorded for another folder does not count,
+    /// so pointing the settings at a new folder leads to a full check.
+    pub fn installed_version(&self) -> Option<u64> {
+        let conf = self.load()?;
+        let section = conf.section(Some("game"))?;
+        if section.get("installed_path")? != section.get("path")? {
+            return None;
+        }
+        section.get("installed_version")?.trim().parse().ok()
+    }
+
+    /// Records `version` as installed in the current game folder.
+    pub fn save_installed_version(&self, version: u64) -> Result<(), LauncherError> {
+        self.update(|conf| {
+            let game_path = conf.get_from(Some("game"), "path").unwrap_or_default().to_string();
+            conf.with_section(Some("game"))
+                .set("installed_version", version.to_string())
+                .set("installed_path", game_path);
+        })
+    }
+
+    /// Points the settings at a new install, creating the file next to the
+    /// launcher if there is none. The installed release is cleared until the
+    /// install is complete.
+    pub fn write_install_config(&self, game_path: &Path) -> Result<(), LauncherError> {
+        let config_path = match self.config_path.clone().or_else(find_config_file) {
+            Some(config_path) => config_path,
+            None => env::current_exe()?
+                .parent()
+                .ok_or_else(|| LauncherError::Config("Launcher folder not found".to_string()))?
+                .join(CONFIG_FILE_NAME),
+        };
+        let mut conf = if config_path.exists() {
+            Ini::load_from_file(&config_path)?
+        } else {
+            info!("Creating {:?}", config_path);
+            Ini::new()
+        };
+
+        let path = game_path.to_str().ok_or_else(|| LauncherError::Config("Invalid game path".to_string()))?;
+        conf.with_section(Some("game")).set("path", path);
+        if conf.get_from(Some("game"), "lang").is_none() {
+            conf.with_section(Some("game")).set("lang", "EUR");
+        }
+        conf.delete_from(Some("game"), "installed_version");
+        conf.delete_from(Some("game"), "installed_path");
+
+        conf.write_to_file(&config_path)?;
+        Ok(())
+    }
+
+    /// Returns the storage kind of the game drive, from the `[verify] storage`
+    /// setting (`auto`, `ssd` or `hdd`).
+    pub fn storage_kind(&self, game_path: &Path) -> StorageKind {
+        let setting = self.get("verify", "storage").unwrap_or_else(|| "auto".to_string());
+        StorageKind::from_setting(&setting, game_path)
+    }
+
+    /// Loads the ignore rules of the game folder, plus the rules file set as
+    /// `[verify] ignore_file` (relative to the config file).
+    pub fn ignore_rules(&self, game_path: &Path) -> Result<IgnoreRules, String> {
+        let config_rules_file = self.config_path().and_then(|config_path| {
+            let conf = Ini::load_from_file(&config_path).ok()?;
+            let rules_file = conf.get_from(Some("verify"), "ignore_file")?.trim();
+            if rules_file.is_empty() {
+                return None;
+            }
+            Some(config_path.parent()?.join(rules_file))
+        });
+        IgnoreRules::load(game_path, config_rules_file.as_deref())
+    }
+
+    /// Reads the `[download]` section, keeping the defaults for missing values.
+    pub fn download_settings(&self) -> DownloadSettings {
+        let mut settings = DownloadSettings::default();
+
+        let conf = match self.load() {
+            Some(conf) => conf,
+            None => return settings,
+        };
+
+        if let Some(section) = conf.section(Some("download")) {
+            if let Some(connections) = section.get("connections").and_then(|v| v.trim().parse::<usize>().ok()) {
+                settings.connections = connections.clamp(1, 32);
+            }
+            if let Some(retries) = section.get("retries").and_then(|v| v.trim().parse::<u32>().ok()) {
+                settings.retries = retries;
+            }
+            if let Some(max_speed) = section.get("max_speed_kbps").and_then(|v| v.trim().parse::<u64>().ok()) {
+                settings.max_speed_kbps = max_speed;
+            }
+            if let Some(staged) = section.get("staged").and_then(|v| v.trim().parse::<bool>().ok()) {
+                settings.staged = staged;
+            }
+        }
+
+        settings
+    }
+
+    pub fn save_speed_limit(&self, kbps: u64) -> Result<(), LauncherError> {
+        self.update(|conf| {
+            conf.with_section(Some("download")).set("max_speed_kbps", kbps.to_string());
+        })
+    }
+
+    /// Returns `server_url` from the `[download]` section, a local patch server
+    /// (`tera_launcher serve`) used instead of the configured file server.
+    fn server_url_override(&self) -> Option<String> {
+        let server_url = self.get("download", "server_url")?;
+        let server_url = server_url.trim().trim_end_matches('/');
+        (!server_url.is_empty()).then(|| server_url.to_string())
+    }
+
+    pub fn hash_file_url(&self) -> String {
+        match self.server_url_override() {
+            Some(server_url) => format!("{}/{}", server_url, MANIFEST_FILE_NAME),
+            None => get_config_value("HASH_FILE_URL"),
+        }
+    }
+
+    pub fn files_server_url(&self) -> String {
+        self.server_url_override().unwrap_or_else(|| get_config_value("FILE_SERVER_URL"))
+    }
+
+    /// Mirrors of the production file server, unused with a local patch server.
+    pub fn file_mirror_urls(&self) -> Vec<String> {
+        match self.server_url_override() {
+            Some(_) => Vec::new(),
+            None => get_config_list("FILE_MIRROR_URLS"),
+        }
+    }
+
+    /// Returns the launcher version that failed to start and was rolled back.
+    pub fn failed_launcher_version(&self) -> Option<String> {
+        self.get("launcher", "failed_version")
+    }
+
+    pub fn save_failed_launcher_version(&self, version: &str) -> Result<(), LauncherError> {
+        self.update(|conf| {
+            conf.with_section(Some("launcher")).set("failed_version", version);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn settings(name: &str, contents: &str) -> SettingsService {
+        let folder = std::env::temp_dir().join(format!("teralaunch-settings-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let config_path = folder.join(CONFIG_FILE_NAME);
+        if !contents.is_empty() {
+            fs::write(&config_path, contents).unwrap();
+        }
+        SettingsService::with_config_path(config_path)
+    }
+
+    #[test]
+    fn reads_and_updates_the_game_section() {
+        let settings = settings("game", "[game]\npath=C:/TERA\nlang=GER\ninstalled_version=41\ninstalled_path=C:/TERA\n");
+
+        assert_eq!(settings.load_config().unwrap(), (PathBuf::from("C:/TERA"), "GER".to_string()));
+        assert_eq!(settings.installed_version(), Some(41));
+
+        settings.save_language("FRA").unwrap();
+        settings.save_installed_version(42).unwrap();
+        assert_eq!(settings.language().unwrap(), "FRA");
+        assert_eq!(settings.installed_version(), Some(42));
+        assert_eq!(settings.game_path().unwrap(), PathBuf::from("C:/TERA"));
+    }
+
+    #[test]
+    fn installed_version_belongs_to_its_game_folder() {
+        let settings = settings("installed", "[game]\npath=D:/Empty\nlang=EUR\ninstalled_version=1\n");
+        assert_eq!(settings.installed_version(), None);
+
+        settings.save_installed_version(2).unwrap();
+        assert_eq!(settings.installed_version(), Some(2));
+
+        settings.save_game_path("E:/Other").unwrap();
+        assert_eq!(settings.installed_version(), None);
+
+        settings.save_game_path("D:/Empty").unwrap();
+        assert_eq!(settings.installed_version(), Some(2));
+    }
+
+    #[test]
+    fn missing_file_is_reported_as_such() {
+        let settings = settings("missing", "");
+
+        assert!(matches!(settings.load_config(), Err(LauncherError::ConfigNotFound)));
+        assert!(matches!(settings.save_language("EUR"), Err(LauncherError::ConfigNotFound)));
+        assert_eq!(settings.installed_version(), None);
+    }
+
+    #[test]
+    fn install_config_creates_the_file_and_clears_the_release() {
+        let settings = settings("install", "");
+        settings.write_install_config(Path::new("D:/Games/TERA")).unwrap();
+        settings.save_installed_version(7).unwrap();
+        settings.write_install_config(Path::new("D:/Games/TERA")).unwrap();
+
+        assert_eq!(settings.load_config().unwrap(), (PathBuf::from("D:/Games/TERA"), "EUR".to_string()));
+        assert_eq!(settings.installed_version(), None);
+    }
+
+    #[test]
+    fn download_settings_are_clamped_and_default() {
+        let settings = settings("download", "[download]\nconnections=100\nretries=x\nmax_speed_kbps=512\nstaged=false\n");
+        let download = settings.download_settings();
+
+        assert_eq!(download.connections, 32);
+        assert_eq!(download.retries, DownloadSettings::default().retries);
+        assert_eq!(download.max_speed_kbps, 512);
+        assert!(!download.staged);
+    }
+}